@@ -0,0 +1,177 @@
+//! Compares redrawing grids by scanning every cell sprite, as `draw_grid` used to, with composing
+//! each grid's cells and updating only the sprites that changed. Both run as systems over the same
+//! world: grids with one child sprite per visible cell and a piece falling above a half-filled
+//! stack, its shadow toggled on alternate redraws so each one has cells to change.
+//!
+//! ```text
+//! cargo bench --bench draw_grid
+//! ```
+#![deny(clippy::pedantic)]
+#![allow(clippy::needless_pass_by_value)]
+
+use bevy::ecs::system::RunSystemOnce;
+use bevy::prelude::*;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use twotris::tetris::components::{Coordinate, Grid, GridConfig, TetrominoType};
+use twotris::tetris::render::{compose, CellLook, GridSprites, Overlay};
+
+const FILLED_COLOR: Color = Color::srgb(0.8, 0.8, 0.8);
+const SHADOW_COLOR: Color = Color::srgb(0.3, 0.3, 0.3);
+
+/// The falling piece's shadow, and whether this redraw shows it.
+#[derive(Resource)]
+struct Shadow {
+    coords: Vec<(usize, usize)>,
+    shown: bool,
+}
+
+impl Shadow {
+    fn coords(&self) -> &[(usize, usize)] {
+        if self.shown {
+            &self.coords
+        } else {
+            &[]
+        }
+    }
+}
+
+/// A half-filled stack with a piece falling above it, and that piece's shadow.
+fn stack(config: GridConfig) -> (Grid, Vec<(usize, usize)>) {
+    let mut grid = Grid::new(config);
+    let bottom = grid.total_height() - 1;
+    for y in bottom - grid.height() / 2..=bottom {
+        for x in (0..grid.width()).filter(|x| (x + y) % 7 != 0) {
+            grid.set(x, y, true);
+        }
+    }
+    let mut piece = grid.spawn_tetromino_type(TetrominoType::T).unwrap();
+    grid.unset_tetromino(&piece);
+    piece.top_left.1 += 2;
+    grid.set_tetromino(&piece);
+    let shadow = grid
+        .controlled_tetromino_shadow(&piece)
+        .coords(&grid)
+        .collect();
+    (grid, shadow)
+}
+
+/// `grids` copies of the stack, each with its cell sprites as children in `compose` order.
+fn spawn_grids(config: GridConfig, grids: usize) -> World {
+    let (grid, shadow) = stack(config);
+    let mut world = World::new();
+    for _ in 0..grids {
+        let mut sprites = vec![];
+        let parent = world.spawn_empty().id();
+        for y in grid.buffer_height()..grid.total_height() {
+            for x in 0..grid.width() {
+                let square = world
+                    .spawn((Sprite::default(), Visibility::Hidden, Coordinate(x, y)))
+                    .set_parent(parent)
+                    .id();
+                sprites.push(square);
+            }
+        }
+        world
+            .entity_mut(parent)
+            .insert((grid.clone(), GridSprites::new(sprites)));
+    }
+    world.insert_resource(Shadow {
+        coords: shadow,
+        shown: false,
+    });
+    world
+}
+
+fn paint(visibility: &mut Visibility, sprite: &mut Sprite, look: CellLook) {
+    let color = match look {
+        CellLook::Empty => {
+            *visibility = Visibility::Hidden;
+            return;
+        }
+        CellLook::Shadow => SHADOW_COLOR,
+        _ => FILLED_COLOR,
+    };
+    *visibility = Visibility::Visible;
+    sprite.color = color;
+}
+
+fn toggle_shadow(mut shadow: ResMut<Shadow>) {
+    shadow.shown = !shadow.shown;
+}
+
+/// The old `draw_grid`: every sprite in the world is checked against each grid's filled cells.
+fn full_scan(
+    shadow: Res<Shadow>,
+    grids: Query<(Entity, &Grid)>,
+    mut squares: Query<(&mut Visibility, &mut Sprite, &Coordinate, &Parent)>,
+) {
+    for (entity, grid) in &grids {
+        let set_coords: Vec<_> = grid
+            .set_coords_iter()
+            .filter(|&(_, y)| grid.is_visible_row(y))
+            .collect();
+        for (mut visibility, mut sprite, coord, parent) in &mut squares {
+            if parent.get() != entity {
+                continue;
+            }
+            let look = if set_coords.contains(&coord.tuple()) {
+                CellLook::Filled { focused: true }
+            } else if shadow.coords().contains(&coord.tuple()) {
+                CellLook::Shadow
+            } else {
+                CellLook::Empty
+            };
+            paint(&mut visibility, &mut sprite, look);
+        }
+    }
+}
+
+/// The current `draw_grid`: each grid's cells are composed and only changed sprites are touched.
+fn incremental(
+    shadow: Res<Shadow>,
+    mut grids: Query<(&Grid, &mut GridSprites)>,
+    mut squares: Query<(&mut Visibility, &mut Sprite), With<Coordinate>>,
+) {
+    for (grid, mut grid_sprites) in &mut grids {
+        let overlay = Overlay {
+            shadow: shadow.coords(),
+            ..Overlay::default()
+        };
+        let looks = compose(grid, true, overlay);
+        for (square, look) in grid_sprites.update(&looks) {
+            if let Ok((mut visibility, mut sprite)) = squares.get_mut(square) {
+                paint(&mut visibility, &mut sprite, look);
+            }
+        }
+    }
+}
+
+fn draw_grid(c: &mut Criterion) {
+    let mut group = c.benchmark_group("draw_grid");
+    for (width, height) in [(10, 20), (20, 40), (40, 80)] {
+        for grids in [2, 4, 8] {
+            let input = format!("{width}x{height}/{grids}");
+            let config = GridConfig::new(width, height);
+
+            let mut world = spawn_grids(config, grids);
+            group.bench_function(BenchmarkId::new("full_scan", &input), |b| {
+                b.iter(|| {
+                    world.run_system_once(toggle_shadow);
+                    world.run_system_once(full_scan);
+                });
+            });
+
+            let mut world = spawn_grids(config, grids);
+            group.bench_function(BenchmarkId::new("incremental", &input), |b| {
+                b.iter(|| {
+                    world.run_system_once(toggle_shadow);
+                    world.run_system_once(incremental);
+                });
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, draw_grid);
+criterion_main!(benches);
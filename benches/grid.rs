@@ -0,0 +1,79 @@
+//! Collision, line and shadow checks on `Grid`, and the bot search built on them.
+//!
+//! ```text
+//! cargo bench --bench grid
+//! ```
+#![deny(clippy::pedantic)]
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use twotris::tetris::bot::{best_placement, reachable_placements, Weights};
+use twotris::tetris::components::{ControlledTetromino, Grid, GridConfig, TetrominoType};
+
+/// A ragged mid-game stack with a few holes and a well on the right.
+const STACK: &str = "
+..........
+X.........
+XX...X....
+XXX.XXX...
+XXXXXXX.X.
+XXXXX.XXX.
+XX.XXXXXX.
+XXXXXXXXX.";
+
+fn stack() -> Grid {
+    Grid::with_visible_rows(GridConfig::default(), STACK).unwrap()
+}
+
+fn collisions(c: &mut Criterion) {
+    let grid = stack();
+    let piece = ControlledTetromino::new_with_tetromino_type(TetrominoType::T, &grid);
+    let landed = grid.controlled_tetromino_shadow(&piece);
+
+    c.bench_function("is_tetromino_space_open", |b| {
+        b.iter(|| black_box(&grid).is_tetromino_space_open(black_box(&piece)));
+    });
+    c.bench_function("is_tetromino_at_bottom", |b| {
+        b.iter(|| black_box(&grid).is_tetromino_at_bottom(black_box(&landed)));
+    });
+    c.bench_function("is_tetromino_blocked_left", |b| {
+        b.iter(|| black_box(&grid).is_tetromino_blocked_left(black_box(&landed)));
+    });
+    c.bench_function("controlled_tetromino_shadow", |b| {
+        b.iter(|| black_box(&grid).controlled_tetromino_shadow(black_box(&piece)));
+    });
+}
+
+fn lines(c: &mut Criterion) {
+    let mut grid = stack();
+    let bottom = grid.total_height() - 1;
+    for y in bottom - 3..=bottom {
+        for x in 0..grid.width() {
+            grid.set(x, y, true);
+        }
+    }
+    c.bench_function("full_rows", |b| b.iter(|| black_box(&grid).full_rows()));
+    c.bench_function("clear_full_grid_rows", |b| {
+        b.iter_batched(
+            || grid.clone(),
+            |mut grid| grid.clear_full_grid_rows(),
+            criterion::BatchSize::SmallInput,
+        );
+    });
+}
+
+fn bot_search(c: &mut Criterion) {
+    let grid = stack();
+    let current = ControlledTetromino::new_with_tetromino_type(TetrominoType::L, &grid);
+    let next = ControlledTetromino::new_with_tetromino_type(TetrominoType::I, &grid);
+    let weights = Weights::default();
+
+    c.bench_function("reachable_placements", |b| {
+        b.iter(|| reachable_placements(black_box(&grid), black_box(&current)));
+    });
+    c.bench_function("best_placement with lookahead", |b| {
+        b.iter(|| best_placement(black_box(&grid), &current, Some(&next), &weights));
+    });
+}
+
+criterion_group!(benches, collisions, lines, bot_search);
+criterion_main!(benches);
@@ -0,0 +1,181 @@
+//! Runs headless two-grid games between bot policies and reports the results.
+//!
+//! ```text
+//! twotris-sim [--games N] [--seed S] [--max-pieces N] [--policies LEFT,RIGHT]
+//!             [--format json|csv] [--output PATH]
+//! ```
+#![deny(clippy::pedantic)]
+#![allow(clippy::cast_precision_loss)]
+
+use std::fmt::Write as _;
+use std::io::Write as _;
+use std::process::ExitCode;
+use twotris::tetris::components::GridConfig;
+use twotris::tetris::sim::{policy_from_name, GameResult, Simulation};
+
+const USAGE: &str = "usage: twotris-sim [--games N] [--seed S] [--max-pieces N] \
+[--policies LEFT,RIGHT] [--format json|csv] [--output PATH]
+policies: heuristic, lookahead, random";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Json,
+    Csv,
+}
+
+#[derive(Debug)]
+struct Args {
+    games: u64,
+    seed: u64,
+    max_pieces: u32,
+    policies: Vec<String>,
+    format: Format,
+    output: Option<String>,
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        Self {
+            games: 1000,
+            seed: 0,
+            max_pieces: 500,
+            policies: vec!["heuristic".to_string(), "heuristic".to_string()],
+            format: Format::Json,
+            output: None,
+        }
+    }
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut parsed = Args::default();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("missing value for {arg}"))
+        };
+        match arg.as_str() {
+            "--games" => parsed.games = value()?.parse().map_err(|e| format!("--games: {e}"))?,
+            "--seed" => parsed.seed = value()?.parse().map_err(|e| format!("--seed: {e}"))?,
+            "--max-pieces" => {
+                parsed.max_pieces = value()?.parse().map_err(|e| format!("--max-pieces: {e}"))?;
+            }
+            "--policies" => {
+                parsed.policies = value()?.split(',').map(str::to_string).collect();
+            }
+            "--format" => {
+                parsed.format = match value()?.as_str() {
+                    "json" => Format::Json,
+                    "csv" => Format::Csv,
+                    other => return Err(format!("unknown format {other}")),
+                }
+            }
+            "--output" => parsed.output = Some(value()?),
+            "--help" | "-h" => return Err(USAGE.to_string()),
+            other => return Err(format!("unknown argument {other}\n{USAGE}")),
+        }
+    }
+    if parsed.policies.len() != 2 {
+        return Err("--policies needs one policy per grid, e.g. heuristic,random".to_string());
+    }
+    Ok(parsed)
+}
+
+fn to_csv(results: &[GameResult]) -> String {
+    let mut csv = String::from("seed,grid,policy,score,lines,pieces,topped_out\n");
+    for game in results {
+        for (i, grid) in game.grids.iter().enumerate() {
+            let _ = writeln!(
+                csv,
+                "{},{},{},{},{},{},{}",
+                game.seed, i, grid.policy, grid.score, grid.lines, grid.pieces, grid.topped_out
+            );
+        }
+    }
+    csv
+}
+
+/// Mean, minimum, median and 90th percentile of `values`.
+fn distribution(mut values: Vec<u32>) -> String {
+    if values.is_empty() {
+        return "n/a".to_string();
+    }
+    values.sort_unstable();
+    let mean = values.iter().map(|&v| f64::from(v)).sum::<f64>() / values.len() as f64;
+    let percentile = |p: usize| values[(values.len() - 1) * p / 100];
+    format!(
+        "mean {mean:.1}, min {}, median {}, p90 {}, max {}",
+        values[0],
+        percentile(50),
+        percentile(90),
+        values[values.len() - 1]
+    )
+}
+
+fn summary(results: &[GameResult], grids: usize) -> String {
+    let mut summary = format!("{} games\n", results.len());
+    for i in 0..grids {
+        let column = |f: fn(&twotris::tetris::sim::GridResult) -> u32| {
+            results.iter().map(|game| f(&game.grids[i])).collect()
+        };
+        let topped_out = results
+            .iter()
+            .filter(|game| game.grids[i].topped_out)
+            .count();
+        let _ = write!(
+            summary,
+            "grid {i} ({})\n  score:  {}\n  lines:  {}\n  pieces: {}\n  topped out: {topped_out}\n",
+            results.first().map_or("", |game| &game.grids[i].policy),
+            distribution(column(|grid| grid.score)),
+            distribution(column(|grid| grid.lines)),
+            distribution(column(|grid| grid.pieces)),
+        );
+    }
+    let _ = writeln!(
+        summary,
+        "combined score: {}",
+        distribution(results.iter().map(GameResult::score).collect())
+    );
+    summary
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("{message}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let configs = [GridConfig::default(), GridConfig::default()];
+    let mut results = Vec::new();
+    for game in 0..args.games {
+        let seed = args.seed.wrapping_add(game);
+        let policies: Option<Vec<_>> = args
+            .policies
+            .iter()
+            .map(|name| policy_from_name(name, seed))
+            .collect();
+        let Some(mut policies) = policies else {
+            eprintln!("unknown policy in {:?}\n{USAGE}", args.policies);
+            return ExitCode::FAILURE;
+        };
+        results.push(Simulation::new(seed, &configs).run(&mut policies, args.max_pieces));
+    }
+
+    let report = match args.format {
+        Format::Json => serde_json::to_string_pretty(&results).expect("results serialize"),
+        Format::Csv => to_csv(&results),
+    };
+    let written = match &args.output {
+        Some(path) => std::fs::write(path, report),
+        None => writeln!(std::io::stdout(), "{report}"),
+    };
+    if let Err(e) = written {
+        eprintln!("failed to write results: {e}");
+        return ExitCode::FAILURE;
+    }
+
+    eprint!("{}", summary(&results, configs.len()));
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,211 @@
+//! Evolves the bot's evaluation weights with a genetic algorithm. Every candidate plays the same
+//! seeded headless games, so a run is reproducible for a given `--seed`. Each generation's winner
+//! is then scored on a fixed set of held-out games, and the best of those scores is written out.
+//!
+//! ```text
+//! twotris-tune [--population N] [--generations N] [--games N] [--max-pieces N] [--seed S]
+//!              [--output PATH]
+//! ```
+#![deny(clippy::pedantic)]
+#![allow(clippy::cast_precision_loss)]
+
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use rayon::prelude::*;
+use std::process::ExitCode;
+use twotris::tetris::bot::{Weights, WEIGHTS_PATH};
+use twotris::tetris::components::GridConfig;
+use twotris::tetris::sim::{HeuristicPolicy, Policy, Simulation};
+
+const USAGE: &str = "usage: twotris-tune [--population N] [--generations N] [--games N] \
+[--max-pieces N] [--seed S] [--output PATH]";
+
+/// Percentage of each generation carried over unchanged.
+const ELITE_PERCENT: usize = 30;
+/// Percentage of the population sampled for each tournament selection.
+const TOURNAMENT_PERCENT: usize = 10;
+const MUTATION_RATE: f64 = 0.05;
+const MUTATION_STEP: f32 = 0.2;
+
+#[derive(Debug)]
+struct Args {
+    population: usize,
+    generations: u64,
+    games: u64,
+    max_pieces: u32,
+    seed: u64,
+    output: String,
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        Self {
+            population: 32,
+            generations: 20,
+            games: 8,
+            max_pieces: 300,
+            seed: 0,
+            output: WEIGHTS_PATH.to_string(),
+        }
+    }
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut parsed = Args::default();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("missing value for {arg}"))
+        };
+        let number = |v: String| v.parse::<u64>().map_err(|e| format!("{arg}: {e}"));
+        match arg.as_str() {
+            "--population" => parsed.population = number(value()?)?.try_into().unwrap_or(0),
+            "--generations" => parsed.generations = number(value()?)?,
+            "--games" => parsed.games = number(value()?)?,
+            "--max-pieces" => parsed.max_pieces = number(value()?)?.try_into().unwrap_or(0),
+            "--seed" => parsed.seed = number(value()?)?,
+            "--output" => parsed.output = value()?,
+            "--help" | "-h" => return Err(USAGE.to_string()),
+            other => return Err(format!("unknown argument {other}\n{USAGE}")),
+        }
+    }
+    if parsed.population < 2 || parsed.games == 0 {
+        return Err("--population must be at least 2 and --games at least 1".to_string());
+    }
+    Ok(parsed)
+}
+
+/// Scales `v` to unit length; the bot only compares scores, so only direction matters.
+fn normalize(mut v: [f32; 5]) -> [f32; 5] {
+    let length = v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if length > f32::EPSILON {
+        for x in &mut v {
+            *x /= length;
+        }
+    }
+    v
+}
+
+/// Seeds for `round` of `games` games. Rounds below the generation count train the population;
+/// the round after them is held out to compare generation winners on equal terms.
+fn round_seeds(args: &Args, round: u64) -> Vec<u64> {
+    (0..args.games)
+        .map(|game| {
+            args.seed
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(round * args.games + game)
+        })
+        .collect()
+}
+
+/// Average lines cleared across both grids over the given seeded games.
+fn fitness(weights: Weights, seeds: &[u64], max_pieces: u32) -> f32 {
+    let configs = [GridConfig::default(), GridConfig::default()];
+    let total: u64 = seeds
+        .par_iter()
+        .map(|&seed| {
+            let mut policies: Vec<Box<dyn Policy>> = (0..configs.len())
+                .map(|_| Box::new(HeuristicPolicy::new("tune", weights, false)) as Box<dyn Policy>)
+                .collect();
+            u64::from(
+                Simulation::new(seed, &configs)
+                    .run(&mut policies, max_pieces)
+                    .lines(),
+            )
+        })
+        .sum();
+    total as f32 / seeds.len() as f32
+}
+
+/// Picks the fittest of a random sample of `ranked`, which is sorted best first.
+fn tournament<'a>(rng: &mut ChaCha8Rng, ranked: &'a [([f32; 5], f32)]) -> &'a ([f32; 5], f32) {
+    let size = (ranked.len() * TOURNAMENT_PERCENT / 100).max(2);
+    let mut indices: Vec<usize> = (0..ranked.len()).collect();
+    indices.shuffle(rng);
+    let best = indices[..size.min(ranked.len())]
+        .iter()
+        .min()
+        .expect("tournament is never empty");
+    &ranked[*best]
+}
+
+/// Fitness-weighted average of two parents, occasionally nudged along one axis.
+fn offspring(rng: &mut ChaCha8Rng, a: &([f32; 5], f32), b: &([f32; 5], f32)) -> [f32; 5] {
+    let total = (a.1 + b.1).max(f32::EPSILON);
+    let (wa, wb) = (a.1 / total, b.1 / total);
+    let mut child = [0.0; 5];
+    for (i, c) in child.iter_mut().enumerate() {
+        *c = a.0[i] * wa + b.0[i] * wb;
+    }
+    if rng.gen_bool(MUTATION_RATE) {
+        let axis = rng.gen_range(0..child.len());
+        child[axis] += rng.gen_range(-MUTATION_STEP..MUTATION_STEP);
+    }
+    normalize(child)
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("{message}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut rng = ChaCha8Rng::seed_from_u64(args.seed);
+    let mut population: Vec<[f32; 5]> = vec![normalize(Weights::default().into())];
+    while population.len() < args.population {
+        population.push(normalize(std::array::from_fn(|_| rng.gen_range(-1.0..1.0))));
+    }
+
+    let held_out = round_seeds(&args, args.generations);
+    let mut best: Option<([f32; 5], f32)> = None;
+    for generation in 0..args.generations {
+        let seeds = round_seeds(&args, generation);
+        let fitnesses: Vec<f32> = population
+            .par_iter()
+            .map(|&candidate| fitness(candidate.into(), &seeds, args.max_pieces))
+            .collect();
+
+        let mut ranked: Vec<_> = population.iter().copied().zip(fitnesses).collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+        let mean = ranked.iter().map(|(_, f)| f).sum::<f32>() / ranked.len() as f32;
+        eprintln!(
+            "generation {generation}: best {:.1} lines, mean {mean:.1} lines, {:?}",
+            ranked[0].1,
+            Weights::from(ranked[0].0)
+        );
+        // Each generation played different games, so winners are only compared on the held-out
+        // ones.
+        let winner = ranked[0].0;
+        let held_out_fitness = fitness(winner.into(), &held_out, args.max_pieces);
+        if best.as_ref().is_none_or(|(_, f)| held_out_fitness > *f) {
+            best = Some((winner, held_out_fitness));
+        }
+
+        let elite = (ranked.len() * ELITE_PERCENT / 100).max(1);
+        population = ranked[..elite].iter().map(|(w, _)| *w).collect();
+        while population.len() < args.population {
+            let a = tournament(&mut rng, &ranked);
+            let b = tournament(&mut rng, &ranked);
+            population.push(offspring(&mut rng, a, b));
+        }
+    }
+
+    let Some((weights, fitness)) = best else {
+        eprintln!("no generations were run");
+        return ExitCode::FAILURE;
+    };
+    let weights = Weights::from(weights);
+    if let Err(e) = weights.save(&args.output) {
+        eprintln!("failed to write {}: {e}", args.output);
+        return ExitCode::FAILURE;
+    }
+    eprintln!(
+        "best {fitness:.1} held-out lines with {weights:?}, written to {}",
+        args.output
+    );
+    ExitCode::SUCCESS
+}
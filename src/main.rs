@@ -1,10 +1,5 @@
 #![deny(clippy::pedantic)]
-#![allow(clippy::module_name_repetitions)]
-#![allow(clippy::needless_pass_by_value)]
-#![allow(clippy::cast_precision_loss)]
-#![allow(clippy::too_many_arguments)]
 
-use crate::tetris::components;
 #[cfg(not(target_arch = "wasm32"))]
 use bevy::diagnostic;
 #[cfg(not(target_arch = "wasm32"))]
@@ -14,13 +9,20 @@ use bevy::prelude::*;
 use bevy_inspector_egui::quick::WorldInspectorPlugin;
 #[cfg(not(target_arch = "wasm32"))]
 use iyes_perf_ui::PerfUiPlugin;
-
-mod tetris;
+use twotris::tetris;
+use twotris::tetris::components;
 
 fn main() {
     let mut app = App::new();
-    app.add_plugins(DefaultPlugins)
-        .add_plugins(tetris::TetrisPlugin);
+    app.add_plugins(DefaultPlugins.set(WindowPlugin {
+        primary_window: Some(Window {
+            // Follow the browser window's size on the web, so the layout can fit it.
+            fit_canvas_to_parent: true,
+            ..default()
+        }),
+        ..default()
+    }))
+    .add_plugins(tetris::TetrisPlugin);
 
     #[cfg(not(target_arch = "wasm32"))]
     {
@@ -0,0 +1,475 @@
+use crate::tetris::bot::Bot;
+use crate::tetris::components::RowClearedEvent;
+use crate::tetris::mode::Level;
+use crate::tetris::plugin::{PauseState, TetrisState};
+use crate::tetris::save::{read_ron, write_ron};
+use bevy::audio::{PitchBundle, Volume};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::time::Duration;
+
+/// File name of the audio settings inside the platform data directory.
+pub const AUDIO_FILE: &str = "audio.ron";
+
+/// The localStorage key the audio settings are saved under on the web.
+pub const AUDIO_STORAGE_KEY: &str = "twotris.audio";
+
+/// How much a volume slider moves per key press, in percent.
+pub const VOLUME_STEP: u32 = 10;
+/// Characters in a volume slider's bar.
+const SLIDER_WIDTH: u32 = 10;
+/// Frequency of the note sounds and music are written relative to, A4.
+const ROOT_FREQUENCY: f32 = 440.0;
+/// Semitones the music sits below the sound effects, so they stand out over it.
+const MUSIC_TRANSPOSE: i32 = -12;
+/// Loudness of a volume slider at 100%. Sine waves at full volume are harsh and clip once a few
+/// of them overlap.
+const MAX_GAIN: f32 = 0.25;
+/// Fraction of its beats each note of the music sounds for, leaving a gap before the next.
+const MUSIC_LEGATO: f32 = 0.9;
+
+/// The melody the music loops, a traditional Russian folk tune. Each note is semitones from A4, or
+/// `None` for a rest, and how many beats it lasts.
+const MELODY: [(Option<i32>, f32); 40] = [
+    (Some(7), 1.0),
+    (Some(2), 0.5),
+    (Some(3), 0.5),
+    (Some(5), 1.0),
+    (Some(3), 0.5),
+    (Some(2), 0.5),
+    (Some(0), 1.0),
+    (Some(0), 0.5),
+    (Some(3), 0.5),
+    (Some(7), 1.0),
+    (Some(5), 0.5),
+    (Some(3), 0.5),
+    (Some(2), 1.5),
+    (Some(3), 0.5),
+    (Some(5), 1.0),
+    (Some(7), 1.0),
+    (Some(3), 1.0),
+    (Some(0), 1.0),
+    (Some(0), 1.0),
+    (None, 1.0),
+    (None, 0.5),
+    (Some(5), 1.0),
+    (Some(8), 0.5),
+    (Some(12), 1.0),
+    (Some(10), 0.5),
+    (Some(8), 0.5),
+    (Some(7), 1.5),
+    (Some(3), 0.5),
+    (Some(7), 1.0),
+    (Some(5), 0.5),
+    (Some(3), 0.5),
+    (Some(2), 1.0),
+    (Some(2), 0.5),
+    (Some(3), 0.5),
+    (Some(5), 1.0),
+    (Some(7), 1.0),
+    (Some(3), 1.0),
+    (Some(0), 1.0),
+    (Some(0), 1.0),
+    (None, 1.0),
+];
+
+/// Something happened that makes a sound.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sound {
+    Move,
+    Rotate,
+    /// A piece locked in place, whether it was dropped or fell.
+    Lock,
+    /// This many rows were cleared at once.
+    LineClear(u32),
+    FocusSwap,
+    LevelUp,
+    GameOver,
+}
+
+#[derive(Debug, Clone, Copy, Event)]
+pub struct SoundEvent(pub Sound);
+
+/// A tone to play.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Note {
+    pub frequency: f32,
+    /// Seconds after the sound starts that this note does.
+    pub delay: f32,
+    /// Seconds the note lasts.
+    pub length: f32,
+}
+
+/// The frequency `semitones` above A4, or below it if negative.
+#[must_use]
+pub fn note_frequency(semitones: i32) -> f32 {
+    ROOT_FREQUENCY * 2f32.powf(semitones as f32 / 12.0)
+}
+
+/// `semitones` played one after another, `spacing` seconds apart.
+fn arpeggio(semitones: &[i32], spacing: f32, length: f32) -> Vec<Note> {
+    semitones
+        .iter()
+        .enumerate()
+        .map(|(i, semitones)| Note {
+            frequency: note_frequency(*semitones),
+            delay: i as f32 * spacing,
+            length,
+        })
+        .collect()
+}
+
+impl Sound {
+    #[must_use]
+    pub fn notes(self) -> Vec<Note> {
+        match self {
+            Sound::Move => arpeggio(&[12], 0.0, 0.03),
+            Sound::Rotate => arpeggio(&[17], 0.0, 0.04),
+            Sound::Lock => arpeggio(&[-19], 0.0, 0.06),
+            // Each extra row climbs one note higher up a major chord, and a tetris ends on a
+            // long high note.
+            Sound::LineClear(rows) => {
+                let chord = [3, 7, 10, 15, 19];
+                let count = (rows as usize).clamp(1, 4);
+                let mut notes = arpeggio(&chord[..=count], 0.06, 0.1);
+                if count == 4 {
+                    if let Some(last) = notes.last_mut() {
+                        last.length = 0.4;
+                    }
+                }
+                notes
+            }
+            Sound::FocusSwap => arpeggio(&[10, 15], 0.04, 0.05),
+            Sound::LevelUp => arpeggio(&[3, 7, 10, 15, 10, 15], 0.08, 0.1),
+            Sound::GameOver => arpeggio(&[3, -2, -5, -9], 0.2, 0.25),
+        }
+    }
+}
+
+/// Beats per minute of the music at `level`, which quickens at high levels.
+#[must_use]
+pub fn music_tempo(level: u32) -> f32 {
+    match level {
+        0..=9 => 120.0,
+        10..=14 => 144.0,
+        _ => 168.0,
+    }
+}
+
+/// Where the music is in its melody.
+#[derive(Debug, Clone, Default, Resource)]
+pub struct Music {
+    next: usize,
+    /// Seconds until the next note starts.
+    wait: f32,
+}
+
+impl Music {
+    /// Moves the music on by `delta` seconds at `tempo`, returning the notes that start.
+    pub fn advance(&mut self, delta: f32, tempo: f32) -> Vec<Note> {
+        let beat = 60.0 / tempo;
+        let mut notes = vec![];
+        self.wait -= delta;
+        while self.wait <= 0.0 {
+            let (semitones, beats) = MELODY[self.next];
+            if let Some(semitones) = semitones {
+                notes.push(Note {
+                    frequency: note_frequency(semitones + MUSIC_TRANSPOSE),
+                    delay: 0.0,
+                    length: beats * beat * MUSIC_LEGATO,
+                });
+            }
+            self.wait += beats * beat;
+            self.next = (self.next + 1) % MELODY.len();
+        }
+        notes
+    }
+
+    /// Starts the melody over.
+    pub fn restart(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// Which volume the pause screen's sliders are adjusting.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Resource)]
+pub enum VolumeSlider {
+    #[default]
+    Music,
+    Sounds,
+}
+
+impl VolumeSlider {
+    #[must_use]
+    pub fn other(self) -> Self {
+        match self {
+            VolumeSlider::Music => VolumeSlider::Sounds,
+            VolumeSlider::Sounds => VolumeSlider::Music,
+        }
+    }
+}
+
+/// Music and sound effect volumes, in percent.
+#[derive(Debug, Clone, PartialEq, Resource, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AudioSettings {
+    pub music_volume: u32,
+    pub sound_volume: u32,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        Self {
+            music_volume: 50,
+            sound_volume: 80,
+        }
+    }
+}
+
+impl AudioSettings {
+    #[must_use]
+    pub fn music_gain(&self) -> f32 {
+        self.music_volume as f32 / 100.0 * MAX_GAIN
+    }
+
+    #[must_use]
+    pub fn sound_gain(&self) -> f32 {
+        self.sound_volume as f32 / 100.0 * MAX_GAIN
+    }
+
+    pub fn volume_mut(&mut self, slider: VolumeSlider) -> &mut u32 {
+        match slider {
+            VolumeSlider::Music => &mut self.music_volume,
+            VolumeSlider::Sounds => &mut self.sound_volume,
+        }
+    }
+
+    /// Turns `slider` up a step, or down if not `up`.
+    pub fn step(&mut self, slider: VolumeSlider, up: bool) {
+        let volume = self.volume_mut(slider);
+        *volume = if up {
+            (*volume + VOLUME_STEP).min(100)
+        } else {
+            volume.saturating_sub(VOLUME_STEP)
+        };
+    }
+
+    /// Both sliders drawn as text, with `selected` marked.
+    #[must_use]
+    pub fn sliders(&self, selected: VolumeSlider) -> String {
+        [
+            (VolumeSlider::Music, "Music", self.music_volume),
+            (VolumeSlider::Sounds, "Sounds", self.sound_volume),
+        ]
+        .iter()
+        .map(|(slider, name, volume)| {
+            let filled = volume * SLIDER_WIDTH / 100;
+            format!(
+                "{} {:<6} [{}{}] {}%",
+                if *slider == selected { '>' } else { ' ' },
+                name,
+                "#".repeat(filled as usize),
+                "-".repeat((SLIDER_WIDTH - filled) as usize),
+                volume
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+    }
+
+    /// Reads the audio settings saved on this platform, the defaults if there are none yet.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the settings can't be read or aren't valid RON.
+    pub fn read() -> io::Result<Self> {
+        Ok(read_ron(AUDIO_FILE, AUDIO_STORAGE_KEY)?.unwrap_or_default())
+    }
+
+    /// Saves the audio settings for this platform.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the settings can't be written.
+    pub fn write(&self) -> io::Result<()> {
+        write_ron(AUDIO_FILE, AUDIO_STORAGE_KEY, self)
+    }
+}
+
+/// A note waiting to play or playing, despawned once it's over. Notes clean up after themselves
+/// like this rather than with `PlaybackSettings::DESPAWN` so they still go away when there's no
+/// audio device to play them on.
+#[derive(Component, Debug, Clone)]
+pub struct ScheduledNote {
+    pub note: Note,
+    pub volume: f32,
+    elapsed: f32,
+    started: bool,
+}
+
+impl ScheduledNote {
+    #[must_use]
+    pub fn new(note: Note, volume: f32) -> Self {
+        Self {
+            note,
+            volume,
+            elapsed: 0.0,
+            started: false,
+        }
+    }
+
+    /// Moves on by `delta` seconds, returning whether the note should start playing now.
+    pub fn tick(&mut self, delta: f32) -> bool {
+        self.elapsed += delta;
+        let start = !self.started && self.elapsed >= self.note.delay;
+        self.started |= start;
+        start
+    }
+
+    #[must_use]
+    pub fn is_over(&self) -> bool {
+        self.elapsed >= self.note.delay + self.note.length
+    }
+}
+
+/// Sound effects and music, and the settings for their volume.
+pub struct AudioPlugin;
+
+impl Plugin for AudioPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<AudioSettings>()
+            .init_resource::<VolumeSlider>()
+            .init_resource::<Music>()
+            .add_event::<SoundEvent>()
+            .add_systems(Startup, load_audio_settings)
+            .add_systems(Update, (play_sounds, play_notes).chain())
+            .add_systems(OnEnter(TetrisState::InGame), restart_music)
+            .add_systems(
+                Update,
+                play_music
+                    .run_if(in_state(TetrisState::InGame).and_then(in_state(PauseState::Running))),
+            );
+    }
+}
+
+fn load_audio_settings(mut settings: ResMut<AudioSettings>) {
+    match AudioSettings::read() {
+        Ok(loaded) => *settings = loaded,
+        Err(e) => warn!("Failed to load audio settings: {}", e),
+    }
+}
+
+fn play_sounds(
+    mut commands: Commands,
+    settings: Res<AudioSettings>,
+    mut sounds: EventReader<SoundEvent>,
+    mut rows_cleared: EventReader<RowClearedEvent>,
+    bots: Query<(), With<Bot>>,
+) {
+    let clears = rows_cleared
+        .read()
+        .filter(|RowClearedEvent(grid, rows)| *rows > 0 && !bots.contains(*grid))
+        .map(|RowClearedEvent(_, rows)| Sound::LineClear(*rows));
+    let sounds: Vec<Sound> = sounds
+        .read()
+        .map(|SoundEvent(sound)| *sound)
+        .chain(clears)
+        .collect();
+    if settings.sound_volume == 0 {
+        return;
+    }
+    for note in sounds.into_iter().flat_map(Sound::notes) {
+        commands.spawn(ScheduledNote::new(note, settings.sound_gain()));
+    }
+}
+
+fn restart_music(mut music: ResMut<Music>) {
+    music.restart();
+}
+
+fn play_music(
+    mut commands: Commands,
+    time: Res<Time>,
+    level: Res<Level>,
+    settings: Res<AudioSettings>,
+    mut music: ResMut<Music>,
+) {
+    let notes = music.advance(time.delta_seconds(), music_tempo(level.0));
+    if settings.music_volume == 0 {
+        return;
+    }
+    for note in notes {
+        commands.spawn(ScheduledNote::new(note, settings.music_gain()));
+    }
+}
+
+/// Starts scheduled notes once their delay is up and despawns them once they're over. Without
+/// bevy's audio plugin the notes are only timed, never heard.
+fn play_notes(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut pitches: Option<ResMut<Assets<Pitch>>>,
+    mut notes: Query<(Entity, &mut ScheduledNote)>,
+) {
+    for (entity, mut scheduled) in &mut notes {
+        if scheduled.tick(time.delta_seconds()) {
+            if let Some(pitches) = pitches.as_mut() {
+                let note = scheduled.note;
+                commands.entity(entity).insert(PitchBundle {
+                    source: pitches.add(Pitch::new(
+                        note.frequency,
+                        Duration::from_secs_f32(note.length),
+                    )),
+                    settings: PlaybackSettings::ONCE.with_volume(Volume::new(scheduled.volume)),
+                });
+            }
+        }
+        if scheduled.is_over() {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sounds_and_music() {
+        assert!((note_frequency(12) - 880.0).abs() < 0.01);
+        let clears: Vec<_> = (1..=4).map(|rows| Sound::LineClear(rows).notes()).collect();
+        for (rows, notes) in clears.iter().enumerate() {
+            assert_eq!(notes.len(), rows + 2);
+        }
+        assert_eq!(Sound::LineClear(5).notes(), clears[3]);
+
+        let beats: f32 = MELODY.iter().map(|(_, beats)| beats).sum();
+        assert!((beats - 32.0).abs() < f32::EPSILON);
+        let mut music = Music::default();
+        let notes = music.advance(60.0 / 120.0 * beats, 120.0);
+        let sounding = MELODY.iter().filter(|(note, _)| note.is_some()).count();
+        assert_eq!(notes.len(), sounding + 1);
+        assert!(music_tempo(15) > music_tempo(1));
+
+        let mut scheduled = ScheduledNote::new(clears[0][1], 1.0);
+        assert!(!scheduled.tick(0.03));
+        assert!(scheduled.tick(0.03));
+        assert!(!scheduled.tick(0.03));
+        assert!(!scheduled.is_over());
+        assert!(!scheduled.tick(0.1));
+        assert!(scheduled.is_over());
+
+        let mut settings = AudioSettings::default();
+        for _ in 0..10 {
+            settings.step(VolumeSlider::Music, true);
+        }
+        settings.step(VolumeSlider::Sounds, false);
+        assert_eq!(settings.music_volume, 100);
+        assert_eq!(settings.sound_volume, 70);
+        assert_eq!(
+            settings.sliders(VolumeSlider::Sounds),
+            "  Music  [##########] 100%\n> Sounds [#######---] 70%"
+        );
+    }
+}
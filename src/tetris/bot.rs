@@ -0,0 +1,458 @@
+use crate::tetris::components::{ControlledTetromino, Grid, GridAction};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::tetris::save::{load_ron, save_ron};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, VecDeque};
+#[cfg(not(target_arch = "wasm32"))]
+use std::io;
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::Path;
+use std::time::Duration;
+
+const DEFAULT_PIECES_PER_SECOND: f32 = 2.0;
+
+/// Where `twotris-tune` writes its best weights and where the game looks for them.
+pub const WEIGHTS_PATH: &str = "assets/bot/weights.ron";
+
+/// Weights applied to each board feature when scoring a placement. Penalised features carry
+/// negative weights.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Weights {
+    pub aggregate_height: f32,
+    pub lines_cleared: f32,
+    pub holes: f32,
+    pub bumpiness: f32,
+    pub wells: f32,
+}
+
+impl Default for Weights {
+    fn default() -> Self {
+        Self {
+            aggregate_height: -0.51,
+            lines_cleared: 0.76,
+            holes: -0.36,
+            bumpiness: -0.18,
+            wells: -0.1,
+        }
+    }
+}
+
+impl From<[f32; 5]> for Weights {
+    fn from(v: [f32; 5]) -> Self {
+        Self {
+            aggregate_height: v[0],
+            lines_cleared: v[1],
+            holes: v[2],
+            bumpiness: v[3],
+            wells: v[4],
+        }
+    }
+}
+
+impl From<Weights> for [f32; 5] {
+    fn from(w: Weights) -> [f32; 5] {
+        [
+            w.aggregate_height,
+            w.lines_cleared,
+            w.holes,
+            w.bumpiness,
+            w.wells,
+        ]
+    }
+}
+
+impl Weights {
+    /// Reads weights written by [`Weights::save`].
+    ///
+    /// # Errors
+    ///
+    /// Fails if the file can't be read or isn't a valid weight set.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        load_ron(path)
+    }
+
+    /// Writes the weights as RON, creating parent directories as needed.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the file can't be written.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        save_ron(path, self)
+    }
+
+    #[must_use]
+    pub fn score(&self, features: &Features) -> f32 {
+        self.aggregate_height * features.aggregate_height as f32
+            + self.lines_cleared * features.lines_cleared as f32
+            + self.holes * features.holes as f32
+            + self.bumpiness * features.bumpiness as f32
+            + self.wells * features.wells as f32
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Features {
+    pub aggregate_height: usize,
+    pub lines_cleared: u32,
+    pub holes: usize,
+    pub bumpiness: usize,
+    pub wells: usize,
+}
+
+impl Features {
+    #[must_use]
+    pub fn from_grid(grid: &Grid, lines_cleared: u32) -> Self {
+        let heights: Vec<usize> = (0..grid.width())
+            .map(|x| {
+                (0..grid.total_height())
+                    .find(|&y| grid.get(x, y))
+                    .map_or(0, |y| grid.total_height() - y)
+            })
+            .collect();
+
+        let holes = heights
+            .iter()
+            .enumerate()
+            .map(|(x, &height)| {
+                let top = grid.total_height() - height;
+                (top..grid.total_height())
+                    .filter(|&y| !grid.get(x, y))
+                    .count()
+            })
+            .sum();
+
+        let bumpiness = heights.windows(2).map(|w| w[0].abs_diff(w[1])).sum();
+
+        let wells = (0..heights.len())
+            .map(|x| {
+                let left = if x == 0 { usize::MAX } else { heights[x - 1] };
+                let right = heights.get(x + 1).copied().unwrap_or(usize::MAX);
+                left.min(right).saturating_sub(heights[x])
+            })
+            .filter(|&depth| depth != usize::MAX)
+            .sum();
+
+        Self {
+            aggregate_height: heights.iter().sum(),
+            lines_cleared,
+            holes,
+            bumpiness,
+            wells,
+        }
+    }
+}
+
+/// Where a piece ends up and the inputs that take it there from its current position.
+#[derive(Debug, Clone)]
+pub struct Placement {
+    pub tetromino: ControlledTetromino,
+    pub actions: Vec<GridAction>,
+}
+
+/// Applies a single input the same way the game does. `grid` must not contain the piece.
+fn apply_action(
+    grid: &Grid,
+    tetromino: &ControlledTetromino,
+    action: GridAction,
+) -> Option<ControlledTetromino> {
+    let mut moved = *tetromino;
+    match action {
+        GridAction::MoveLeft => {
+            moved.top_left.0 = moved.top_left.0.checked_sub(1)?;
+        }
+        GridAction::MoveRight => moved.top_left.0 += 1,
+        GridAction::Rotate => return grid.rotated(tetromino),
+        GridAction::SoftDrop => {
+            if grid.is_tetromino_at_bottom(tetromino) {
+                return None;
+            }
+            moved.top_left.1 += 1;
+        }
+        GridAction::HardDrop => return Some(grid.controlled_tetromino_shadow(tetromino)),
+    }
+    grid.is_tetromino_space_open(&moved).then_some(moved)
+}
+
+/// Every resting position reachable from the piece's current position, including rotations and
+/// soft drop tucks, with the shortest input sequence for each. `grid` must not contain the piece.
+#[must_use]
+pub fn reachable_placements(grid: &Grid, tetromino: &ControlledTetromino) -> Vec<Placement> {
+    type State = (usize, usize, usize);
+    let key = |t: &ControlledTetromino| (t.top_left.0, t.top_left.1, t.rotation);
+
+    let mut parents: HashMap<State, Option<(State, GridAction)>> = HashMap::new();
+    let mut queue = VecDeque::new();
+    let mut placements: HashMap<State, Placement> = HashMap::new();
+    parents.insert(key(tetromino), None);
+    queue.push_back(*tetromino);
+
+    let path_to = |parents: &HashMap<State, Option<(State, GridAction)>>, mut state: State| {
+        let mut actions = vec![];
+        while let Some(Some((parent, action))) = parents.get(&state) {
+            actions.push(*action);
+            state = *parent;
+        }
+        actions.reverse();
+        actions
+    };
+
+    while let Some(current) = queue.pop_front() {
+        let rest = grid.controlled_tetromino_shadow(&current);
+        placements.entry(key(&rest)).or_insert_with(|| {
+            let mut actions = path_to(&parents, key(&current));
+            actions.push(GridAction::HardDrop);
+            Placement {
+                tetromino: rest,
+                actions,
+            }
+        });
+
+        for action in [
+            GridAction::MoveLeft,
+            GridAction::MoveRight,
+            GridAction::Rotate,
+            GridAction::SoftDrop,
+        ] {
+            if let Some(next) = apply_action(grid, &current, action) {
+                if let Entry::Vacant(entry) = parents.entry(key(&next)) {
+                    entry.insert(Some((key(&current), action)));
+                    queue.push_back(next);
+                }
+            }
+        }
+    }
+
+    let mut placements: Vec<_> = placements.into_values().collect();
+    placements.sort_by_key(|p| (p.actions.len(), key(&p.tetromino)));
+    placements
+}
+
+/// Locks `placement` into a copy of `grid`, returning the result and the number of cleared rows.
+fn lock_placement(grid: &Grid, placement: &Placement) -> (Grid, u32) {
+    let mut grid = grid.clone();
+    grid.set_tetromino(&placement.tetromino);
+    let lines = grid.clear_full_grid_rows();
+    (grid, lines)
+}
+
+/// Picks the highest scoring placement for `current`, looking one piece ahead when `next` is
+/// known. `grid` must not contain either piece. Placements that would lock out are avoided
+/// whenever anything else is available.
+pub fn best_placement(
+    grid: &Grid,
+    current: &ControlledTetromino,
+    next: Option<&ControlledTetromino>,
+    weights: &Weights,
+) -> Option<Placement> {
+    let mut best: Option<(bool, f32, Placement)> = None;
+    for placement in reachable_placements(grid, current) {
+        let locks_out = grid.is_tetromino_above_visible(&placement.tetromino);
+        let (after, lines) = lock_placement(grid, &placement);
+        let score = match next {
+            Some(next) if after.is_tetromino_space_open(next) => reachable_placements(&after, next)
+                .iter()
+                .map(|next_placement| {
+                    let (after_next, next_lines) = lock_placement(&after, next_placement);
+                    weights.score(&Features::from_grid(&after_next, lines + next_lines))
+                })
+                .fold(f32::NEG_INFINITY, f32::max),
+            _ => weights.score(&Features::from_grid(&after, lines)),
+        };
+        let is_better = best.as_ref().is_none_or(|(best_locks_out, best_score, _)| {
+            (!locks_out, score) > (!*best_locks_out, *best_score)
+        });
+        if is_better {
+            best = Some((locks_out, score, placement));
+        }
+    }
+    best.map(|(_, _, placement)| placement)
+}
+
+/// Settings applied to bots enabled during play.
+#[derive(Debug, Clone, Resource)]
+pub struct BotSettings {
+    pub weights: Weights,
+    pub pieces_per_second: f32,
+}
+
+impl Default for BotSettings {
+    fn default() -> Self {
+        Self {
+            weights: Weights::default(),
+            pieces_per_second: DEFAULT_PIECES_PER_SECOND,
+        }
+    }
+}
+
+/// Marks a grid as played by the CPU instead of the keyboard.
+#[derive(Debug, Component)]
+pub struct Bot {
+    pub weights: Weights,
+    pub pieces_per_second: f32,
+    piece: Option<Entity>,
+    /// The inputs still to send, each with where the piece should be once it has been applied.
+    plan: VecDeque<(GridAction, ControlledTetromino)>,
+    /// Where the piece should be once the inputs sent so far have been applied.
+    expected: Option<ControlledTetromino>,
+    timer: Timer,
+}
+
+impl Bot {
+    #[must_use]
+    pub fn new(settings: &BotSettings) -> Self {
+        Self {
+            weights: settings.weights,
+            pieces_per_second: settings.pieces_per_second,
+            piece: None,
+            plan: VecDeque::new(),
+            expected: None,
+            timer: Timer::default(),
+        }
+    }
+
+    /// Whether `tetromino` is a new piece, or has left the path planned for it, for example by
+    /// falling under gravity.
+    #[must_use]
+    pub fn needs_plan(&self, piece: Entity, tetromino: &ControlledTetromino) -> bool {
+        self.piece != Some(piece) || self.expected.as_ref() != Some(tetromino)
+    }
+
+    /// Plans the inputs that take the piece from where it is now to its best placement, looking
+    /// at `next` when it is known. A new piece's inputs are spread evenly over the time budget
+    /// for one piece; a replanned piece keeps its pace. `grid` still contains the piece.
+    pub fn plan(
+        &mut self,
+        piece: Entity,
+        grid: &Grid,
+        tetromino: &ControlledTetromino,
+        next: Option<&ControlledTetromino>,
+    ) {
+        let mut grid = grid.clone();
+        grid.unset_tetromino(tetromino);
+        let actions = best_placement(&grid, tetromino, next, &self.weights)
+            .map_or_else(|| vec![GridAction::HardDrop], |p| p.actions);
+        if self.piece != Some(piece) {
+            let interval = 1.0 / (self.pieces_per_second.max(f32::EPSILON) * actions.len() as f32);
+            self.piece = Some(piece);
+            self.timer = Timer::from_seconds(interval, TimerMode::Repeating);
+        }
+        let mut at = *tetromino;
+        self.plan = actions
+            .into_iter()
+            .map(|action| {
+                at = apply_action(&grid, &at, action).unwrap_or(at);
+                (action, at)
+            })
+            .collect();
+        self.expected = Some(*tetromino);
+    }
+
+    /// Advances the bot's clock, returning the inputs that are due.
+    pub fn tick(&mut self, delta: Duration) -> Vec<GridAction> {
+        self.timer.tick(delta);
+        let due = self.timer.times_finished_this_tick() as usize;
+        let due = due.min(self.plan.len());
+        self.plan
+            .drain(..due)
+            .map(|(action, at)| {
+                self.expected = Some(at);
+                action
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tetris::components::{GridConfig, TetrominoType};
+
+    fn grid_with_rows(rows: &[&str]) -> Grid {
+        let mut grid = Grid::new(GridConfig::new(rows[0].len(), rows.len()));
+        let offset = grid.buffer_height();
+        for (y, row) in rows.iter().enumerate() {
+            for (x, cell) in row.chars().enumerate() {
+                grid.set(x, y + offset, cell == 'X');
+            }
+        }
+        grid
+    }
+
+    #[test]
+    fn test_weights_round_trip() {
+        let path = std::env::temp_dir().join("twotris_test_weights.ron");
+        let weights = Weights::from([-1.0, 2.0, -3.0, -4.0, -5.0]);
+        weights.save(&path).unwrap();
+        assert_eq!(Weights::load(&path).unwrap(), weights);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_features() {
+        let grid = grid_with_rows(&["....", "X.X.", "X...", "XXX."]);
+        let features = Features::from_grid(&grid, 0);
+        assert_eq!(features.aggregate_height, 3 + 1 + 3);
+        assert_eq!(features.holes, 1);
+        assert_eq!(features.bumpiness, 2 + 2 + 3);
+        assert_eq!(features.wells, 2 + 3);
+    }
+
+    #[test]
+    fn test_best_placement_fills_well() {
+        let grid = grid_with_rows(&[
+            "......", "......", "......", "XXXXX.", "XXXXX.", "XXXXX.", "XXXXX.",
+        ]);
+        let tetromino = ControlledTetromino::new_with_tetromino_type(TetrominoType::I, &grid);
+        let placement = best_placement(&grid, &tetromino, None, &Weights::default()).unwrap();
+        assert!(placement.tetromino.coords(&grid).all(|(x, _)| x == 5));
+        assert_eq!(placement.actions.last(), Some(&GridAction::HardDrop));
+
+        let (after, lines) = lock_placement(&grid, &placement);
+        assert_eq!(lines, 4);
+        assert_eq!(after.set_coords_iter().count(), 0);
+    }
+
+    #[test]
+    fn test_reachable_placements_include_tucks() {
+        let grid = grid_with_rows(&["......", "XXX...", "......", "......"]);
+        let tetromino = ControlledTetromino::new_with_tetromino_type(TetrominoType::O, &grid);
+        let bottom = grid.total_height() - 2;
+        let tuck = reachable_placements(&grid, &tetromino)
+            .into_iter()
+            .find(|p| p.tetromino.top_left == (0, bottom))
+            .expect("O piece can be tucked under the overhang");
+        assert!(tuck.actions.contains(&GridAction::SoftDrop));
+        assert!(tuck.actions.contains(&GridAction::MoveLeft));
+    }
+
+    #[test]
+    fn test_bot_replans_after_gravity() {
+        let mut grid = grid_with_rows(&["......"; 6]);
+        let piece = Entity::from_raw(1);
+        let tetromino = grid.spawn_tetromino_type(TetrominoType::T).unwrap();
+        let mut bot = Bot::new(&BotSettings::default());
+        assert!(bot.needs_plan(piece, &tetromino));
+        bot.plan(piece, &grid, &tetromino, None);
+        assert!(!bot.needs_plan(piece, &tetromino));
+
+        grid.unset_tetromino(&tetromino);
+        let mut fallen = tetromino;
+        fallen.top_left.1 += 1;
+        assert!(bot.needs_plan(piece, &fallen));
+
+        grid.set_tetromino(&fallen);
+        bot.plan(piece, &grid, &fallen, None);
+        assert!(!bot.needs_plan(piece, &fallen));
+        grid.unset_tetromino(&fallen);
+        let actions = bot.tick(Duration::from_secs(10));
+        let landed = actions.into_iter().fold(fallen, |at, action| {
+            apply_action(&grid, &at, action).expect("replanned inputs start from the fallen piece")
+        });
+        assert!(grid.is_tetromino_at_bottom(&landed));
+        assert!(!bot.needs_plan(piece, &landed));
+    }
+}
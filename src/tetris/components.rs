@@ -1,32 +1,71 @@
 use super::RandomSource;
+use crate::tetris::pieces::{Piece, PieceDef, PieceSet};
+use crate::tetris::save::read_ron;
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::error::Error;
 use std::fmt::{Display, Formatter, Result as fmtResult};
+use std::io;
+use std::str::FromStr;
+use std::sync::{Arc, LazyLock};
+use std::time::Duration;
 
-const GRID_WIDTH: usize = 10;
-const GRID_HEIGHT: usize = 16;
+pub const DEFAULT_GRID_WIDTH: usize = 10;
+pub const DEFAULT_GRID_HEIGHT: usize = 16;
+pub const DEFAULT_BUFFER_HEIGHT: usize = 20;
+/// Widest grid that fits a row in a `u64` bitmask.
+pub const MAX_GRID_WIDTH: usize = 64;
 
-#[derive(Debug, Clone, Default, Event)]
-pub struct RowClearedEvent(pub u32);
+/// The delays file, in the same data directory as the high scores.
+pub const DELAYS_FILE: &str = "delays.ron";
+
+/// The localStorage key the delays are kept under on the web.
+pub const DELAYS_STORAGE_KEY: &str = "twotris.delays";
+
+/// Sent when a grid finishes clearing the given number of rows.
+#[derive(Debug, Clone, Event)]
+pub struct RowClearedEvent(pub Entity, pub u32);
 
 #[derive(Debug, Clone, Event)]
 pub struct DrawGrid(pub Entity);
 
+/// Sent when a new piece of the given kind enters a grid.
+#[derive(Debug, Clone, Event)]
+pub struct PieceSpawnedEvent(pub Entity, pub Piece);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum GridAction {
+    MoveLeft,
+    MoveRight,
+    Rotate,
+    SoftDrop,
+    HardDrop,
+}
+
+/// An input for the piece on the given grid, sent by the keyboard or a bot.
+#[derive(Debug, Clone, Event)]
+pub struct GridActionEvent(pub Entity, pub GridAction);
+
 impl RowClearedEvent {
-    pub fn new(v: u32) -> Self {
-        RowClearedEvent(v)
+    #[must_use]
+    pub fn new(grid: Entity, rows: u32) -> Self {
+        RowClearedEvent(grid, rows)
     }
 }
 
 impl From<RowClearedEvent> for u32 {
     fn from(v: RowClearedEvent) -> u32 {
-        v.0
+        v.1
     }
 }
 
-#[derive(Debug, Default, Component, Clone, Copy)]
+#[derive(Debug, Default, Component, Clone, Copy, Serialize, Deserialize)]
 pub struct Score(pub u32);
 
 impl Score {
+    #[must_use]
     pub fn get(self) -> u32 {
         self.0
     }
@@ -47,57 +86,472 @@ impl Score {
     }
 }
 
+/// Lines cleared and pieces locked on one grid since the game started, and the garbage rows
+/// still left on it.
+#[derive(Debug, Default, Component, Clone, Copy, Serialize, Deserialize)]
+pub struct GridStats {
+    pub lines: u32,
+    pub pieces: u32,
+    pub garbage: u32,
+    pub perfect_clears: u32,
+    #[serde(default)]
+    pub t_spin_doubles: u32,
+}
+
+/// Frame counts, at 60 frames per second, of the pauses around one piece locking and the next
+/// one spawning.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Resource, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Delays {
+    /// How long a piece can rest on the stack before it locks.
+    pub lock_frames: u32,
+    /// How long full rows flash before they're removed.
+    pub line_clear_frames: u32,
+    /// The entry delay (ARE) before the next piece spawns.
+    pub entry_frames: u32,
+}
+
+impl Delays {
+    pub const FRAMES_PER_SECOND: u32 = 60;
+
+    /// Reads the delays set on this platform, the defaults if there are none.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the delays can't be read or aren't valid RON.
+    pub fn read() -> io::Result<Self> {
+        Ok(read_ron(DELAYS_FILE, DELAYS_STORAGE_KEY)?.unwrap_or_default())
+    }
+
+    #[must_use]
+    pub fn lock(&self) -> Duration {
+        Self::frames(self.lock_frames)
+    }
+
+    #[must_use]
+    pub fn line_clear(&self) -> Duration {
+        Self::frames(self.line_clear_frames)
+    }
+
+    #[must_use]
+    pub fn entry(&self) -> Duration {
+        Self::frames(self.entry_frames)
+    }
+
+    fn frames(frames: u32) -> Duration {
+        Duration::from_secs(u64::from(frames)) / Self::FRAMES_PER_SECOND
+    }
+}
+
+impl Default for Delays {
+    fn default() -> Self {
+        Self {
+            lock_frames: 30,
+            line_clear_frames: 24,
+            entry_frames: 6,
+        }
+    }
+}
+
+/// Where a grid is in the cycle between pieces.
+#[derive(Debug, Clone, Default, Component)]
+pub enum GridPhase {
+    /// The piece is falling.
+    #[default]
+    Falling,
+    /// The piece rests on the stack and locks once `timer` runs out, unless it's moved off the
+    /// stack first.
+    Locking { timer: Timer },
+    /// Full `rows` flash before they're removed. `t_spin` is whether the locked piece spun into
+    /// place.
+    Clearing {
+        rows: Vec<usize>,
+        t_spin: bool,
+        timer: Timer,
+    },
+    /// Waiting out the entry delay before the next piece spawns.
+    Spawning { timer: Timer },
+}
+
+impl GridPhase {
+    /// The phase after a piece locks into `grid`: clearing if it filled any rows, otherwise
+    /// waiting to spawn the next piece.
+    #[must_use]
+    pub fn after_lock(grid: &Grid, t_spin: bool, delays: &Delays) -> Self {
+        let rows = grid.full_rows();
+        if rows.is_empty() {
+            Self::spawning(delays)
+        } else {
+            GridPhase::Clearing {
+                rows,
+                t_spin,
+                timer: Timer::new(delays.line_clear(), TimerMode::Once),
+            }
+        }
+    }
+
+    #[must_use]
+    pub fn locking(delays: &Delays) -> Self {
+        GridPhase::Locking {
+            timer: Timer::new(delays.lock(), TimerMode::Once),
+        }
+    }
+
+    #[must_use]
+    pub fn spawning(delays: &Delays) -> Self {
+        GridPhase::Spawning {
+            timer: Timer::new(delays.entry(), TimerMode::Once),
+        }
+    }
+
+    /// Whether the last piece has locked and the next one hasn't spawned yet.
+    #[must_use]
+    pub fn is_between_pieces(&self) -> bool {
+        matches!(
+            self,
+            GridPhase::Clearing { .. } | GridPhase::Spawning { .. }
+        )
+    }
+}
+
+/// Position of a grid from left to right, matching its entry in `GridConfigs`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Component)]
+pub struct GridIndex(pub usize);
+
+/// A fixed sequence of pieces for a grid. Grids without one draw random pieces; grids whose
+/// queue has run out stop spawning.
+#[derive(Debug, Default, Clone, Component, Serialize, Deserialize)]
+pub struct PieceQueue(pub VecDeque<Piece>);
+
 #[derive(Debug, Default, Component, Reflect)]
 pub struct Coordinate(pub usize, pub usize);
 
 impl Coordinate {
+    #[must_use]
     pub fn tuple(&self) -> (usize, usize) {
         (self.0, self.1)
     }
 }
 
+/// Marks the text shown once a game ends, despawned on restart.
 #[derive(Debug, Component)]
 pub struct GameOver;
 
+/// Marks the pause and continue prompts, despawned once play resumes.
 #[derive(Debug, Component)]
-pub struct Focus;
+pub struct PauseText;
 
 #[derive(Debug, Component)]
+pub struct Focus;
+
+/// Size of a single grid. `height` is the number of visible rows, `buffer` the number of hidden
+/// rows stacked above them where new pieces spawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GridConfig {
+    pub width: usize,
+    pub height: usize,
+    pub buffer: usize,
+}
+
+impl GridConfig {
+    #[must_use]
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            buffer: DEFAULT_BUFFER_HEIGHT,
+        }
+    }
+}
+
+impl Default for GridConfig {
+    fn default() -> Self {
+        Self::new(DEFAULT_GRID_WIDTH, DEFAULT_GRID_HEIGHT)
+    }
+}
+
+/// The grids spawned when a game starts, from left to right.
+#[derive(Debug, Clone, Resource)]
+pub struct GridConfigs(pub Vec<GridConfig>);
+
+impl Default for GridConfigs {
+    fn default() -> Self {
+        Self(vec![GridConfig::default(), GridConfig::default()])
+    }
+}
+
+/// Rows are indexed from the top of the hidden buffer, so the first visible row is
+/// `buffer_height()`.
+///
+/// Each row is stored as a bitmask with bit `x` set when column `x` is filled, so collision and
+/// line checks test a whole row of a piece at once.
+#[derive(Debug, Clone, Component, Serialize, Deserialize)]
+#[serde(try_from = "SerializedGrid", into = "SerializedGrid")]
 pub struct Grid {
-    grid: [[bool; GRID_WIDTH]; GRID_HEIGHT],
+    rows: Vec<u64>,
+    /// Whether each row was added as garbage rather than built by placed pieces.
+    garbage: Vec<bool>,
+    width: usize,
+    buffer: usize,
+    /// The set pieces are dealt from, which pieces in play refer to by index unless they're
+    /// classic. Shared, so searching placements can clone the grid cheaply.
+    pieces: Arc<[Piece]>,
 }
 
 impl Grid {
+    /// A grid dealing only the classic pieces until `set_pieces` is called.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `config` is wider than `MAX_GRID_WIDTH`.
+    #[must_use]
+    pub fn new(config: GridConfig) -> Self {
+        assert!(
+            config.width <= MAX_GRID_WIDTH,
+            "grids are at most {MAX_GRID_WIDTH} cells wide, not {}",
+            config.width
+        );
+        Grid {
+            rows: vec![0; config.buffer + config.height],
+            garbage: vec![false; config.buffer + config.height],
+            width: config.width,
+            buffer: config.buffer,
+            pieces: Arc::from([]),
+        }
+    }
+
+    /// Deals from `pieces` from now on. Pieces in play from an earlier set must be removed first.
+    pub fn set_pieces(&mut self, pieces: &PieceSet) {
+        self.pieces = pieces.pieces().into();
+    }
+
+    /// The id pieces of this kind have on this grid, if it's classic or in the grid's set.
+    #[must_use]
+    pub fn piece_id(&self, piece: &Piece) -> Option<PieceId> {
+        match piece.tetromino_type() {
+            Some(tetromino_type) => Some(PieceId::Classic(tetromino_type)),
+            None => self
+                .pieces
+                .iter()
+                .position(|dealt| dealt == piece)
+                .map(PieceId::Set),
+        }
+    }
+
+    /// The kind of piece `id` refers to on this grid.
+    #[must_use]
+    pub fn piece(&self, id: PieceId) -> &Piece {
+        match id {
+            PieceId::Classic(tetromino_type) => &CLASSIC[tetromino_type as usize],
+            PieceId::Set(index) => &self.pieces[index],
+        }
+    }
+
+    /// Number of visible rows.
     #[inline]
+    #[must_use]
     pub fn height(&self) -> usize {
-        self.grid.len()
+        self.rows.len() - self.buffer
     }
 
     #[inline]
+    #[must_use]
     pub fn width(&self) -> usize {
-        self.grid[0].len()
+        self.width
+    }
+
+    #[must_use]
+    pub fn config(&self) -> GridConfig {
+        GridConfig {
+            width: self.width,
+            height: self.height(),
+            buffer: self.buffer,
+        }
+    }
+
+    /// Builds an empty grid of the given size whose visible field ends with the rows in `text`,
+    /// written in the same notation as `Display`. Piece letters are accepted and parsed as
+    /// filled cells. Rows above a line of `-` end the hidden buffer, so a grid's `Display` output
+    /// can be read back as is.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `text` isn't valid grid notation, its rows don't match the width of `config`
+    /// or there are more of them than visible or buffer rows.
+    pub fn with_visible_rows(config: GridConfig, text: &str) -> Result<Self, ParseGridError> {
+        let (cells, separator) = split_buffer(text);
+        let rows = filled_rows(&parse_rows(&cells)?);
+        if rows[0].len() != config.width {
+            return Err(ParseGridError::RowWidth {
+                row: 0,
+                expected: config.width,
+                found: rows[0].len(),
+            });
+        }
+        let (buffer_rows, visible_rows) = rows.split_at(separator.unwrap_or(0));
+        if visible_rows.len() > config.height {
+            return Err(ParseGridError::TooManyRows {
+                max: config.height,
+                found: visible_rows.len(),
+            });
+        }
+        if buffer_rows.len() > config.buffer {
+            return Err(ParseGridError::TooManyRows {
+                max: config.buffer,
+                found: buffer_rows.len(),
+            });
+        }
+        let mut grid = Grid::new(config);
+        let buffer_top = grid.buffer_height() - buffer_rows.len();
+        for (y, row) in buffer_rows.iter().enumerate() {
+            grid.rows[buffer_top + y] = row_mask(row);
+        }
+        let top = grid.total_height() - visible_rows.len();
+        for (y, row) in visible_rows.iter().enumerate() {
+            grid.rows[top + y] = row_mask(row);
+        }
+        Ok(grid)
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn buffer_height(&self) -> usize {
+        self.buffer
+    }
+
+    /// Number of rows including the hidden buffer.
+    #[inline]
+    #[must_use]
+    pub fn total_height(&self) -> usize {
+        self.rows.len()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn is_visible_row(&self, y: usize) -> bool {
+        y >= self.buffer && y < self.rows.len()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn get(&self, x: usize, y: usize) -> bool {
+        x < self.width && self.rows[y] >> x & 1 == 1
     }
 
     pub fn set(&mut self, x: usize, y: usize, val: bool) {
-        if x >= GRID_WIDTH || y >= GRID_HEIGHT {
+        if x >= self.width() || y >= self.total_height() {
             error!(
                 "Attempted to set a cell outside of the grid: ({}, {})",
                 x, y
             );
             return;
         }
-        self.grid[y][x] = val;
+        if val {
+            self.rows[y] |= 1 << x;
+        } else {
+            self.rows[y] &= !(1 << x);
+        }
     }
 
     pub fn clear(&mut self) {
-        self.grid = [[false; GRID_WIDTH]; GRID_HEIGHT];
+        self.rows.fill(0);
+        self.garbage.fill(false);
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn is_garbage_row(&self, y: usize) -> bool {
+        self.garbage[y]
+    }
+
+    /// Number of garbage rows that haven't been cleared yet.
+    #[must_use]
+    pub fn garbage_rows(&self) -> usize {
+        self.garbage.iter().filter(|&&garbage| garbage).count()
+    }
+
+    /// Pushes every row up by one and fills the bottom row except for the `holes` columns. The
+    /// top row of the buffer is discarded.
+    pub fn add_garbage_row(&mut self, holes: &[usize]) {
+        let row = holes
+            .iter()
+            .filter(|&&x| x < self.width)
+            .fold(self.full_row(), |row, &x| row & !(1 << x));
+        self.rows.remove(0);
+        self.rows.push(row);
+        self.garbage.remove(0);
+        self.garbage.push(true);
+    }
+
+    /// Adds `rows` garbage rows at the bottom, each with one or two holes in random columns.
+    pub fn add_random_garbage(&mut self, rows: usize, rng: &mut RandomSource) {
+        let width = u32::try_from(self.width).unwrap_or(u32::MAX);
+        for _ in 0..rows {
+            let holes: Vec<usize> = (0..rng.next(1, 3))
+                .map(|_| rng.next(0, width) as usize)
+                .collect();
+            self.add_garbage_row(&holes);
+        }
+    }
+
+    /// The mask of a row with every cell filled.
+    #[inline]
+    fn full_row(&self) -> u64 {
+        u32::try_from(self.width)
+            .ok()
+            .and_then(|width| u64::MAX.checked_shr(u64::BITS - width))
+            .unwrap_or(0)
+    }
+
+    /// `mask` moved right by `x` columns, or `None` if any of its cells would leave the grid.
+    #[inline]
+    fn shift_mask(&self, mask: u64, x: usize) -> Option<u64> {
+        let bits = (u64::BITS - mask.leading_zeros()) as usize;
+        if x + bits > self.width {
+            return None;
+        }
+        mask.checked_shl(u32::try_from(x).ok()?).or(Some(0))
+    }
+
+    /// Each row of `tetromino` as a mask of grid columns, along with the grid row it's on and the
+    /// mask of the piece's row below it. `None` for a row with cells outside the grid.
+    fn tetromino_rows<'a>(
+        &'a self,
+        tetromino: &'a ControlledTetromino,
+    ) -> impl Iterator<Item = (usize, Option<u64>, u64)> + 'a {
+        let (x, top) = tetromino.top_left;
+        let masks = tetromino.row_masks(self);
+        let below = tetromino.row_masks(self).skip(1).chain(std::iter::once(0));
+        masks
+            .zip(below)
+            .enumerate()
+            .map(move |(dy, (mask, below))| {
+                (
+                    top + dy,
+                    self.shift_mask(mask, x),
+                    self.shift_mask(below, x).unwrap_or(0),
+                )
+            })
     }
 
     fn set_tetromino_values(&mut self, tetromino: &ControlledTetromino, val: bool) {
-        for (y, row) in tetromino.current_structure().iter().enumerate() {
-            for (x, cell) in row.iter().enumerate() {
-                if *cell {
-                    self.set(tetromino.top_left.0 + x, tetromino.top_left.1 + y, val);
+        let (left, top) = tetromino.top_left;
+        for dy in 0..tetromino.shape(self).height() {
+            let mask = tetromino.shape(self).rows[dy];
+            let y = top + dy;
+            match self.shift_mask(mask, left) {
+                Some(mask) if y < self.total_height() => {
+                    if val {
+                        self.rows[y] |= mask;
+                    } else {
+                        self.rows[y] &= !mask;
+                    }
+                }
+                // Let `set` report the cells that don't fit.
+                _ => {
+                    for x in (0..MAX_GRID_WIDTH).filter(|x| mask >> x & 1 == 1) {
+                        self.set(left + x, y, val);
+                    }
                 }
             }
         }
@@ -111,139 +565,267 @@ impl Grid {
         self.set_tetromino_values(tetromino, false);
     }
 
+    /// Spawns a new piece above the visible field and drops it one row if nothing blocks it.
+    /// Returns `None` on block out, when the spawn position is already occupied.
+    pub fn spawn_tetromino(&mut self, rng: &mut RandomSource) -> Option<ControlledTetromino> {
+        self.spawn_tetromino_type(TetrominoType::random(rng))
+    }
+
+    pub fn spawn_tetromino_type(
+        &mut self,
+        tetromino_type: TetrominoType,
+    ) -> Option<ControlledTetromino> {
+        self.spawn_id(PieceId::Classic(tetromino_type))
+    }
+
+    /// Like `spawn_tetromino`, also returning `None` if `piece` isn't classic or in the grid's
+    /// set.
+    pub fn spawn_piece(&mut self, piece: &Piece) -> Option<ControlledTetromino> {
+        let id = self.piece_id(piece)?;
+        self.spawn_id(id)
+    }
+
+    fn spawn_id(&mut self, id: PieceId) -> Option<ControlledTetromino> {
+        let mut tetromino = ControlledTetromino::new_with_id(id, self);
+        if !self.is_tetromino_space_open(&tetromino) {
+            return None;
+        }
+        if !self.is_tetromino_at_bottom(&tetromino) {
+            tetromino.top_left.1 += 1;
+        }
+        self.set_tetromino(&tetromino);
+        Some(tetromino)
+    }
+
+    #[must_use]
     pub fn is_tetromino_space_open(&self, tetromino: &ControlledTetromino) -> bool {
-        for (y, row) in tetromino.current_structure().iter().enumerate() {
-            for (x, cell) in row.iter().enumerate() {
-                if *cell
-                    && (tetromino.top_left.0 + x >= GRID_WIDTH
-                        || tetromino.top_left.1 + y >= GRID_HEIGHT
-                        || self.grid[tetromino.top_left.1 + y][tetromino.top_left.0 + x])
-                {
-                    return false;
-                }
-            }
+        self.tetromino_rows(tetromino)
+            .all(|(y, mask, _)| match mask {
+                Some(mask) => mask == 0 || (y < self.total_height() && self.rows[y] & mask == 0),
+                None => false,
+            })
+    }
+
+    /// `tetromino` turned a quarter, or if that doesn't fit where it is, one cell left and one
+    /// row down. That's where a T pointing right ends up turning about its center, so the kick
+    /// lets it turn down under an overhang into a T-spin slot. `None` if neither fits. The grid
+    /// must not contain the piece.
+    #[must_use]
+    pub fn rotated(&self, tetromino: &ControlledTetromino) -> Option<ControlledTetromino> {
+        let mut rotated = *tetromino;
+        rotated.rotate(self);
+        if self.is_tetromino_space_open(&rotated) {
+            return Some(rotated);
         }
-        true
+        rotated.top_left = (rotated.top_left.0.checked_sub(1)?, rotated.top_left.1 + 1);
+        self.is_tetromino_space_open(&rotated).then_some(rotated)
     }
 
+    /// Whether the piece is against the left wall or a filled cell. The grid may contain the
+    /// piece.
+    #[must_use]
     pub fn is_tetromino_blocked_left(&self, tetromino: &ControlledTetromino) -> bool {
-        for (y, row) in tetromino.current_structure().iter().enumerate() {
-            let mut left = tetromino.top_left.0;
-            for (i, v) in row.iter().enumerate() {
-                if *v {
-                    left = i + tetromino.top_left.0;
-                    break;
-                }
-            }
-            if left == 0 || (left > 0 && self.grid[tetromino.top_left.1 + y][left - 1]) {
-                return true;
-            }
-        }
-        false
+        self.tetromino_rows(tetromino).any(|(y, mask, _)| {
+            let mask = mask.unwrap_or(0);
+            mask & 1 != 0 || self.rows[y] & (mask >> 1) & !mask != 0
+        })
     }
 
+    /// Whether the piece is against the right wall or a filled cell. The grid may contain the
+    /// piece.
+    #[must_use]
     pub fn is_tetromino_blocked_right(&self, tetromino: &ControlledTetromino) -> bool {
-        for (y, row) in tetromino.current_structure().iter().enumerate() {
-            let mut right = row.len() - 1;
-            for (i, v) in row.iter().enumerate().rev() {
-                if *v {
-                    right = i;
-                    break;
-                }
-            }
-            let right = right + tetromino.top_left.0;
-            if right == GRID_WIDTH - 1
-                || (right < GRID_WIDTH - 1 && self.grid[tetromino.top_left.1 + y][right + 1])
-            {
-                return true;
-            }
-        }
-        false
+        let right_wall = 1 << (self.width - 1);
+        self.tetromino_rows(tetromino).any(|(y, mask, _)| {
+            let mask = mask.unwrap_or(0);
+            mask & right_wall != 0 || self.rows[y] & (mask << 1) & self.full_row() & !mask != 0
+        })
     }
 
+    /// Whether the piece rests on the floor or a filled cell. The grid may contain the piece.
+    #[must_use]
     pub fn is_tetromino_at_bottom(&self, tetromino: &ControlledTetromino) -> bool {
-        let mut checked_cols = vec![];
-        for (y, row) in tetromino.current_structure().iter().enumerate().rev() {
-            for (x, cell) in row.iter().enumerate() {
-                if *cell && !checked_cols.contains(&x) {
-                    checked_cols.push(x);
-                    debug!(
-                        "Checking cell at ({}, {})",
-                        tetromino.top_left.0 + x,
-                        tetromino.top_left.1 + y
-                    );
-                    if tetromino.top_left.1 + y == GRID_HEIGHT - 1
-                        || self.grid[tetromino.top_left.1 + y + 1][tetromino.top_left.0 + x]
-                    {
-                        return true;
-                    }
-                }
-            }
-        }
-        false
+        self.tetromino_rows(tetromino).any(|(y, mask, below)| {
+            let mask = mask.unwrap_or(0);
+            // Cells with more of the piece below them can't be what it rests on.
+            let bottom = mask & !below;
+            bottom != 0 && (y + 1 >= self.total_height() || self.rows[y + 1] & bottom != 0)
+        })
     }
 
+    #[must_use]
     pub fn controlled_tetromino_shadow(
         &self,
         tetromino: &ControlledTetromino,
     ) -> ControlledTetromino {
-        let mut shadow = tetromino.clone();
+        let mut shadow = *tetromino;
         while !self.is_tetromino_at_bottom(&shadow) {
             shadow.top_left.1 += 1;
         }
         shadow
     }
 
+    /// Whether `tetromino` is a T piece with at least three of the four cells diagonal to its
+    /// center filled or outside the grid. The grid must not contain the piece.
+    #[must_use]
+    pub fn is_t_spin_position(&self, tetromino: &ControlledTetromino) -> bool {
+        if tetromino.piece != PieceId::Classic(TetrominoType::T) {
+            return false;
+        }
+        let coords: Vec<_> = tetromino.coords(self).collect();
+        let Some(&(x, y)) = coords.iter().find(|&&(x, y)| {
+            [
+                (x + 1, y),
+                (x.wrapping_sub(1), y),
+                (x, y + 1),
+                (x, y.wrapping_sub(1)),
+            ]
+            .iter()
+            .filter(|neighbour| coords.contains(neighbour))
+            .count()
+                == 3
+        }) else {
+            return false;
+        };
+        let corners = [
+            (x.checked_sub(1), y.checked_sub(1)),
+            (Some(x + 1), y.checked_sub(1)),
+            (x.checked_sub(1), Some(y + 1)),
+            (Some(x + 1), Some(y + 1)),
+        ];
+        corners
+            .iter()
+            .filter(|corner| match corner {
+                (Some(x), Some(y)) => {
+                    *x >= self.width() || *y >= self.total_height() || self.get(*x, *y)
+                }
+                _ => true,
+            })
+            .count()
+            >= 3
+    }
+
+    /// Lock out: a piece that locks without any cell in the visible field ends the game.
+    #[must_use]
+    pub fn is_tetromino_above_visible(&self, tetromino: &ControlledTetromino) -> bool {
+        tetromino.coords(self).all(|(_, y)| y < self.buffer)
+    }
+
     pub fn force_tetromino_to_bottom(&mut self, tetromino: &mut ControlledTetromino) {
         while !self.is_tetromino_at_bottom(tetromino) {
             tetromino.top_left.1 += 1;
         }
     }
 
+    /// Rows with every cell filled, top to bottom.
+    #[must_use]
+    pub fn full_rows(&self) -> Vec<usize> {
+        let full = self.full_row();
+        self.rows
+            .iter()
+            .enumerate()
+            .filter(|(_, &row)| row == full)
+            .map(|(y, _)| y)
+            .collect()
+    }
+
     pub fn clear_full_grid_rows(&mut self) -> u32 {
-        let mut cleared_rows = 0;
-        let mut new_grid = [[false; GRID_WIDTH]; GRID_HEIGHT];
-        let mut new_row = GRID_HEIGHT - 1;
-        for row in self.grid.iter().rev() {
-            if row.iter().all(|&cell| cell) {
-                cleared_rows += 1;
-            } else {
-                new_grid[new_row] = *row;
-                new_row = new_row.saturating_sub(1);
+        let full = self.full_row();
+        // Move the rows that stay down over the cleared ones, from the bottom up, then open up
+        // empty rows above them.
+        let mut kept_top = self.total_height();
+        for y in (0..self.total_height()).rev() {
+            if self.rows[y] != full {
+                kept_top -= 1;
+                self.rows[kept_top] = self.rows[y];
+                self.garbage[kept_top] = self.garbage[y];
             }
         }
-        self.grid = new_grid;
-        cleared_rows
+        self.rows[..kept_top].fill(0);
+        self.garbage[..kept_top].fill(false);
+        u32::try_from(kept_top).unwrap_or(u32::MAX)
     }
 
     pub fn set_coords_iter(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
-        self.grid.iter().enumerate().flat_map(|(y, row)| {
-            row.iter().enumerate().filter_map(
-                move |(x, &cell)| {
-                    if cell {
-                        Some((x, y))
-                    } else {
-                        None
-                    }
-                },
-            )
+        self.rows.iter().enumerate().flat_map(|(y, &row)| {
+            (0..self.width)
+                .filter(move |&x| row >> x & 1 == 1)
+                .map(move |x| (x, y))
+        })
+    }
+}
+
+/// The mask of a row of cells, with bit `x` set when cell `x` is filled.
+fn row_mask(row: &[bool]) -> u64 {
+    row.iter()
+        .enumerate()
+        .filter(|(_, &cell)| cell)
+        .fold(0, |mask, (x, _)| mask | 1 << x)
+}
+
+/// How a `Grid` is saved: a row of cells at a time, independent of how it's stored.
+#[derive(Serialize, Deserialize)]
+struct SerializedGrid {
+    cells: Vec<Vec<bool>>,
+    garbage: Vec<bool>,
+    width: usize,
+    buffer: usize,
+}
+
+impl From<Grid> for SerializedGrid {
+    fn from(grid: Grid) -> Self {
+        let cells = (0..grid.total_height())
+            .map(|y| (0..grid.width).map(|x| grid.get(x, y)).collect())
+            .collect();
+        Self {
+            cells,
+            garbage: grid.garbage,
+            width: grid.width,
+            buffer: grid.buffer,
+        }
+    }
+}
+
+impl TryFrom<SerializedGrid> for Grid {
+    type Error = String;
+
+    fn try_from(saved: SerializedGrid) -> Result<Self, Self::Error> {
+        if saved.width > MAX_GRID_WIDTH {
+            return Err(format!(
+                "grids are at most {MAX_GRID_WIDTH} cells wide, not {}",
+                saved.width
+            ));
+        }
+        if saved.cells.iter().any(|row| row.len() != saved.width)
+            || saved.garbage.len() != saved.cells.len()
+            || saved.buffer > saved.cells.len()
+        {
+            return Err("grid rows don't match its size".to_string());
+        }
+        Ok(Grid {
+            rows: saved.cells.iter().map(|row| row_mask(row)).collect(),
+            garbage: saved.garbage,
+            width: saved.width,
+            buffer: saved.buffer,
+            pieces: Arc::from([]),
         })
     }
 }
 
 impl Default for Grid {
     fn default() -> Self {
-        Grid {
-            grid: [[false; GRID_WIDTH]; GRID_HEIGHT],
-        }
+        Grid::new(GridConfig::default())
     }
 }
 
 impl Display for Grid {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmtResult {
-        for row in &self.grid {
-            for cell in row {
-                write!(f, "{}", if *cell { "X" } else { "." })?;
+        for y in 0..self.total_height() {
+            if y == self.buffer {
+                writeln!(f, "{}", "-".repeat(self.width))?;
+            }
+            for x in 0..self.width {
+                write!(f, "{}", if self.get(x, y) { "X" } else { "." })?;
             }
             writeln!(f)?;
         }
@@ -251,6 +833,417 @@ impl Display for Grid {
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseGridError {
+    Empty,
+    TooFewRows {
+        min: usize,
+        found: usize,
+    },
+    TooManyRows {
+        max: usize,
+        found: usize,
+    },
+    /// Rows are wider than `MAX_GRID_WIDTH`.
+    TooWide {
+        max: usize,
+        found: usize,
+    },
+    RowWidth {
+        row: usize,
+        expected: usize,
+        found: usize,
+    },
+    UnknownCell {
+        row: usize,
+        column: usize,
+        found: char,
+    },
+    /// The active piece is written with more than one lowercase letter.
+    MixedActivePieces {
+        first: TetrominoType,
+        second: TetrominoType,
+    },
+    /// The active piece's cells don't form any rotation of its piece.
+    ActivePieceShape(TetrominoType),
+}
+
+impl Display for ParseGridError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmtResult {
+        match self {
+            ParseGridError::Empty => write!(f, "grid has no rows"),
+            ParseGridError::TooFewRows { min, found } => {
+                write!(f, "expected at least {min} rows, found {found}")
+            }
+            ParseGridError::TooManyRows { max, found } => {
+                write!(f, "expected at most {max} rows, found {found}")
+            }
+            ParseGridError::TooWide { max, found } => {
+                write!(f, "rows are {found} cells wide, at most {max} fit")
+            }
+            ParseGridError::RowWidth {
+                row,
+                expected,
+                found,
+            } => write!(f, "row {row} is {found} cells wide, expected {expected}"),
+            ParseGridError::UnknownCell { row, column, found } => {
+                write!(f, "unknown cell {found:?} at row {row}, column {column}")
+            }
+            ParseGridError::MixedActivePieces { first, second } => write!(
+                f,
+                "active piece is written as both {} and {}",
+                first.letter().to_ascii_lowercase(),
+                second.letter().to_ascii_lowercase()
+            ),
+            ParseGridError::ActivePieceShape(tetromino_type) => write!(
+                f,
+                "active cells don't form a {} piece",
+                tetromino_type.letter()
+            ),
+        }
+    }
+}
+
+impl Error for ParseGridError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Cell {
+    Empty,
+    Filled,
+    Active(TetrominoType),
+}
+
+/// Parses rows of cells, ignoring blank lines and surrounding whitespace. `.` is an empty cell,
+/// `X` or an uppercase piece letter a filled one and a lowercase piece letter a cell of the
+/// active piece.
+fn parse_rows(text: &str) -> Result<Vec<Vec<Cell>>, ParseGridError> {
+    let rows: Vec<&str> = text
+        .lines()
+        .map(str::trim)
+        .filter(|row| !row.is_empty())
+        .collect();
+    let width = rows.first().ok_or(ParseGridError::Empty)?.chars().count();
+    rows.iter()
+        .enumerate()
+        .map(|(y, row)| {
+            let cells = row
+                .chars()
+                .enumerate()
+                .map(|(x, cell)| match cell {
+                    '.' => Ok(Cell::Empty),
+                    'X' => Ok(Cell::Filled),
+                    found => match TetrominoType::from_letter(found.to_ascii_uppercase()) {
+                        Some(_) if found.is_ascii_uppercase() => Ok(Cell::Filled),
+                        Some(tetromino_type) => Ok(Cell::Active(tetromino_type)),
+                        None => Err(ParseGridError::UnknownCell {
+                            row: y,
+                            column: x,
+                            found,
+                        }),
+                    },
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            if cells.len() == width {
+                Ok(cells)
+            } else {
+                Err(ParseGridError::RowWidth {
+                    row: y,
+                    expected: width,
+                    found: cells.len(),
+                })
+            }
+        })
+        .collect()
+}
+
+/// The line of `-` that `Display` writes between the hidden buffer and the visible rows.
+fn is_buffer_separator(line: &str) -> bool {
+    line.chars().all(|c| c == '-')
+}
+
+/// Drops the `-` separator from grid notation, returning the remaining rows and how many of them
+/// were above it.
+fn split_buffer(text: &str) -> (String, Option<usize>) {
+    let lines: Vec<&str> = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect();
+    let separator = lines.iter().position(|line| is_buffer_separator(line));
+    let cells: Vec<&str> = lines
+        .into_iter()
+        .filter(|line| !is_buffer_separator(line))
+        .collect();
+    (cells.join("\n"), separator)
+}
+
+fn filled_rows(rows: &[Vec<Cell>]) -> Vec<Vec<bool>> {
+    rows.iter()
+        .map(|row| row.iter().map(|cell| *cell != Cell::Empty).collect())
+        .collect()
+}
+
+/// Finds the rotation of the active piece that covers exactly the active cells in `rows`.
+fn parse_active_piece(rows: &[Vec<Cell>]) -> Result<Option<ControlledTetromino>, ParseGridError> {
+    let mut active_type = None;
+    let mut coords = Vec::new();
+    for (y, row) in rows.iter().enumerate() {
+        for (x, cell) in row.iter().enumerate() {
+            if let Cell::Active(tetromino_type) = *cell {
+                match active_type {
+                    Some(first) if first != tetromino_type => {
+                        return Err(ParseGridError::MixedActivePieces {
+                            first,
+                            second: tetromino_type,
+                        });
+                    }
+                    _ => active_type = Some(tetromino_type),
+                }
+                coords.push((x, y));
+            }
+        }
+    }
+    let Some(tetromino_type) = active_type else {
+        return Ok(None);
+    };
+    let left = coords.iter().map(|(x, _)| *x).min().unwrap_or_default();
+    let top = coords.iter().map(|(_, y)| *y).min().unwrap_or_default();
+    tetromino_type
+        .rotations()
+        .iter()
+        .position(|shape| {
+            let mut piece: Vec<_> = shape.coords().map(|(x, y)| (x + left, y + top)).collect();
+            piece.sort_by_key(|(x, y)| (*y, *x));
+            piece == coords
+        })
+        .map(|rotation| {
+            Some(ControlledTetromino {
+                piece: PieceId::Classic(tetromino_type),
+                rotation,
+                top_left: (left, top),
+            })
+        })
+        .ok_or(ParseGridError::ActivePieceShape(tetromino_type))
+}
+
+/// A grid together with the piece falling through it, as written in bug reports and tests.
+///
+/// The text notation extends `Grid`'s `Display` output: uppercase piece letters are filled cells
+/// and the active piece is written with its lowercase letter, e.g. `..ttt.`. Grids only remember
+/// whether a cell is filled, so locked piece letters print back as `X`. The hidden buffer is
+/// written above a line of `-` and can be left out along with the line when it's the default
+/// empty one.
+#[derive(Debug, Clone)]
+pub struct GridSnapshot {
+    /// The grid, with the active piece set like it is during play.
+    pub grid: Grid,
+    pub active: Option<ControlledTetromino>,
+}
+
+impl FromStr for GridSnapshot {
+    type Err = ParseGridError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (cells, separator) = split_buffer(s);
+        let mut rows = parse_rows(&cells)?;
+        let width = rows[0].len();
+        if width > MAX_GRID_WIDTH {
+            return Err(ParseGridError::TooWide {
+                max: MAX_GRID_WIDTH,
+                found: width,
+            });
+        }
+        let buffer = separator.unwrap_or_else(|| {
+            rows.splice(
+                0..0,
+                std::iter::repeat_n(vec![Cell::Empty; width], DEFAULT_BUFFER_HEIGHT),
+            );
+            DEFAULT_BUFFER_HEIGHT
+        });
+        if rows.len() <= buffer {
+            return Err(ParseGridError::TooFewRows {
+                min: buffer + 1,
+                found: rows.len(),
+            });
+        }
+        let active = parse_active_piece(&rows)?;
+        let mut grid = Grid::new(GridConfig {
+            width,
+            height: rows.len() - buffer,
+            buffer,
+        });
+        grid.rows = filled_rows(&rows).iter().map(|row| row_mask(row)).collect();
+        Ok(GridSnapshot { grid, active })
+    }
+}
+
+impl TryFrom<&str> for GridSnapshot {
+    type Error = ParseGridError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl Display for GridSnapshot {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmtResult {
+        let active: Vec<_> = self
+            .active
+            .iter()
+            .flat_map(|active| active.coords(&self.grid))
+            .collect();
+        let letter = self.active.map_or('X', |active| {
+            self.grid.piece(active.piece).letter().to_ascii_lowercase()
+        });
+        for y in 0..self.grid.total_height() {
+            if y == self.grid.buffer_height() {
+                writeln!(f, "{}", "-".repeat(self.grid.width()))?;
+            }
+            for x in 0..self.grid.width() {
+                if active.contains(&(x, y)) {
+                    write!(f, "{letter}")?;
+                } else {
+                    write!(f, "{}", if self.grid.get(x, y) { "X" } else { "." })?;
+                }
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// The inverse of `Display`. Rows above a line of `-` are the hidden buffer; without one, every
+/// row is visible and an empty buffer of `DEFAULT_BUFFER_HEIGHT` rows is added above. Accepts the
+/// extended notation of `GridSnapshot`, with the active piece parsed as filled cells.
+impl FromStr for Grid {
+    type Err = ParseGridError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse::<GridSnapshot>().map(|snapshot| snapshot.grid)
+    }
+}
+
+impl TryFrom<&str> for Grid {
+    type Error = ParseGridError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+/// One rotation state of a piece: the rows of its bounding box from the top, each a bitmask with
+/// bit `x` set for column `x`. Binary literals therefore read right to left.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PieceShape {
+    pub width: usize,
+    pub rows: Cow<'static, [u64]>,
+}
+
+impl PieceShape {
+    #[must_use]
+    pub const fn new(width: usize, rows: &'static [u64]) -> Self {
+        Self {
+            width,
+            rows: Cow::Borrowed(rows),
+        }
+    }
+
+    #[must_use]
+    pub fn height(&self) -> usize {
+        self.rows.len()
+    }
+
+    /// The filled cells, relative to the top left of the bounding box, row by row.
+    pub fn coords(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.rows.iter().enumerate().flat_map(move |(y, &row)| {
+            (0..self.width)
+                .filter(move |x| row >> x & 1 == 1)
+                .map(move |x| (x, y))
+        })
+    }
+}
+
+static I_ROTATIONS: [PieceShape; 2] = [
+    PieceShape::new(4, &[0b1111]),
+    PieceShape::new(1, &[0b1, 0b1, 0b1, 0b1]),
+];
+static O_ROTATIONS: [PieceShape; 1] = [PieceShape::new(2, &[0b11, 0b11])];
+static T_ROTATIONS: [PieceShape; 4] = [
+    PieceShape::new(3, &[0b010, 0b111]),
+    PieceShape::new(2, &[0b01, 0b11, 0b01]),
+    PieceShape::new(3, &[0b111, 0b010]),
+    PieceShape::new(2, &[0b10, 0b11, 0b10]),
+];
+static S_ROTATIONS: [PieceShape; 2] = [
+    PieceShape::new(3, &[0b110, 0b011]),
+    PieceShape::new(2, &[0b01, 0b11, 0b10]),
+];
+static Z_ROTATIONS: [PieceShape; 2] = [
+    PieceShape::new(3, &[0b011, 0b110]),
+    PieceShape::new(2, &[0b10, 0b11, 0b01]),
+];
+static J_ROTATIONS: [PieceShape; 4] = [
+    PieceShape::new(3, &[0b001, 0b111]),
+    PieceShape::new(2, &[0b11, 0b01, 0b01]),
+    PieceShape::new(3, &[0b111, 0b100]),
+    PieceShape::new(2, &[0b10, 0b10, 0b11]),
+];
+static L_ROTATIONS: [PieceShape; 4] = [
+    PieceShape::new(3, &[0b100, 0b111]),
+    PieceShape::new(2, &[0b01, 0b01, 0b11]),
+    PieceShape::new(3, &[0b111, 0b001]),
+    PieceShape::new(2, &[0b11, 0b10, 0b10]),
+];
+
+/// The classic piece definitions, in `TetrominoType` order.
+static CLASSIC_PIECES: [PieceDef; 7] = [
+    PieceDef {
+        letter: 'I',
+        color: [0, 240, 240],
+        rotations: Cow::Borrowed(&I_ROTATIONS),
+        tetromino_type: Some(TetrominoType::I),
+    },
+    PieceDef {
+        letter: 'O',
+        color: [240, 240, 0],
+        rotations: Cow::Borrowed(&O_ROTATIONS),
+        tetromino_type: Some(TetrominoType::O),
+    },
+    PieceDef {
+        letter: 'T',
+        color: [170, 0, 255],
+        rotations: Cow::Borrowed(&T_ROTATIONS),
+        tetromino_type: Some(TetrominoType::T),
+    },
+    PieceDef {
+        letter: 'S',
+        color: [0, 240, 0],
+        rotations: Cow::Borrowed(&S_ROTATIONS),
+        tetromino_type: Some(TetrominoType::S),
+    },
+    PieceDef {
+        letter: 'Z',
+        color: [240, 0, 0],
+        rotations: Cow::Borrowed(&Z_ROTATIONS),
+        tetromino_type: Some(TetrominoType::Z),
+    },
+    PieceDef {
+        letter: 'J',
+        color: [0, 80, 240],
+        rotations: Cow::Borrowed(&J_ROTATIONS),
+        tetromino_type: Some(TetrominoType::J),
+    },
+    PieceDef {
+        letter: 'L',
+        color: [240, 160, 0],
+        rotations: Cow::Borrowed(&L_ROTATIONS),
+        tetromino_type: Some(TetrominoType::L),
+    },
+];
+
+/// The seven tetrominoes of the classic piece set, which puzzles and grid snapshots are written
+/// with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum TetrominoType {
     I,
     O,
@@ -262,54 +1255,52 @@ pub enum TetrominoType {
 }
 
 impl TetrominoType {
-    pub fn structure_with_rotations(&self) -> Vec<Vec<Vec<bool>>> {
+    pub const ALL: [TetrominoType; 7] = [
+        TetrominoType::I,
+        TetrominoType::O,
+        TetrominoType::T,
+        TetrominoType::S,
+        TetrominoType::Z,
+        TetrominoType::J,
+        TetrominoType::L,
+    ];
+
+    /// The uppercase letter the piece is named after.
+    #[must_use]
+    pub fn letter(self) -> char {
         match self {
-            TetrominoType::I => vec![
-                vec![vec![true, true, true, true]],
-                vec![vec![true], vec![true], vec![true], vec![true]],
-            ],
-            TetrominoType::O => vec![vec![vec![true, true], vec![true, true]]],
-            TetrominoType::T => vec![
-                vec![vec![false, true, false], vec![true, true, true]],
-                vec![vec![true, false], vec![true, true], vec![true, false]],
-                vec![vec![true, true, true], vec![false, true, false]],
-                vec![vec![false, true], vec![true, true], vec![false, true]],
-            ],
-            TetrominoType::S => vec![
-                vec![vec![false, true, true], vec![true, true, false]],
-                vec![vec![true, false], vec![true, true], vec![false, true]],
-            ],
-            TetrominoType::Z => vec![
-                vec![vec![true, true, false], vec![false, true, true]],
-                vec![vec![false, true], vec![true, true], vec![true, false]],
-            ],
-            TetrominoType::J => vec![
-                vec![vec![true, false, false], vec![true, true, true]],
-                vec![vec![true, true], vec![true, false], vec![true, false]],
-                vec![vec![true, true, true], vec![false, false, true]],
-                vec![vec![false, true], vec![false, true], vec![true, true]],
-            ],
-            TetrominoType::L => vec![
-                vec![vec![false, false, true], vec![true, true, true]],
-                vec![vec![true, false], vec![true, false], vec![true, true]],
-                vec![vec![true, true, true], vec![true, false, false]],
-                vec![vec![true, true], vec![false, true], vec![false, true]],
-            ],
-        }
-    }
-
-    fn random(rng: &mut RandomSource) -> Self {
-        let idx = rng.next(0, 7);
-        match idx {
-            0 => TetrominoType::I,
-            1 => TetrominoType::O,
-            2 => TetrominoType::T,
-            3 => TetrominoType::S,
-            4 => TetrominoType::Z,
-            5 => TetrominoType::J,
-            _ => TetrominoType::L,
+            TetrominoType::I => 'I',
+            TetrominoType::O => 'O',
+            TetrominoType::T => 'T',
+            TetrominoType::S => 'S',
+            TetrominoType::Z => 'Z',
+            TetrominoType::J => 'J',
+            TetrominoType::L => 'L',
         }
     }
+
+    #[must_use]
+    pub fn from_letter(letter: char) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|tetromino_type| tetromino_type.letter() == letter)
+    }
+
+    #[must_use]
+    pub fn piece(self) -> Piece {
+        CLASSIC[self as usize].clone()
+    }
+
+    /// The piece's rotation states, in the order it turns through them.
+    #[must_use]
+    pub fn rotations(self) -> &'static [PieceShape] {
+        &CLASSIC_PIECES[self as usize].rotations
+    }
+
+    pub fn random(rng: &mut RandomSource) -> Self {
+        let len = u32::try_from(Self::ALL.len()).unwrap_or(u32::MAX);
+        Self::ALL[rng.next(0, len) as usize]
+    }
 }
 
 #[derive(Debug, Component)]
@@ -319,22 +1310,35 @@ pub struct Shadow;
 pub struct GridTetromino(Entity);
 
 impl GridTetromino {
+    #[must_use]
     pub fn new(grid: Entity) -> Self {
         Self(grid)
     }
 
+    #[must_use]
     pub fn get(&self) -> Entity {
         self.0
     }
 }
 
+/// The last input that actually moved the piece, used to tell spins from slides.
+#[derive(Debug, Default, Clone, Component, Serialize, Deserialize)]
+pub struct LastMove(pub Option<GridAction>);
+
 #[derive(Debug, Component)]
 pub struct TetrominoTimer(pub Timer);
 
 impl TetrominoTimer {
+    #[must_use]
     pub fn new() -> Self {
         Self(Timer::from_seconds(1.0, TimerMode::Repeating))
     }
+
+    /// A gravity timer that moves the piece down one row every `interval`.
+    #[must_use]
+    pub fn from_interval(interval: Duration) -> Self {
+        Self(Timer::new(interval, TimerMode::Repeating))
+    }
 }
 
 impl Default for TetrominoTimer {
@@ -343,47 +1347,81 @@ impl Default for TetrominoTimer {
     }
 }
 
-#[derive(Debug, Clone, Component)]
+/// The classic pieces, in `TetrominoType` order.
+static CLASSIC: LazyLock<[Piece; 7]> =
+    LazyLock::new(|| CLASSIC_PIECES.each_ref().map(|def| Piece::new(def.clone())));
+
+/// Which kind of piece a piece in play is: one of the classic pieces, or an index into the set
+/// its grid deals from. `Grid::piece` looks up the piece it stands for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PieceId {
+    Classic(TetrominoType),
+    Set(usize),
+}
+
+/// A piece in play. It refers to its kind by `PieceId` and looks up its rotation states through
+/// the grid it's on, so it's `Copy` for searching placements or replaying inputs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Component, Serialize, Deserialize)]
 pub struct ControlledTetromino {
-    pub structure: Vec<Vec<Vec<bool>>>,
+    pub piece: PieceId,
     pub rotation: usize,
     pub top_left: (usize, usize),
 }
 
 impl ControlledTetromino {
-    pub fn new(rng: &mut RandomSource) -> Self {
-        Self::new_with_tetromino_type(TetrominoType::random(rng))
+    pub fn new(rng: &mut RandomSource, grid: &Grid) -> Self {
+        Self::new_with_tetromino_type(TetrominoType::random(rng), grid)
+    }
+
+    #[must_use]
+    pub fn new_with_tetromino_type(tetromino_type: TetrominoType, grid: &Grid) -> Self {
+        Self::new_with_id(PieceId::Classic(tetromino_type), grid)
+    }
+
+    /// Like `new_with_id`, or `None` if `piece` isn't classic or in `grid`'s set.
+    #[must_use]
+    pub fn new_with_piece(piece: &Piece, grid: &Grid) -> Option<Self> {
+        Some(Self::new_with_id(grid.piece_id(piece)?, grid))
     }
 
-    pub fn new_with_tetromino_type(tetromino_type: TetrominoType) -> Self {
+    /// Spawns the piece in the hidden buffer, centered on `grid` and resting directly above the
+    /// visible field.
+    #[must_use]
+    pub fn new_with_id(piece: PieceId, grid: &Grid) -> Self {
+        let spawn = &grid.piece(piece).rotations()[0];
+        let piece_width = spawn.width;
+        let piece_height = spawn.height();
+        let x = (grid.width() / 2)
+            .saturating_sub(1)
+            .min(grid.width().saturating_sub(piece_width));
         Self {
-            structure: tetromino_type.structure_with_rotations(),
+            piece,
             rotation: 0,
-            top_left: ((GRID_WIDTH / 2) - 1, 0),
+            top_left: (x, grid.buffer_height().saturating_sub(piece_height)),
         }
     }
 
-    pub fn current_structure(&self) -> &Vec<Vec<bool>> {
-        &self.structure[self.rotation]
+    /// The current rotation state.
+    #[must_use]
+    pub fn shape<'a>(&self, grid: &'a Grid) -> &'a PieceShape {
+        &grid.piece(self.piece).rotations()[self.rotation]
     }
 
-    pub fn rotate(&mut self) {
-        self.rotation = (self.rotation + 1) % self.structure.len();
+    /// Each row of the current rotation as a bitmask, with bit `x` set for column `x` of the
+    /// piece.
+    pub fn row_masks<'a>(&self, grid: &'a Grid) -> impl Iterator<Item = u64> + 'a {
+        self.shape(grid).rows.iter().copied()
     }
 
-    pub fn coords(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
-        self.structure[self.rotation]
-            .iter()
-            .enumerate()
-            .flat_map(move |(y, row)| {
-                row.iter().enumerate().filter_map(move |(x, &cell)| {
-                    if cell {
-                        Some((x + self.top_left.0, y + self.top_left.1))
-                    } else {
-                        None
-                    }
-                })
-            })
+    pub fn rotate(&mut self, grid: &Grid) {
+        self.rotation = (self.rotation + 1) % grid.piece(self.piece).rotations().len();
+    }
+
+    pub fn coords<'a>(&self, grid: &'a Grid) -> impl Iterator<Item = (usize, usize)> + 'a {
+        let (left, top) = self.top_left;
+        self.shape(grid)
+            .coords()
+            .map(move |(x, y)| (x + left, y + top))
     }
 }
 
@@ -396,24 +1434,344 @@ mod test {
     fn test_grid_is_space_open() {
         let mut grid = Grid::default();
         let tetromino = ControlledTetromino {
-            structure: vec![vec![vec![true]]],
+            piece: PieceId::Classic(TetrominoType::O),
             rotation: 0,
             top_left: (0, 0),
         };
         assert!(grid.is_tetromino_space_open(&tetromino));
-        grid.set(0, 0, true);
+        grid.set(1, 1, true);
         assert!(!grid.is_tetromino_space_open(&tetromino));
     }
 
     #[test]
     fn test_grid_clear_full_grid_rows() {
         let mut grid = Grid::default();
-        for i in 0..GRID_WIDTH {
+        for i in 0..grid.width() {
             grid.set(i, 0, true);
         }
         assert_eq!(grid.clear_full_grid_rows(), 1);
-        for i in 0..GRID_WIDTH {
-            assert!(!grid.grid[0][i]);
+        for i in 0..grid.width() {
+            assert!(!grid.get(i, 0));
+        }
+    }
+
+    #[test]
+    fn test_grid_garbage_rows() {
+        let mut grid = Grid::default();
+        let bottom = grid.total_height() - 1;
+        grid.add_garbage_row(&[3]);
+        grid.add_garbage_row(&[0, 5]);
+        assert_eq!(grid.garbage_rows(), 2);
+        assert!(grid.is_garbage_row(bottom) && grid.is_garbage_row(bottom - 1));
+        assert!(!grid.get(3, bottom - 1) && grid.get(0, bottom - 1));
+        assert!(!grid.get(0, bottom) && !grid.get(5, bottom) && grid.get(3, bottom));
+
+        // Filling the hole clears the older garbage row and leaves the other in place.
+        grid.set(3, bottom - 1, true);
+        assert_eq!(grid.clear_full_grid_rows(), 1);
+        assert_eq!(grid.garbage_rows(), 1);
+        assert!(grid.is_garbage_row(bottom) && !grid.is_garbage_row(bottom - 1));
+        assert!(!grid.get(0, bottom));
+
+        let mut rng = RandomSource::from_seed(3);
+        grid.add_random_garbage(4, &mut rng);
+        assert_eq!(grid.garbage_rows(), 5);
+        assert!((bottom - 4..=bottom).all(|y| (0..grid.width()).any(|x| !grid.get(x, y))));
+    }
+
+    #[test]
+    fn test_rotation_tables() {
+        for tetromino_type in TetrominoType::ALL {
+            for shape in tetromino_type.rotations() {
+                assert_eq!(shape.coords().count(), 4, "{tetromino_type:?}");
+                // The bounding box is tight: every row and column has a cell.
+                assert!(shape
+                    .rows
+                    .iter()
+                    .all(|&row| row != 0 && row >> shape.width == 0));
+                let columns = shape.rows.iter().fold(0, |mask, row| mask | row);
+                assert_eq!(columns, (1 << shape.width) - 1, "{tetromino_type:?}");
+            }
+        }
+        let grid = Grid::default();
+        let mut tetromino = ControlledTetromino::new_with_tetromino_type(TetrominoType::J, &grid);
+        let spawn = tetromino;
+        tetromino.rotate(&grid);
+        assert_eq!(tetromino.coords(&grid).count(), 4);
+        assert_ne!(tetromino, spawn);
+        for _ in 1..4 {
+            tetromino.rotate(&grid);
+        }
+        assert_eq!(tetromino, spawn);
+    }
+
+    #[test]
+    fn test_grid_bitboard_collisions() {
+        // The piece is set in the grid, as it is during play, and mustn't block itself.
+        let mut grid = Grid::with_visible_rows(GridConfig::new(4, 4), ".t..\nttt.\nX..X").unwrap();
+        let top = grid.buffer_height() + 1;
+        let mut tetromino = ControlledTetromino {
+            piece: PieceId::Classic(TetrominoType::T),
+            rotation: 0,
+            top_left: (0, top),
+        };
+        assert!(grid.is_tetromino_blocked_left(&tetromino));
+        assert!(!grid.is_tetromino_blocked_right(&tetromino));
+        assert!(grid.is_tetromino_at_bottom(&tetromino));
+
+        grid.unset_tetromino(&tetromino);
+        tetromino.top_left.0 = 1;
+        assert!(grid.is_tetromino_space_open(&tetromino));
+        grid.set_tetromino(&tetromino);
+        assert!(!grid.is_tetromino_blocked_left(&tetromino));
+        assert!(grid.is_tetromino_blocked_right(&tetromino));
+        assert!(grid.is_tetromino_at_bottom(&tetromino));
+        tetromino.top_left.0 = 2;
+        assert!(!grid.is_tetromino_space_open(&tetromino));
+
+        let mut wide = Grid::with_visible_rows(
+            GridConfig::new(MAX_GRID_WIDTH, 2),
+            &format!("{}.\n{}", "X".repeat(63), "X".repeat(64)),
+        )
+        .unwrap();
+        let bottom = wide.total_height() - 1;
+        assert_eq!(wide.full_rows(), [bottom]);
+        assert_eq!(wide.clear_full_grid_rows(), 1);
+        assert!(wide.get(62, bottom) && !wide.get(63, bottom));
+        assert_eq!(
+            format!("{}\n", "X".repeat(65))
+                .repeat(21)
+                .parse::<Grid>()
+                .unwrap_err(),
+            ParseGridError::TooWide {
+                max: MAX_GRID_WIDTH,
+                found: 65
+            }
+        );
+    }
+
+    #[test]
+    fn test_grid_parse_round_trip() {
+        let mut grid = Grid::default();
+        grid.set(0, grid.total_height() - 1, true);
+        grid.set(3, grid.total_height() - 2, true);
+        let parsed: Grid = grid.to_string().parse().unwrap();
+        assert_eq!(parsed.to_string(), grid.to_string());
+        assert_eq!(parsed.config(), grid.config());
+
+        assert_eq!("".parse::<Grid>().unwrap_err(), ParseGridError::Empty);
+        assert_eq!(
+            "X.\n.?".parse::<Grid>().unwrap_err(),
+            ParseGridError::UnknownCell {
+                row: 1,
+                column: 1,
+                found: '?'
+            }
+        );
+        assert_eq!(
+            Grid::with_visible_rows(GridConfig::default(), "....\nXX.X").unwrap_err(),
+            ParseGridError::RowWidth {
+                row: 0,
+                expected: 10,
+                found: 4
+            }
+        );
+
+        let grid = Grid::with_visible_rows(GridConfig::new(4, 6), "X...\nXX.X").unwrap();
+        let bottom = grid.total_height() - 1;
+        assert!(grid.get(0, bottom - 1) && !grid.get(1, bottom - 1));
+        assert_eq!(grid.set_coords_iter().count(), 4);
+    }
+
+    #[test]
+    fn test_grid_snapshot_notation() {
+        let buffer = "....\n".repeat(DEFAULT_BUFFER_HEIGHT) + "----\n";
+        let snapshot =
+            GridSnapshot::try_from(format!("{buffer}.t..\nttt.\nIIJ.").as_str()).unwrap();
+        let active = snapshot.active.as_ref().unwrap();
+        assert_eq!(active.piece, PieceId::Classic(TetrominoType::T));
+        assert_eq!(active.rotation, 0);
+        assert_eq!(active.top_left, (0, DEFAULT_BUFFER_HEIGHT));
+        // The active piece is set on the grid and locked letters are plain filled cells.
+        assert_eq!(snapshot.grid.set_coords_iter().count(), 7);
+        assert_eq!(snapshot.to_string(), format!("{buffer}.t..\nttt.\nXXX.\n"));
+        let grid = Grid::try_from(format!("{buffer}.t..\nttt.\nIIJ.").as_str()).unwrap();
+        assert_eq!(grid.to_string(), format!("{buffer}.X..\nXXX.\nXXX.\n"));
+
+        assert_eq!(
+            format!("{buffer}.t..\ntts.")
+                .parse::<GridSnapshot>()
+                .unwrap_err(),
+            ParseGridError::MixedActivePieces {
+                first: TetrominoType::T,
+                second: TetrominoType::S
+            }
+        );
+        assert_eq!(
+            format!("{buffer}.t..\ntt.t")
+                .parse::<GridSnapshot>()
+                .unwrap_err(),
+            ParseGridError::ActivePieceShape(TetrominoType::T)
+        );
+        assert_eq!(
+            buffer.parse::<Grid>().unwrap_err(),
+            ParseGridError::TooFewRows {
+                min: DEFAULT_BUFFER_HEIGHT + 1,
+                found: DEFAULT_BUFFER_HEIGHT
+            }
+        );
+
+        // Without the buffer, the rows are all visible under an empty default buffer.
+        let snapshot = GridSnapshot::try_from(".t..\nttt.\nIIJ.").unwrap();
+        assert_eq!(snapshot.grid.config(), GridConfig::new(4, 3));
+        let active = snapshot.active.as_ref().unwrap();
+        assert_eq!(active.top_left, (0, DEFAULT_BUFFER_HEIGHT));
+        assert_eq!(snapshot.to_string(), format!("{buffer}.t..\nttt.\nXXX.\n"));
+    }
+
+    #[test]
+    fn test_grid_snapshot_buffer_height() {
+        let config = GridConfig {
+            width: 4,
+            height: 3,
+            buffer: 2,
+        };
+        let mut grid = Grid::new(config);
+        let tetromino = grid.spawn_tetromino_type(TetrominoType::O).unwrap();
+        grid.set(3, grid.total_height() - 1, true);
+        let snapshot = GridSnapshot {
+            grid,
+            active: Some(tetromino),
+        };
+        let text = snapshot.to_string();
+        assert_eq!(text, "....\n.oo.\n----\n.oo.\n....\n...X\n");
+
+        let parsed: GridSnapshot = text.parse().unwrap();
+        assert_eq!(parsed.grid.config(), config);
+        assert_eq!(parsed.active, snapshot.active);
+        assert_eq!(parsed.to_string(), text);
+        let grid: Grid = "...X\n----\n....".parse().unwrap();
+        assert_eq!(grid.buffer_height(), 1);
+        assert!(grid.get(3, 0));
+    }
+
+    #[test]
+    fn test_grid_phase_after_lock() {
+        let delays = Delays::default();
+        let grid =
+            Grid::with_visible_rows(GridConfig::new(4, 4), "X...\nXXXX\nXX.X\nXXXX").unwrap();
+        let bottom = grid.total_height() - 1;
+        assert_eq!(grid.full_rows(), [bottom - 2, bottom]);
+        let GridPhase::Clearing { rows, t_spin, .. } = GridPhase::after_lock(&grid, true, &delays)
+        else {
+            panic!("expected the full rows to clear");
+        };
+        assert_eq!(rows, [bottom - 2, bottom]);
+        assert!(t_spin);
+
+        let grid = Grid::with_visible_rows(GridConfig::new(4, 4), "XX.X").unwrap();
+        let phase = GridPhase::after_lock(&grid, false, &delays);
+        assert!(matches!(phase, GridPhase::Spawning { .. }));
+        assert!(phase.is_between_pieces());
+        assert_eq!(delays.entry(), Duration::from_millis(100));
+
+        let GridPhase::Locking { timer } = GridPhase::locking(&delays) else {
+            panic!("expected a lock delay");
+        };
+        assert_eq!(timer.duration(), Duration::from_millis(500));
+        assert!(!GridPhase::locking(&delays).is_between_pieces());
+    }
+
+    #[test]
+    fn test_delays_fill_in_defaults() {
+        let delays: Delays = ron::from_str("(lock_frames: 15)").unwrap();
+        assert_eq!(
+            delays,
+            Delays {
+                lock_frames: 15,
+                ..Delays::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_grid_t_spin_position() {
+        let grid = Grid::with_visible_rows(GridConfig::new(4, 3), "X...\n...X\nX.XX").unwrap();
+        let bottom = grid.total_height() - 1;
+        let mut tetromino = ControlledTetromino::new_with_tetromino_type(TetrominoType::T, &grid);
+        // Pointing down into the slot, with three filled corners around its center.
+        tetromino.rotation = 2;
+        tetromino.top_left = (0, bottom - 1);
+        assert!(grid.is_t_spin_position(&tetromino));
+
+        tetromino.top_left = (1, bottom - 2);
+        assert!(!grid.is_t_spin_position(&tetromino));
+
+        let mut other = ControlledTetromino::new_with_tetromino_type(TetrominoType::L, &grid);
+        other.top_left = (0, bottom - 1);
+        assert!(!grid.is_t_spin_position(&other));
+    }
+
+    #[test]
+    fn test_grid_rotation_kick() {
+        let grid = Grid::with_visible_rows(GridConfig::new(5, 3), "X..X.\n...XX\nX.XXX").unwrap();
+        let bottom = grid.total_height() - 1;
+        let mut tetromino = ControlledTetromino::new_with_tetromino_type(TetrominoType::T, &grid);
+        // Pointing right, resting with its stem in the slot.
+        tetromino.rotation = 1;
+        tetromino.top_left = (1, bottom - 2);
+        assert!(grid.is_tetromino_space_open(&tetromino));
+        assert!(grid.is_tetromino_at_bottom(&tetromino));
+
+        // Pointing down doesn't fit where it is, so it's kicked down and left into the slot.
+        let rotated = grid.rotated(&tetromino).unwrap();
+        assert_eq!(rotated.rotation, 2);
+        assert_eq!(rotated.top_left, (0, bottom - 1));
+        assert!(grid.is_t_spin_position(&rotated));
+
+        // Where it fits in place, it doesn't move.
+        tetromino.top_left = (1, 0);
+        assert_eq!(grid.rotated(&tetromino).unwrap().top_left, (1, 0));
+    }
+
+    #[test]
+    fn test_grid_custom_dimensions() {
+        let grid = Grid::new(GridConfig {
+            width: 6,
+            height: 20,
+            buffer: 3,
+        });
+        assert_eq!(grid.width(), 6);
+        assert_eq!(grid.height(), 20);
+        assert_eq!(grid.buffer_height(), 3);
+        assert_eq!(grid.total_height(), 23);
+
+        let tetromino = ControlledTetromino::new_with_tetromino_type(TetrominoType::I, &grid);
+        assert_eq!(tetromino.top_left, (2, 2));
+        assert!(grid.is_tetromino_space_open(&tetromino));
+        assert!(tetromino
+            .coords(&grid)
+            .all(|(_, y)| !grid.is_visible_row(y)));
+    }
+
+    #[test]
+    fn test_grid_lock_out() {
+        let mut grid = Grid::default();
+        let buffer = grid.buffer_height();
+        let mut tetromino = ControlledTetromino::new_with_tetromino_type(TetrominoType::O, &grid);
+        assert_eq!(tetromino.top_left.1, buffer - 2);
+        assert!(grid.is_tetromino_above_visible(&tetromino));
+
+        tetromino.top_left.1 += 1;
+        assert!(!grid.is_tetromino_above_visible(&tetromino));
+
+        // A stack reaching the top of the visible field leaves the piece resting in the buffer.
+        for y in buffer..grid.total_height() {
+            grid.set(tetromino.top_left.0, y, true);
         }
+        tetromino.top_left.1 = 0;
+        grid.force_tetromino_to_bottom(&mut tetromino);
+        assert_eq!(tetromino.top_left.1, buffer - 2);
+        assert!(grid.is_tetromino_above_visible(&tetromino));
     }
 }
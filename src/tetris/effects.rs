@@ -0,0 +1,511 @@
+use crate::tetris::bot::Bot;
+use crate::tetris::components::{DrawGrid, Grid};
+use crate::tetris::plugin::{cell_position, PauseState, CELL_SIZE};
+use crate::tetris::save::{read_ron, write_ron};
+use crate::tetris::theme::Theme;
+use bevy::prelude::*;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::io;
+
+/// File name of the effect settings inside the platform data directory.
+pub const EFFECTS_FILE: &str = "effects.ron";
+
+/// The localStorage key the effect settings are saved under on the web.
+pub const EFFECTS_STORAGE_KEY: &str = "twotris.effects";
+
+/// How long a particle lives before it has faded out completely.
+pub const PARTICLE_SECONDS: f32 = 0.6;
+/// How long a hard drop trail takes to fade.
+pub const TRAIL_SECONDS: f32 = 0.2;
+/// How long the screen shakes for.
+pub const SHAKE_SECONDS: f32 = 0.25;
+/// Downward pull on particles, in pixels per second squared.
+const PARTICLE_GRAVITY: f32 = 600.0;
+
+/// A visual effect that can be turned off.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Effect {
+    Particles,
+    ScreenShake,
+    Trails,
+    /// The falling piece gliding between cells and rotations.
+    SmoothPieces,
+}
+
+impl Effect {
+    /// The effects that add motion of their own, which `EffectSettings::toggle` switches
+    /// together. Smooth pieces only smooth out motion that's already there.
+    pub const MOTION: [Effect; 3] = [Effect::Particles, Effect::ScreenShake, Effect::Trails];
+}
+
+/// Which visual effects play and how strong they are. Each can be turned off, for players who
+/// find motion on screen uncomfortable.
+#[derive(Debug, Clone, PartialEq, Resource, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EffectSettings {
+    /// The effects that play.
+    pub enabled: BTreeSet<Effect>,
+    /// Particles burst from each cleared row.
+    pub particles_per_row: u32,
+    /// How far a tetris shakes the screen, in pixels. Hard drops shake it a third as far.
+    pub shake_strength: f32,
+}
+
+impl Default for EffectSettings {
+    fn default() -> Self {
+        Self {
+            enabled: Effect::MOTION
+                .into_iter()
+                .chain([Effect::SmoothPieces])
+                .collect(),
+            particles_per_row: 12,
+            shake_strength: 6.0,
+        }
+    }
+}
+
+impl EffectSettings {
+    #[must_use]
+    pub fn is_enabled(&self, effect: Effect) -> bool {
+        self.enabled.contains(&effect)
+    }
+
+    /// Turns `effect` off if it's on, otherwise turns it on.
+    pub fn flip(&mut self, effect: Effect) {
+        if !self.enabled.remove(&effect) {
+            self.enabled.insert(effect);
+        }
+    }
+
+    #[must_use]
+    pub fn any_enabled(&self) -> bool {
+        Effect::MOTION
+            .into_iter()
+            .any(|effect| self.is_enabled(effect))
+    }
+
+    /// Turns every effect that adds motion off if any are on, otherwise turns them all back on.
+    pub fn toggle(&mut self) {
+        if self.any_enabled() {
+            self.enabled
+                .retain(|effect| !Effect::MOTION.contains(effect));
+        } else {
+            self.enabled.extend(Effect::MOTION);
+        }
+    }
+
+    /// Particles to burst from a clear of `rows`. T-spins and tetrises burst twice as many per
+    /// row.
+    #[must_use]
+    pub fn burst_size(&self, rows: usize, t_spin: bool) -> usize {
+        if !self.is_enabled(Effect::Particles) {
+            return 0;
+        }
+        let per_row = self.particles_per_row as usize;
+        if t_spin || rows >= 4 {
+            rows * per_row * 2
+        } else {
+            rows * per_row
+        }
+    }
+
+    /// How far the screen shakes for an effect, or `None` if it doesn't shake.
+    #[must_use]
+    pub fn shake(&self, effect: &EffectEvent) -> Option<f32> {
+        if !self.is_enabled(Effect::ScreenShake) {
+            return None;
+        }
+        match effect {
+            EffectEvent::LinesCleared { rows, .. } if rows.len() >= 4 => Some(self.shake_strength),
+            EffectEvent::HardDrop { distance, .. } if *distance > 0 => {
+                Some(self.shake_strength / 3.0)
+            }
+            _ => None,
+        }
+    }
+
+    /// Reads the effect settings saved on this platform, the defaults if there are none yet.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the settings can't be read or aren't valid RON.
+    pub fn read() -> io::Result<Self> {
+        Ok(read_ron(EFFECTS_FILE, EFFECTS_STORAGE_KEY)?.unwrap_or_default())
+    }
+
+    /// Saves the effect settings for this platform.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the settings can't be written.
+    pub fn write(&self) -> io::Result<()> {
+        write_ron(EFFECTS_FILE, EFFECTS_STORAGE_KEY, self)
+    }
+}
+
+/// Something happened on a grid that's worth some visual feedback.
+#[derive(Debug, Clone, Event)]
+pub enum EffectEvent {
+    /// `rows` of the grid were cleared, by a T-spin if `t_spin`.
+    LinesCleared {
+        grid: Entity,
+        rows: Vec<usize>,
+        t_spin: bool,
+    },
+    /// A piece was hard dropped `distance` rows, landing on `cells`.
+    HardDrop {
+        grid: Entity,
+        cells: Vec<(usize, usize)>,
+        distance: usize,
+    },
+}
+
+impl EffectEvent {
+    #[must_use]
+    pub fn grid(&self) -> Entity {
+        match self {
+            EffectEvent::LinesCleared { grid, .. } | EffectEvent::HardDrop { grid, .. } => *grid,
+        }
+    }
+}
+
+/// A fading square thrown out of a cleared row.
+#[derive(Debug, Component)]
+pub struct Particle {
+    /// Pixels per second.
+    pub velocity: Vec2,
+    pub lifetime: Timer,
+}
+
+impl Particle {
+    #[must_use]
+    pub fn new(velocity: Vec2) -> Self {
+        Self {
+            velocity,
+            lifetime: Timer::from_seconds(PARTICLE_SECONDS, TimerMode::Once),
+        }
+    }
+}
+
+/// A streak left behind by a hard dropped piece, fading out.
+#[derive(Debug, Component)]
+pub struct Trail(pub Timer);
+
+impl Default for Trail {
+    fn default() -> Self {
+        Self(Timer::from_seconds(TRAIL_SECONDS, TimerMode::Once))
+    }
+}
+
+/// The camera's current shake, strongest when it starts and settling as its timer runs out.
+#[derive(Debug, Resource)]
+pub struct ScreenShake {
+    /// Furthest the camera moves from its resting place, in pixels.
+    pub strength: f32,
+    pub timer: Timer,
+}
+
+impl Default for ScreenShake {
+    fn default() -> Self {
+        let mut timer = Timer::from_seconds(SHAKE_SECONDS, TimerMode::Once);
+        timer.tick(timer.duration());
+        Self {
+            strength: 0.0,
+            timer,
+        }
+    }
+}
+
+impl ScreenShake {
+    /// Starts shaking by `strength` pixels, unless a stronger shake is already under way.
+    pub fn start(&mut self, strength: f32) {
+        if strength >= self.amplitude() {
+            self.strength = strength;
+            self.timer.reset();
+        }
+    }
+
+    /// How far the camera may move from its resting place right now.
+    #[must_use]
+    pub fn amplitude(&self) -> f32 {
+        self.strength * self.timer.fraction_remaining()
+    }
+}
+
+/// Particles, hard drop trails and screen shake, and the settings that turn them on and off.
+pub struct EffectsPlugin;
+
+impl Plugin for EffectsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<EffectSettings>()
+            .init_resource::<ScreenShake>()
+            .add_event::<EffectEvent>()
+            .add_systems(Startup, load_effect_settings)
+            .add_systems(
+                Update,
+                (
+                    toggle_effects,
+                    toggle_smooth_pieces,
+                    spawn_effects,
+                    animate_particles,
+                    fade_trails,
+                    shake_camera,
+                )
+                    .run_if(in_state(PauseState::Running)),
+            );
+    }
+}
+
+fn load_effect_settings(mut settings: ResMut<EffectSettings>) {
+    match EffectSettings::read() {
+        Ok(loaded) => *settings = loaded,
+        Err(e) => warn!("Failed to load effect settings: {}", e),
+    }
+}
+
+fn toggle_effects(input: Res<ButtonInput<KeyCode>>, mut settings: ResMut<EffectSettings>) {
+    if input.just_pressed(KeyCode::KeyV) {
+        settings.toggle();
+        debug!(
+            "Effects {}",
+            if settings.any_enabled() { "on" } else { "off" }
+        );
+        if let Err(e) = settings.write() {
+            warn!("Failed to save effect settings: {}", e);
+        }
+    }
+}
+
+fn toggle_smooth_pieces(
+    input: Res<ButtonInput<KeyCode>>,
+    mut settings: ResMut<EffectSettings>,
+    grids: Query<Entity, With<Grid>>,
+    mut draw_grid: EventWriter<DrawGrid>,
+) {
+    if input.just_pressed(KeyCode::KeyI) {
+        settings.flip(Effect::SmoothPieces);
+        if let Err(e) = settings.write() {
+            warn!("Failed to save effect settings: {}", e);
+        }
+        for entity in &grids {
+            draw_grid.send(DrawGrid(entity));
+        }
+    }
+}
+
+/// Throws `count` particles out of the cleared `rows`.
+fn spawn_burst(
+    commands: &mut Commands,
+    grid: &Grid,
+    theme: &Theme,
+    grid_transform: &Transform,
+    rows: &[usize],
+    count: usize,
+) {
+    let mut rng = rand::thread_rng();
+    for i in 0..count {
+        let x = rng.gen_range(0.0..grid.width() as f32) * CELL_SIZE;
+        let position = cell_position(grid, (0, rows[i % rows.len()])) + Vec2::new(x, 0.0);
+        let velocity = Vec2::new(rng.gen_range(-150.0..150.0), rng.gen_range(50.0..300.0));
+        commands.spawn((
+            Particle::new(velocity * grid_transform.scale.truncate()),
+            SpriteBundle {
+                transform: Transform::from_translation(
+                    grid_transform.transform_point(position.extend(5.0)),
+                )
+                .with_scale(grid_transform.scale),
+                sprite: Sprite {
+                    color: theme.palette.particle.into(),
+                    custom_size: Some(Vec2::splat(CELL_SIZE / 4.0)),
+                    ..default()
+                },
+                ..default()
+            },
+        ));
+    }
+}
+
+/// Leaves a streak as long as the drop above each column of a hard dropped piece.
+fn spawn_trail(
+    commands: &mut Commands,
+    grid: &Grid,
+    theme: &Theme,
+    grid_transform: &Transform,
+    cells: &[(usize, usize)],
+    distance: usize,
+) {
+    let height = distance as f32 * CELL_SIZE;
+    // Only the topmost cell of each column starts a streak.
+    let tops = cells
+        .iter()
+        .filter(|&&(x, y)| !cells.iter().any(|&(ox, oy)| ox == x && oy < y));
+    for &(x, y) in tops {
+        let center = cell_position(grid, (x, y)) + Vec2::new(0.0, f32::midpoint(height, CELL_SIZE));
+        commands.spawn((
+            Trail::default(),
+            SpriteBundle {
+                transform: Transform::from_translation(
+                    grid_transform.transform_point(center.extend(1.0)),
+                )
+                .with_scale(grid_transform.scale),
+                sprite: Sprite {
+                    color: theme.palette.trail.into(),
+                    custom_size: Some(Vec2::new(theme.cell_sprite_size(CELL_SIZE), height)),
+                    ..default()
+                },
+                ..default()
+            },
+        ));
+    }
+}
+
+fn spawn_effects(
+    mut commands: Commands,
+    settings: Res<EffectSettings>,
+    theme: Res<Theme>,
+    mut effects: EventReader<EffectEvent>,
+    mut shake: ResMut<ScreenShake>,
+    grids: Query<(&Grid, &Transform, Has<Bot>)>,
+) {
+    for effect in effects.read() {
+        let Ok((grid, transform, is_bot)) = grids.get(effect.grid()) else {
+            continue;
+        };
+        // A bot drops pieces far too often for the screen to shake every time.
+        if let Some(strength) = settings.shake(effect).filter(|_| !is_bot) {
+            shake.start(strength);
+        }
+        match effect {
+            EffectEvent::LinesCleared { rows, t_spin, .. } => {
+                let count = settings.burst_size(rows.len(), *t_spin);
+                if count > 0 {
+                    spawn_burst(&mut commands, grid, &theme, transform, rows, count);
+                }
+            }
+            EffectEvent::HardDrop {
+                cells, distance, ..
+            } => {
+                if settings.is_enabled(Effect::Trails) && *distance > 0 {
+                    spawn_trail(&mut commands, grid, &theme, transform, cells, *distance);
+                }
+            }
+        }
+    }
+}
+
+fn animate_particles(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut particles: Query<(Entity, &mut Particle, &mut Transform, &mut Sprite)>,
+) {
+    let delta = time.delta_seconds();
+    for (entity, mut particle, mut transform, mut sprite) in &mut particles {
+        if particle.lifetime.tick(time.delta()).finished() {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        particle.velocity.y -= PARTICLE_GRAVITY * delta;
+        transform.translation += (particle.velocity * delta).extend(0.0);
+        sprite
+            .color
+            .set_alpha(particle.lifetime.fraction_remaining());
+    }
+}
+
+fn fade_trails(
+    mut commands: Commands,
+    time: Res<Time>,
+    theme: Res<Theme>,
+    mut trails: Query<(Entity, &mut Trail, &mut Sprite)>,
+) {
+    let alpha = Color::from(theme.palette.trail).alpha();
+    for (entity, mut trail, mut sprite) in &mut trails {
+        if trail.0.tick(time.delta()).finished() {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        sprite.color.set_alpha(alpha * trail.0.fraction_remaining());
+    }
+}
+
+fn shake_camera(
+    time: Res<Time>,
+    mut shake: ResMut<ScreenShake>,
+    mut camera: Query<&mut Transform, With<Camera>>,
+) {
+    shake.timer.tick(time.delta());
+    let amplitude = shake.amplitude();
+    let offset = if amplitude > 0.0 {
+        let mut rng = rand::thread_rng();
+        Vec2::new(
+            rng.gen_range(-1.0..=1.0) * amplitude,
+            rng.gen_range(-1.0..=1.0) * amplitude,
+        )
+    } else {
+        Vec2::ZERO
+    };
+    for mut transform in &mut camera {
+        transform.translation.x = offset.x;
+        transform.translation.y = offset.y;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_effect_settings() {
+        let mut settings = EffectSettings::default();
+        assert_eq!(settings.burst_size(1, false), 12);
+        assert_eq!(settings.burst_size(1, true), 24);
+        assert_eq!(settings.burst_size(4, false), 96);
+
+        let tetris = EffectEvent::LinesCleared {
+            grid: Entity::PLACEHOLDER,
+            rows: vec![16, 17, 18, 19],
+            t_spin: false,
+        };
+        let single = EffectEvent::LinesCleared {
+            grid: Entity::PLACEHOLDER,
+            rows: vec![19],
+            t_spin: false,
+        };
+        let drop = EffectEvent::HardDrop {
+            grid: Entity::PLACEHOLDER,
+            cells: vec![],
+            distance: 3,
+        };
+        assert_eq!(settings.shake(&tetris), Some(6.0));
+        assert_eq!(settings.shake(&single), None);
+        assert_eq!(settings.shake(&drop), Some(2.0));
+
+        settings.toggle();
+        assert!(!settings.any_enabled());
+        assert_eq!(settings.burst_size(4, false), 0);
+        assert_eq!(settings.shake(&tetris), None);
+        settings.toggle();
+        assert_eq!(settings, EffectSettings::default());
+
+        // Smooth pieces are left alone by the toggle.
+        settings.flip(Effect::SmoothPieces);
+        settings.toggle();
+        assert!(!settings.is_enabled(Effect::SmoothPieces));
+        assert!(!settings.any_enabled());
+    }
+
+    #[test]
+    fn test_screen_shake() {
+        let mut shake = ScreenShake::default();
+        assert!(shake.amplitude().abs() < f32::EPSILON);
+        shake.start(6.0);
+        assert!((shake.amplitude() - 6.0).abs() < f32::EPSILON);
+        // A weaker shake doesn't cut a stronger one short.
+        shake.start(2.0);
+        assert!((shake.amplitude() - 6.0).abs() < f32::EPSILON);
+        shake.timer.tick(shake.timer.duration() / 2);
+        assert!((shake.amplitude() - 3.0).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,164 @@
+use crate::tetris::bot::reachable_placements;
+use crate::tetris::components::{ControlledTetromino, Grid, GridAction};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter, Result as fmtResult};
+
+/// How long the "finesse fault" message stays on screen.
+pub const FAULT_FLASH_SECONDS: f32 = 0.75;
+
+/// Sent when a human-played piece locks with more inputs than it needed.
+#[derive(Debug, Clone, Event)]
+pub struct FinesseFaultEvent(pub Entity, pub Finesse);
+
+/// The running finesse statistics text.
+#[derive(Debug, Component)]
+pub struct FinesseText;
+
+/// The "finesse fault" message, hidden again once its timer runs out.
+#[derive(Debug, Component)]
+pub struct FinesseFlash(pub Timer);
+
+impl Default for FinesseFlash {
+    fn default() -> Self {
+        Self(Timer::from_seconds(FAULT_FLASH_SECONDS, TimerMode::Once))
+    }
+}
+
+/// Every input applied to a piece during its lifetime, along with where it spawned.
+#[derive(Debug, Clone, Component, Serialize, Deserialize)]
+pub struct InputLog {
+    pub spawn: ControlledTetromino,
+    pub actions: Vec<GridAction>,
+}
+
+impl InputLog {
+    #[must_use]
+    pub fn new(spawn: &ControlledTetromino) -> Self {
+        Self {
+            spawn: *spawn,
+            actions: vec![],
+        }
+    }
+
+    pub fn record(&mut self, action: GridAction) {
+        self.actions.push(action);
+    }
+}
+
+/// Finesse only counts moves and rotations; gravity and drops are free.
+fn is_counted(action: GridAction) -> bool {
+    !matches!(action, GridAction::SoftDrop | GridAction::HardDrop)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Finesse {
+    pub optimal: usize,
+    pub actual: usize,
+}
+
+impl Finesse {
+    #[must_use]
+    pub fn is_fault(&self) -> bool {
+        self.actual > self.optimal
+    }
+
+    #[must_use]
+    pub fn extra_inputs(&self) -> usize {
+        self.actual.saturating_sub(self.optimal)
+    }
+}
+
+/// Compares the inputs in `log` with the fewest that reach `locked` from the spawn position.
+/// `board` is the grid the piece locked into, without the piece. Returns `None` if the placement
+/// can't be reached from the spawn position.
+#[must_use]
+pub fn analyze(board: &Grid, log: &InputLog, locked: &ControlledTetromino) -> Option<Finesse> {
+    let optimal = reachable_placements(board, &log.spawn)
+        .into_iter()
+        .find(|placement| {
+            placement.tetromino.top_left == locked.top_left
+                && placement.tetromino.rotation == locked.rotation
+        })?
+        .actions
+        .into_iter()
+        .filter(|&action| is_counted(action))
+        .count();
+    let actual = log
+        .actions
+        .iter()
+        .filter(|&&action| is_counted(action))
+        .count();
+    Some(Finesse { optimal, actual })
+}
+
+/// Finesse results for human-played pieces since the game started.
+#[derive(Debug, Default, Clone, Resource, Serialize, Deserialize)]
+pub struct FinesseStats {
+    pub pieces: u32,
+    pub faults: u32,
+    pub extra_inputs: u32,
+}
+
+impl FinesseStats {
+    pub fn record(&mut self, finesse: Finesse) {
+        self.pieces += 1;
+        if finesse.is_fault() {
+            self.faults += 1;
+            self.extra_inputs += u32::try_from(finesse.extra_inputs()).unwrap_or(u32::MAX);
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+impl Display for FinesseStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmtResult {
+        write!(
+            f,
+            "Finesse faults: {}/{} ({} extra inputs)",
+            self.faults, self.pieces, self.extra_inputs
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tetris::components::TetrominoType;
+
+    #[test]
+    fn test_finesse_fault() {
+        let board = Grid::default();
+        let spawn = ControlledTetromino::new_with_tetromino_type(TetrominoType::O, &board);
+        let mut locked = board.controlled_tetromino_shadow(&spawn);
+        locked.top_left.0 -= 1;
+
+        let mut log = InputLog::new(&spawn);
+        log.record(GridAction::MoveLeft);
+        log.record(GridAction::HardDrop);
+        let finesse = analyze(&board, &log, &locked).unwrap();
+        assert_eq!(finesse.optimal, 1);
+        assert!(!finesse.is_fault());
+
+        // Wiggling back and forth reaches the same spot with extra inputs.
+        let mut log = InputLog::new(&spawn);
+        for action in [
+            GridAction::MoveLeft,
+            GridAction::MoveLeft,
+            GridAction::MoveRight,
+            GridAction::HardDrop,
+        ] {
+            log.record(action);
+        }
+        let finesse = analyze(&board, &log, &locked).unwrap();
+        assert!(finesse.is_fault());
+        assert_eq!(finesse.extra_inputs(), 2);
+
+        let mut stats = FinesseStats::default();
+        stats.record(finesse);
+        assert_eq!((stats.pieces, stats.faults, stats.extra_inputs), (1, 1, 2));
+    }
+}
@@ -0,0 +1,253 @@
+use crate::tetris::mode::{format_duration, GameMode};
+use crate::tetris::save::{read_ron, write_ron};
+use bevy::prelude::*;
+use bevy::utils::SystemTime;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::io;
+use std::time::Duration;
+
+/// Entries kept in each mode's table.
+pub const HIGH_SCORES_LEN: usize = 10;
+/// Longest name that can be entered for a high score.
+pub const MAX_NAME_LEN: usize = 12;
+
+/// File name of the high score tables inside the platform data directory.
+pub const HIGH_SCORES_FILE: &str = "high_scores.ron";
+
+/// The localStorage key the high score tables are saved under on the web.
+pub const HIGH_SCORES_STORAGE_KEY: &str = "twotris.high_scores";
+
+/// One finished game in a high score table.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HighScore {
+    pub name: String,
+    pub score: u32,
+    pub lines: u32,
+    pub level: u32,
+    pub duration: Duration,
+    /// Seed of the game's `RandomSource`.
+    pub seed: u64,
+    /// When the game was played, in seconds since the Unix epoch.
+    pub date: u64,
+}
+
+impl HighScore {
+    /// Seconds since the Unix epoch, for `date`.
+    #[must_use]
+    pub fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs())
+    }
+}
+
+/// Sprints and digs are a race to finish, so their tables are ordered by time and only take
+/// completed runs. Every other mode is ordered by score.
+fn is_race(mode: GameMode) -> bool {
+    matches!(mode, GameMode::Sprint { .. } | GameMode::Dig { .. })
+}
+
+/// Orders `a` before `b` if it ranks higher in `mode`'s table.
+fn compare(mode: GameMode, a: &HighScore, b: &HighScore) -> Ordering {
+    if is_race(mode) {
+        a.duration.cmp(&b.duration)
+    } else {
+        b.score
+            .cmp(&a.score)
+            .then(b.lines.cmp(&a.lines))
+            .then(a.duration.cmp(&b.duration))
+    }
+}
+
+/// The top games of each mode, best first.
+#[derive(Debug, Default, Clone, PartialEq, Resource, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HighScores {
+    pub tables: BTreeMap<GameMode, Vec<HighScore>>,
+}
+
+impl HighScores {
+    /// Whether a game of `mode` that ended this way can be entered at all. Puzzles aren't scored.
+    #[must_use]
+    pub fn is_eligible(mode: GameMode, completed: bool) -> bool {
+        !matches!(mode, GameMode::Puzzle { .. }) && (completed || !is_race(mode))
+    }
+
+    pub fn table(&self, mode: GameMode) -> &[HighScore] {
+        self.tables.get(&mode).map_or(&[], Vec::as_slice)
+    }
+
+    /// The zero-based position `entry` would take in `mode`'s table, or `None` if it doesn't
+    /// make the cut. Ties go to the earlier game.
+    #[must_use]
+    pub fn rank(&self, mode: GameMode, entry: &HighScore) -> Option<usize> {
+        let rank = self
+            .table(mode)
+            .partition_point(|existing| compare(mode, existing, entry) != Ordering::Greater);
+        (rank < HIGH_SCORES_LEN).then_some(rank)
+    }
+
+    /// Adds `entry` to `mode`'s table, returning its position if it made the cut.
+    pub fn insert(&mut self, mode: GameMode, entry: HighScore) -> Option<usize> {
+        let rank = self.rank(mode, &entry)?;
+        let table = self.tables.entry(mode).or_default();
+        table.insert(rank, entry);
+        table.truncate(HIGH_SCORES_LEN);
+        Some(rank)
+    }
+
+    /// `mode`'s table as text for the high score screen.
+    #[must_use]
+    pub fn format_table(&self, mode: GameMode) -> String {
+        let table = self.table(mode);
+        if table.is_empty() {
+            return "No high scores yet".to_string();
+        }
+        let mut text = format!(
+            "{:>2}  {:<width$}  {:>7}  {:>5}  {:>3}  {:>9}  {:<10}  {:<16}",
+            "#",
+            "Name",
+            "Score",
+            "Lines",
+            "Lv",
+            "Time",
+            "Date",
+            "Seed",
+            width = MAX_NAME_LEN
+        );
+        for (i, entry) in table.iter().enumerate() {
+            let _ = write!(
+                text,
+                "\n{:>2}  {:<width$}  {:>7}  {:>5}  {:>3}  {:>9}  {:<10}  {:016x}",
+                i + 1,
+                entry.name,
+                entry.score,
+                entry.lines,
+                entry.level,
+                format_duration(entry.duration),
+                format_date(entry.date),
+                entry.seed,
+                width = MAX_NAME_LEN
+            );
+        }
+        text
+    }
+
+    /// Reads the high score tables saved on this platform, empty if there are none yet.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the tables can't be read or aren't valid RON.
+    pub fn read() -> io::Result<Self> {
+        Ok(read_ron(HIGH_SCORES_FILE, HIGH_SCORES_STORAGE_KEY)?.unwrap_or_default())
+    }
+
+    /// Saves the high score tables for this platform.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the tables can't be written.
+    pub fn write(&self) -> io::Result<()> {
+        write_ron(HIGH_SCORES_FILE, HIGH_SCORES_STORAGE_KEY, self)
+    }
+}
+
+/// A finished game waiting for the player to enter their name.
+#[derive(Debug, Clone, Resource)]
+pub struct PendingHighScore {
+    pub mode: GameMode,
+    pub entry: HighScore,
+}
+
+/// The "new high score" prompt on the end screen.
+#[derive(Debug, Component)]
+pub struct HighScorePrompt;
+
+/// The high score screen, showing the table of `GameMode::ALL[self.0]`.
+#[derive(Debug, Component)]
+pub struct HighScoreTable(pub usize);
+
+/// Formats seconds since the Unix epoch as a UTC `YYYY-MM-DD` date.
+#[must_use]
+pub fn format_date(unix_seconds: u64) -> String {
+    // Days to civil date, from Howard Hinnant's `civil_from_days`.
+    let days = unix_seconds / 86_400 + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn entry(name: &str, score: u32, seconds: u64) -> HighScore {
+        HighScore {
+            name: name.to_string(),
+            score,
+            lines: 0,
+            level: 1,
+            duration: Duration::from_secs(seconds),
+            seed: 0,
+            date: 0,
+        }
+    }
+
+    #[test]
+    fn test_high_score_ranking() {
+        let marathon = GameMode::Marathon { endless: false };
+        let sprint = GameMode::Sprint { per_grid: false };
+        let mut high_scores = HighScores::default();
+        assert_eq!(high_scores.insert(marathon, entry("a", 100, 60)), Some(0));
+        assert_eq!(high_scores.insert(marathon, entry("b", 300, 60)), Some(0));
+        // Ties go to the earlier game.
+        assert_eq!(high_scores.insert(marathon, entry("c", 100, 60)), Some(2));
+        assert_eq!(high_scores.insert(sprint, entry("d", 0, 50)), Some(0));
+        assert_eq!(high_scores.insert(sprint, entry("e", 9000, 70)), Some(1));
+        assert_eq!(high_scores.table(sprint)[0].name, "d");
+
+        for i in 0..20 {
+            high_scores.insert(marathon, entry("f", 1000 + i, 60));
+        }
+        assert_eq!(high_scores.table(marathon).len(), HIGH_SCORES_LEN);
+        assert_eq!(high_scores.rank(marathon, &entry("g", 500, 60)), None);
+
+        assert!(HighScores::is_eligible(marathon, false));
+        assert!(!HighScores::is_eligible(sprint, false));
+        assert!(HighScores::is_eligible(sprint, true));
+        assert!(!HighScores::is_eligible(
+            GameMode::Puzzle { index: 0 },
+            true
+        ));
+    }
+
+    #[test]
+    fn test_high_scores_round_trip() {
+        let mut high_scores = HighScores::default();
+        high_scores.insert(GameMode::Ultra { minutes: 2 }, entry("a", 100, 120));
+        high_scores.insert(GameMode::Dig { rows: 10 }, entry("b", 0, 45));
+        let contents = ron::to_string(&high_scores).unwrap();
+        assert_eq!(ron::from_str::<HighScores>(&contents).unwrap(), high_scores);
+    }
+
+    #[test]
+    fn test_format_date() {
+        assert_eq!(format_date(0), "1970-01-01");
+        assert_eq!(format_date(951_782_400), "2000-02-29");
+        assert_eq!(format_date(1_790_208_000), "2026-09-24");
+    }
+}
@@ -0,0 +1,120 @@
+use crate::tetris::components::{ControlledTetromino, Grid};
+use bevy::prelude::*;
+use std::f32::consts::FRAC_PI_2;
+
+/// How quickly a smoothly drawn piece catches up with where it really is, per second. Higher is
+/// snappier.
+pub const SMOOTHING: f32 = 30.0;
+
+/// The falling piece drawn as its own sprites, gliding after the piece as it moves and turns
+/// instead of jumping from cell to cell. Positions are in cells, measured like grid coordinates:
+/// `x` to the right and `y` down from the top of the buffer.
+#[derive(Debug, Component)]
+pub struct PieceSprites {
+    /// The `ControlledTetromino` being drawn.
+    pub tetromino: Entity,
+    /// The rotation the cell sprites are laid out for.
+    pub rotation: usize,
+    /// The cell sprites, in the order of `ControlledTetromino::coords`.
+    pub cells: Vec<Entity>,
+    /// Where the center of the piece is drawn.
+    pub center: Vec2,
+    /// How far the sprites are still turned back towards the previous rotation, in radians.
+    pub angle: f32,
+}
+
+impl PieceSprites {
+    #[must_use]
+    pub fn new(
+        tetromino_id: Entity,
+        grid: &Grid,
+        tetromino: &ControlledTetromino,
+        cells: Vec<Entity>,
+    ) -> Self {
+        Self {
+            tetromino: tetromino_id,
+            rotation: tetromino.rotation,
+            cells,
+            center: piece_center(grid, tetromino),
+            angle: 0.0,
+        }
+    }
+
+    /// Starts turning towards `tetromino`'s new rotation from the old one, a quarter turn
+    /// clockwise.
+    pub fn rotate_to(&mut self, tetromino: &ControlledTetromino, cells: Vec<Entity>) {
+        self.rotation = tetromino.rotation;
+        self.cells = cells;
+        self.angle += FRAC_PI_2;
+    }
+
+    /// Moves the sprites `delta_seconds` closer to where `tetromino` is.
+    pub fn follow(&mut self, grid: &Grid, tetromino: &ControlledTetromino, delta_seconds: f32) {
+        let t = 1.0 - (-SMOOTHING * delta_seconds).exp();
+        self.center = self.center.lerp(piece_center(grid, tetromino), t);
+        self.angle *= 1.0 - t;
+    }
+}
+
+/// The middle of `tetromino`'s bounding box, in cells. Each rotation turns the piece about this
+/// point.
+#[must_use]
+pub fn piece_center(grid: &Grid, tetromino: &ControlledTetromino) -> Vec2 {
+    let shape = tetromino.shape(grid);
+    Vec2::new(
+        tetromino.top_left.0 as f32 + (shape.width as f32 - 1.0) / 2.0,
+        tetromino.top_left.1 as f32 + (shape.height() as f32 - 1.0) / 2.0,
+    )
+}
+
+/// Where each of `tetromino`'s cells sits relative to `piece_center`, in the order of
+/// `ControlledTetromino::coords`.
+#[must_use]
+pub fn cell_offsets(grid: &Grid, tetromino: &ControlledTetromino) -> Vec<Vec2> {
+    let center = piece_center(grid, tetromino);
+    tetromino
+        .coords(grid)
+        .map(|(x, y)| Vec2::new(x as f32, y as f32) - center)
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tetris::components::TetrominoType;
+
+    #[test]
+    fn test_piece_sprites_follow() {
+        let mut grid = Grid::default();
+        let mut tetromino = grid.spawn_tetromino_type(TetrominoType::T).unwrap();
+        let (x, y) = tetromino.top_left;
+        assert_eq!(
+            piece_center(&grid, &tetromino),
+            Vec2::new(x as f32 + 1.0, y as f32 + 0.5)
+        );
+        assert_eq!(
+            cell_offsets(&grid, &tetromino),
+            [
+                Vec2::new(0.0, -0.5),
+                Vec2::new(-1.0, 0.5),
+                Vec2::new(0.0, 0.5),
+                Vec2::new(1.0, 0.5)
+            ]
+        );
+
+        let mut sprites = PieceSprites::new(Entity::PLACEHOLDER, &grid, &tetromino, vec![]);
+        tetromino.top_left.1 += 1;
+        tetromino.rotate(&grid);
+        sprites.rotate_to(&tetromino, vec![]);
+        assert_eq!(sprites.rotation, 1);
+        sprites.follow(&grid, &tetromino, 1.0 / 60.0);
+        let target = piece_center(&grid, &tetromino);
+        assert!(sprites.center.y > y as f32 + 0.5 && sprites.center.y < target.y);
+        assert!(sprites.angle > 0.0 && sprites.angle < FRAC_PI_2);
+        for _ in 0..60 {
+            sprites.follow(&grid, &tetromino, 1.0 / 60.0);
+        }
+        assert!(sprites.center.distance(target) < 1e-3);
+        assert!(sprites.angle < 1e-3);
+    }
+}
@@ -0,0 +1,168 @@
+use crate::tetris::components::GridConfig;
+use bevy::prelude::*;
+
+/// Space kept clear around the edges of the window and between the grids and the HUD, in pixels.
+pub const MARGIN: f32 = 20.0;
+/// Width kept for the HUD beside the grids in landscape, in pixels.
+pub const HUD_WIDTH: f32 = 340.0;
+/// Height kept for the HUD below the grids in portrait, in pixels.
+pub const HUD_HEIGHT: f32 = 300.0;
+/// Room left above menus for the high score prompt, in pixels.
+pub const PROMPT_HEIGHT: f32 = 110.0;
+/// Gap between neighbouring grids, in cells.
+pub const GRID_GAP: f32 = 4.0;
+pub const MIN_CELL_SIZE: f32 = 6.0;
+pub const MAX_CELL_SIZE: f32 = 40.0;
+/// Window size laid out for before the real one is known.
+const DEFAULT_WINDOW: Vec2 = Vec2::new(1280.0, 720.0);
+
+/// UI nodes placed by the layout rather than at fixed positions.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayoutAnchor {
+    /// The score, mode and controls text beside or below the grids.
+    Hud,
+    /// Full screen panels and the high score prompt, over the top left of the grids.
+    Panel,
+    /// Menus, below the room kept for the high score prompt.
+    Menu,
+}
+
+/// Where the grids and HUD go in a window of a given size. Windows taller than they are wide are
+/// laid out in portrait, with the HUD below the grids instead of beside them.
+#[derive(Debug, Clone, PartialEq, Resource)]
+pub struct Layout {
+    /// Window size laid out for, in logical pixels.
+    pub window: Vec2,
+    /// How wide a cell is drawn, in pixels.
+    pub cell_size: f32,
+    /// World position of the center of each grid's top left visible cell, left to right.
+    pub grids: Vec<Vec2>,
+    /// Top left corner of the HUD, in pixels from the window's top left.
+    pub hud: Vec2,
+    /// Top left corner of the grids, in pixels from the window's top left.
+    pub panel: Vec2,
+    pub portrait: bool,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self::new(
+            DEFAULT_WINDOW,
+            &[GridConfig::default(), GridConfig::default()],
+        )
+    }
+}
+
+impl Layout {
+    /// Fits `grids` side by side, plus the HUD, into a `window` sized window, with cells as large
+    /// as there's room for.
+    #[must_use]
+    pub fn new(window: Vec2, grids: &[GridConfig]) -> Self {
+        let portrait = window.y > window.x;
+        let columns = grids.iter().map(|config| config.width as f32).sum::<f32>()
+            + GRID_GAP * grids.len().saturating_sub(1) as f32;
+        let rows = grids.iter().map(|config| config.height).max().unwrap_or(0) as f32;
+        let room = if portrait {
+            Vec2::new(
+                window.x - 2.0 * MARGIN,
+                window.y - HUD_HEIGHT - 3.0 * MARGIN,
+            )
+        } else {
+            Vec2::new(window.x - HUD_WIDTH - 3.0 * MARGIN, window.y - 2.0 * MARGIN)
+        };
+        let cell_size = (room.x / columns.max(1.0))
+            .min(room.y / rows.max(1.0))
+            .clamp(MIN_CELL_SIZE, MAX_CELL_SIZE);
+        let size = Vec2::new(columns, rows) * cell_size;
+
+        // The grids and the HUD are centered together.
+        let (panel, hud) = if portrait {
+            let panel = Vec2::new(
+                ((window.x - size.x) / 2.0).max(MARGIN),
+                ((window.y - size.y - MARGIN - HUD_HEIGHT) / 2.0).max(MARGIN),
+            );
+            (panel, Vec2::new(MARGIN, panel.y + size.y + MARGIN))
+        } else {
+            let panel = Vec2::new(
+                ((window.x - size.x - MARGIN - HUD_WIDTH) / 2.0).max(MARGIN),
+                ((window.y - size.y) / 2.0).max(MARGIN),
+            );
+            (panel, Vec2::new(panel.x + size.x + MARGIN, panel.y))
+        };
+
+        let mut left = panel.x;
+        let grids = grids
+            .iter()
+            .map(|config| {
+                let origin = Vec2::new(
+                    left + cell_size / 2.0 - window.x / 2.0,
+                    window.y / 2.0 - panel.y - cell_size / 2.0,
+                );
+                left += (config.width as f32 + GRID_GAP) * cell_size;
+                origin
+            })
+            .collect();
+        Self {
+            window,
+            cell_size,
+            grids,
+            hud,
+            panel,
+            portrait,
+        }
+    }
+
+    /// Moves a node anchored at `anchor` into place. The HUD is a column beside the grids, or rows
+    /// across the window below them in portrait.
+    pub fn place(&self, anchor: LayoutAnchor, style: &mut Style) {
+        let position = match anchor {
+            LayoutAnchor::Hud => self.hud,
+            LayoutAnchor::Panel => self.panel,
+            LayoutAnchor::Menu => self.panel + Vec2::new(0.0, PROMPT_HEIGHT),
+        };
+        style.position_type = PositionType::Absolute;
+        style.left = Val::Px(position.x);
+        style.top = Val::Px(position.y);
+        if anchor == LayoutAnchor::Hud {
+            if self.portrait {
+                style.flex_direction = FlexDirection::Row;
+                style.flex_wrap = FlexWrap::Wrap;
+                style.width = Val::Px(self.window.x - 2.0 * MARGIN);
+            } else {
+                style.flex_direction = FlexDirection::Column;
+                style.flex_wrap = FlexWrap::NoWrap;
+                style.width = Val::Auto;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_layout() {
+        let grids = [GridConfig::default(), GridConfig::default()];
+        let wide = Layout::new(Vec2::new(1280.0, 720.0), &grids);
+        assert!(!wide.portrait);
+        assert!((wide.cell_size - 880.0 / 24.0).abs() < 0.01);
+        // Grids sit side by side a gap apart, with the HUD to their right.
+        let spacing = wide.grids[1] - wide.grids[0];
+        assert!((spacing.x - 14.0 * wide.cell_size).abs() < 0.01);
+        assert!(spacing.y.abs() < 0.01);
+        assert!(wide.hud.x > wide.panel.x + 24.0 * wide.cell_size);
+        assert!(wide.hud.x + HUD_WIDTH <= 1280.0);
+
+        // A narrow window shrinks the cells to fit both grids across it.
+        let tall = Layout::new(Vec2::new(390.0, 844.0), &grids);
+        assert!(tall.portrait);
+        assert!(tall.cell_size < wide.cell_size);
+        assert!(24.0 * tall.cell_size <= 390.0 - 2.0 * MARGIN + 0.01);
+        assert!(tall.hud.y >= tall.panel.y + 16.0 * tall.cell_size);
+        assert!(tall.hud.y + HUD_HEIGHT <= 844.0);
+
+        let tiny = Layout::new(Vec2::new(50.0, 50.0), &grids);
+        assert!((tiny.cell_size - MIN_CELL_SIZE).abs() < f32::EPSILON);
+    }
+}
@@ -1,5 +1,20 @@
-pub(crate) mod components;
+pub mod audio;
+pub mod bot;
+pub mod components;
+pub mod effects;
+pub mod finesse;
+pub mod highscore;
+pub mod interpolation;
+pub mod layout;
+pub mod mode;
+pub mod pieces;
 mod plugin;
+pub mod puzzle;
+pub mod render;
+pub mod save;
+pub mod sim;
+pub mod stats;
+pub mod theme;
 
 pub use plugin::RandomSource;
 pub use plugin::TetrisPlugin;
@@ -0,0 +1,436 @@
+use crate::tetris::components::GridStats;
+use crate::tetris::save::{read_ron, write_ron};
+use bevy::prelude::*;
+use bevy::time::Stopwatch;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::io;
+use std::time::Duration;
+
+/// File name of the personal bests inside the platform data directory.
+pub const PERSONAL_BESTS_FILE: &str = "personal_bests.ron";
+
+/// The localStorage key the personal bests are saved under on the web.
+pub const PERSONAL_BESTS_STORAGE_KEY: &str = "twotris.personal_bests";
+
+pub const SPRINT_LINES: u32 = 40;
+pub const SPRINT_LINES_PER_GRID: u32 = 20;
+/// A sprint records a split each time this many more lines are cleared towards its goal.
+pub const SPLIT_LINES: u32 = 10;
+pub const MARATHON_LINES: u32 = 150;
+pub const LINES_PER_LEVEL: u32 = 10;
+/// Gravity stops speeding up past this level.
+pub const MAX_GRAVITY_LEVEL: u32 = 20;
+pub const DIG_GARBAGE_ROWS: u32 = 10;
+
+/// The rules for the current game. Chosen from the game over screen and kept across restarts.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Resource, Serialize, Deserialize,
+)]
+pub enum GameMode {
+    /// Level up every 10 lines, winning at 150 lines. The endless variant plays until a grid
+    /// tops out.
+    Marathon { endless: bool },
+    /// Clear 40 lines as fast as possible, either combined across both grids or 20 on each.
+    Sprint { per_grid: bool },
+    /// Score as much as possible before the time runs out.
+    Ultra { minutes: u32 },
+    /// Each grid starts with `rows` rows of garbage to clear as fast as possible.
+    Dig { rows: u32 },
+    /// Reach the goal of the puzzle at `index` in `Puzzles` with its fixed pieces.
+    Puzzle { index: usize },
+}
+
+impl Default for GameMode {
+    fn default() -> Self {
+        GameMode::Marathon { endless: false }
+    }
+}
+
+impl GameMode {
+    /// Every mode selectable from the game over screen, in the order they're listed. Puzzles have
+    /// their own select screen.
+    pub const ALL: [GameMode; 7] = [
+        GameMode::Marathon { endless: false },
+        GameMode::Marathon { endless: true },
+        GameMode::Sprint { per_grid: false },
+        GameMode::Sprint { per_grid: true },
+        GameMode::Ultra { minutes: 2 },
+        GameMode::Ultra { minutes: 3 },
+        GameMode::Dig {
+            rows: DIG_GARBAGE_ROWS,
+        },
+    ];
+
+    #[must_use]
+    pub fn name(&self) -> String {
+        match self {
+            GameMode::Marathon { endless: false } => "Marathon".to_string(),
+            GameMode::Marathon { endless: true } => "Endless".to_string(),
+            GameMode::Sprint { per_grid: false } => "Sprint 40".to_string(),
+            GameMode::Sprint { per_grid: true } => "Sprint 20+20".to_string(),
+            GameMode::Ultra { minutes } => format!("Ultra {minutes}:00"),
+            GameMode::Dig { rows } => format!("Dig {rows}"),
+            GameMode::Puzzle { index } => format!("Puzzle {}", index + 1),
+        }
+    }
+
+    /// How long the game lasts before time's up, if it's timed.
+    #[must_use]
+    pub fn time_limit(&self) -> Option<Duration> {
+        match self {
+            GameMode::Ultra { minutes } => Some(Duration::from_secs(u64::from(*minutes) * 60)),
+            GameMode::Marathon { .. }
+            | GameMode::Sprint { .. }
+            | GameMode::Dig { .. }
+            | GameMode::Puzzle { .. } => None,
+        }
+    }
+
+    /// Garbage rows each grid starts with.
+    #[must_use]
+    pub fn garbage_rows(&self) -> u32 {
+        match self {
+            GameMode::Dig { rows } => *rows,
+            GameMode::Marathon { .. }
+            | GameMode::Sprint { .. }
+            | GameMode::Ultra { .. }
+            | GameMode::Puzzle { .. } => 0,
+        }
+    }
+
+    /// Lines still needed to finish, given the stats of each grid.
+    #[must_use]
+    pub fn lines_remaining(&self, grids: &[GridStats]) -> Option<u32> {
+        let lines = grids.iter().map(|stats| stats.lines);
+        match self {
+            GameMode::Marathon { endless: true }
+            | GameMode::Ultra { .. }
+            | GameMode::Puzzle { .. } => None,
+            GameMode::Marathon { endless: false } => {
+                Some(MARATHON_LINES.saturating_sub(lines.sum()))
+            }
+            GameMode::Sprint { per_grid: false } => Some(SPRINT_LINES.saturating_sub(lines.sum())),
+            GameMode::Sprint { per_grid: true } => Some(
+                lines
+                    .map(|lines| SPRINT_LINES_PER_GRID.saturating_sub(lines))
+                    .sum(),
+            ),
+            GameMode::Dig { .. } => Some(grids.iter().map(|stats| stats.garbage).sum()),
+        }
+    }
+
+    /// How many splits a game should have recorded with the given stats on each grid. Only
+    /// sprints record splits.
+    #[must_use]
+    pub fn splits(&self, grids: &[GridStats]) -> usize {
+        match self {
+            GameMode::Sprint { .. } => {
+                let remaining = self.lines_remaining(grids).unwrap_or(SPRINT_LINES);
+                (SPRINT_LINES.saturating_sub(remaining) / SPLIT_LINES) as usize
+            }
+            GameMode::Marathon { .. }
+            | GameMode::Ultra { .. }
+            | GameMode::Dig { .. }
+            | GameMode::Puzzle { .. } => 0,
+        }
+    }
+
+    #[must_use]
+    pub fn is_complete(&self, grids: &[GridStats]) -> bool {
+        self.lines_remaining(grids) == Some(0)
+    }
+
+    /// The level reached with the given stats on each grid. Only marathon levels up.
+    #[must_use]
+    pub fn level(&self, grids: &[GridStats]) -> u32 {
+        match self {
+            GameMode::Marathon { .. } => {
+                grids.iter().map(|stats| stats.lines).sum::<u32>() / LINES_PER_LEVEL + 1
+            }
+            GameMode::Sprint { .. }
+            | GameMode::Ultra { .. }
+            | GameMode::Dig { .. }
+            | GameMode::Puzzle { .. } => 1,
+        }
+    }
+}
+
+/// The current level, which sets how fast pieces fall.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Resource)]
+pub struct Level(pub u32);
+
+impl Default for Level {
+    fn default() -> Self {
+        Self(1)
+    }
+}
+
+impl Level {
+    /// Time between gravity steps, following the guideline curve
+    /// `(0.8 - (level - 1) * 0.007) ^ (level - 1)` seconds.
+    #[must_use]
+    pub fn gravity_interval(self) -> Duration {
+        let level = self.0.clamp(1, MAX_GRAVITY_LEVEL) - 1;
+        let base = 0.8 - level as f32 * 0.007;
+        Duration::from_secs_f32(base.powi(level.try_into().unwrap_or(i32::MAX)))
+    }
+}
+
+/// The HUD line showing the mode, clock and progress.
+#[derive(Debug, Component)]
+pub struct ModeText;
+
+/// Time spent in the current game. Only runs while the game is being played.
+#[derive(Debug, Default, Resource)]
+pub struct GameClock(pub Stopwatch);
+
+impl GameClock {
+    #[must_use]
+    pub fn elapsed(&self) -> Duration {
+        self.0.elapsed()
+    }
+
+    pub fn reset(&mut self) {
+        self.0.reset();
+    }
+}
+
+/// Formats a duration the way the HUD shows it, e.g. `1:02.345`.
+#[must_use]
+pub fn format_duration(duration: Duration) -> String {
+    let millis = duration.as_millis();
+    format!(
+        "{}:{:02}.{:03}",
+        millis / 60_000,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+/// The game clock at each split of the current sprint.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Resource)]
+pub struct Splits(pub Vec<Duration>);
+
+/// One line per split, e.g. `10 lines: 0:21.345 (-0:01.200)`, each compared against the same
+/// split of `best` when it has one.
+#[must_use]
+pub fn format_splits(splits: &[Duration], best: &[Duration]) -> String {
+    let mut lines = String::new();
+    for (index, &split) in splits.iter().enumerate() {
+        if index > 0 {
+            lines.push('\n');
+        }
+        let lines_cleared = (index + 1) * SPLIT_LINES as usize;
+        let _ = write!(lines, "{lines_cleared} lines: {}", format_duration(split));
+        if let Some(&best) = best.get(index) {
+            let sign = if split <= best { '-' } else { '+' };
+            let _ = write!(lines, " ({sign}{})", format_duration(split.abs_diff(best)));
+        }
+    }
+    lines
+}
+
+/// Whether a bot has played on any grid during the current game, which keeps the run out of the
+/// personal bests.
+#[derive(Debug, Default, Clone, Copy, Resource)]
+pub struct BotAssisted(pub bool);
+
+/// The best result recorded for each mode.
+#[derive(Debug, Default, Clone, PartialEq, Resource, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PersonalBests {
+    pub sprint: Option<Duration>,
+    pub sprint_per_grid: Option<Duration>,
+    /// Splits of the best sprint.
+    pub sprint_splits: Vec<Duration>,
+    /// Splits of the best per-grid sprint.
+    pub sprint_per_grid_splits: Vec<Duration>,
+    /// Best ultra score for each duration in minutes.
+    pub ultra: BTreeMap<u32, u32>,
+    /// Best dig time for each number of garbage rows.
+    pub dig: BTreeMap<u32, Duration>,
+}
+
+impl PersonalBests {
+    /// Reads the personal bests saved on this platform, empty if there are none yet.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the personal bests can't be read or aren't valid RON.
+    pub fn read() -> io::Result<Self> {
+        Ok(read_ron(PERSONAL_BESTS_FILE, PERSONAL_BESTS_STORAGE_KEY)?.unwrap_or_default())
+    }
+
+    /// Saves the personal bests for this platform.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the personal bests can't be written.
+    pub fn write(&self) -> io::Result<()> {
+        write_ron(PERSONAL_BESTS_FILE, PERSONAL_BESTS_STORAGE_KEY, self)
+    }
+
+    /// The splits of the best sprint, empty if there isn't one or it was set before splits were
+    /// recorded.
+    #[must_use]
+    pub fn sprint_splits(&self, per_grid: bool) -> &[Duration] {
+        if per_grid {
+            &self.sprint_per_grid_splits
+        } else {
+            &self.sprint_splits
+        }
+    }
+
+    /// Records a finished sprint and its splits, returning whether it beat the previous best.
+    pub fn record_sprint(&mut self, per_grid: bool, time: Duration, splits: &[Duration]) -> bool {
+        let (best, best_splits) = if per_grid {
+            (&mut self.sprint_per_grid, &mut self.sprint_per_grid_splits)
+        } else {
+            (&mut self.sprint, &mut self.sprint_splits)
+        };
+        if best.is_some_and(|best| best <= time) {
+            return false;
+        }
+        *best = Some(time);
+        *best_splits = splits.to_vec();
+        true
+    }
+
+    /// Records the score of a finished ultra game, returning whether it beat the previous best.
+    pub fn record_ultra(&mut self, minutes: u32, score: u32) -> bool {
+        if self.ultra.get(&minutes).is_some_and(|&best| best >= score) {
+            return false;
+        }
+        self.ultra.insert(minutes, score);
+        true
+    }
+
+    /// Records a finished dig, returning whether it beat the previous best.
+    pub fn record_dig(&mut self, rows: u32, time: Duration) -> bool {
+        if self.dig.get(&rows).is_some_and(|&best| best <= time) {
+            return false;
+        }
+        self.dig.insert(rows, time);
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn lines(lines: &[u32]) -> Vec<GridStats> {
+        lines
+            .iter()
+            .map(|&lines| GridStats {
+                lines,
+                ..GridStats::default()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_sprint_lines_remaining() {
+        let combined = GameMode::Sprint { per_grid: false };
+        assert_eq!(combined.lines_remaining(&lines(&[10, 12])), Some(18));
+        assert!(combined.is_complete(&lines(&[40, 0])));
+
+        let per_grid = GameMode::Sprint { per_grid: true };
+        assert_eq!(per_grid.lines_remaining(&lines(&[25, 12])), Some(8));
+        assert!(!per_grid.is_complete(&lines(&[40, 0])));
+        assert!(per_grid.is_complete(&lines(&[20, 21])));
+
+        assert_eq!(
+            GameMode::Marathon { endless: true }.lines_remaining(&lines(&[100, 100])),
+            None
+        );
+    }
+
+    #[test]
+    fn test_dig_complete() {
+        let dig = GameMode::Dig { rows: 5 };
+        let mut grids = lines(&[3, 4]);
+        grids[0].garbage = 2;
+        assert_eq!(dig.lines_remaining(&grids), Some(2));
+        grids[0].garbage = 0;
+        assert!(dig.is_complete(&grids));
+        assert_eq!(dig.garbage_rows(), 5);
+        assert_eq!(GameMode::Ultra { minutes: 2 }.garbage_rows(), 0);
+    }
+
+    #[test]
+    fn test_marathon_levels() {
+        let marathon = GameMode::Marathon { endless: false };
+        assert_eq!(marathon.level(&lines(&[0, 9])), 1);
+        assert_eq!(marathon.level(&lines(&[6, 6])), 2);
+        assert_eq!(marathon.lines_remaining(&lines(&[100, 20])), Some(30));
+        assert!(marathon.is_complete(&lines(&[75, 75])));
+        assert!(!GameMode::Marathon { endless: true }.is_complete(&lines(&[75, 75])));
+        assert_eq!(
+            GameMode::Sprint { per_grid: false }.level(&lines(&[30, 0])),
+            1
+        );
+
+        assert_eq!(Level(1).gravity_interval(), Duration::from_secs(1));
+        assert!(Level(2).gravity_interval() < Level(1).gravity_interval());
+        assert_eq!(Level(100).gravity_interval(), Level(20).gravity_interval());
+    }
+
+    #[test]
+    fn test_personal_best_sprint() {
+        let mut bests = PersonalBests::default();
+        let splits = [15, 30, 45, 59].map(Duration::from_secs);
+        assert!(bests.record_sprint(false, Duration::from_mins(1), &[]));
+        assert!(!bests.record_sprint(false, Duration::from_secs(61), &splits));
+        assert!(bests.sprint_splits(false).is_empty());
+        assert!(bests.record_sprint(false, Duration::from_secs(59), &splits));
+        assert_eq!(bests.sprint, Some(Duration::from_secs(59)));
+        assert_eq!(bests.sprint_splits(false), splits);
+        assert_eq!(bests.sprint_per_grid, None);
+        assert!(bests.sprint_splits(true).is_empty());
+    }
+
+    #[test]
+    fn test_sprint_splits() {
+        let combined = GameMode::Sprint { per_grid: false };
+        assert_eq!(combined.splits(&lines(&[4, 5])), 0);
+        assert_eq!(combined.splits(&lines(&[14, 7])), 2);
+        assert_eq!(combined.splits(&lines(&[40, 3])), 4);
+        // Lines past a grid's 20 don't count towards a per-grid sprint.
+        let per_grid = GameMode::Sprint { per_grid: true };
+        assert_eq!(per_grid.splits(&lines(&[25, 4])), 2);
+        assert_eq!(GameMode::Dig { rows: 10 }.splits(&lines(&[30, 30])), 0);
+
+        let splits = [Duration::from_millis(21_345), Duration::from_secs(40)];
+        let best = [Duration::from_millis(22_545)];
+        assert_eq!(
+            format_splits(&splits, &best),
+            "10 lines: 0:21.345 (-0:01.200)\n20 lines: 0:40.000"
+        );
+        assert_eq!(
+            format_splits(&splits[..1], &[Duration::from_secs(20)]),
+            "10 lines: 0:21.345 (+0:01.345)"
+        );
+    }
+
+    #[test]
+    fn test_personal_best_ultra() {
+        let mut bests = PersonalBests::default();
+        assert!(bests.record_ultra(2, 1200));
+        assert!(!bests.record_ultra(2, 1200));
+        assert!(bests.record_ultra(3, 400));
+        assert!(bests.record_ultra(2, 1240));
+        assert_eq!(bests.ultra.get(&2), Some(&1240));
+        assert_eq!(
+            GameMode::Ultra { minutes: 3 }.time_limit(),
+            Some(Duration::from_mins(3))
+        );
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(Duration::from_millis(62_345)), "1:02.345");
+    }
+}
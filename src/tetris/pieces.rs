@@ -0,0 +1,499 @@
+use crate::tetris::components::{PieceShape, TetrominoType, MAX_GRID_WIDTH};
+use crate::tetris::RandomSource;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as fmtResult};
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Where piece set definitions are loaded from on native builds.
+pub const PIECE_SETS_DIR: &str = "assets/pieces";
+
+/// One kind of piece: the letter it's named by, the sRGB color it's drawn in and its rotation
+/// states.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PieceDef {
+    pub letter: char,
+    pub color: [u8; 3],
+    pub rotations: Cow<'static, [PieceShape]>,
+    /// Which of the classic pieces this is, if any.
+    pub tetromino_type: Option<TetrominoType>,
+}
+
+/// A kind of piece, shared by every piece in play of that kind. Pieces are equal only if they
+/// share a definition; every piece read with a classic definition shares the classic one.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "PieceData", into = "PieceData")]
+pub struct Piece(Arc<PieceDef>);
+
+impl PartialEq for Piece {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Piece {}
+
+impl Piece {
+    pub(crate) fn new(def: PieceDef) -> Self {
+        Self(Arc::new(def))
+    }
+
+    #[must_use]
+    pub fn letter(&self) -> char {
+        self.0.letter
+    }
+
+    #[must_use]
+    pub fn color(&self) -> [u8; 3] {
+        self.0.color
+    }
+
+    /// The piece's rotation states, in the order it turns through them.
+    #[must_use]
+    pub fn rotations(&self) -> &[PieceShape] {
+        &self.0.rotations
+    }
+
+    /// The widest of its rotation states.
+    #[must_use]
+    pub fn width(&self) -> usize {
+        self.rotations()
+            .iter()
+            .map(|shape| shape.width)
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// Which of the classic pieces this is, if any.
+    #[must_use]
+    pub fn tetromino_type(&self) -> Option<TetrominoType> {
+        self.0.tetromino_type
+    }
+
+    /// Whether both pieces are defined the same way, even if they don't share a definition.
+    fn same_def(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+/// How a piece is written in piece set files and saved games. Each rotation state is a list of
+/// rows from the top, with `X` for filled cells and `.` for empty ones.
+#[derive(Serialize, Deserialize)]
+struct PieceData {
+    letter: char,
+    color: [u8; 3],
+    /// When only one state is listed, the rest are made by turning it clockwise until it
+    /// repeats.
+    rotations: Vec<Vec<String>>,
+}
+
+impl TryFrom<PieceData> for Piece {
+    type Error = PieceSetError;
+
+    fn try_from(data: PieceData) -> Result<Self, Self::Error> {
+        let letter = data.letter;
+        if !letter.is_ascii_uppercase() {
+            return Err(PieceSetError::InvalidLetter(letter));
+        }
+        let mut shapes = data
+            .rotations
+            .iter()
+            .enumerate()
+            .map(|(rotation, rows)| {
+                parse_shape(rows).ok_or(PieceSetError::InvalidShape { letter, rotation })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        match shapes.len() {
+            0 => return Err(PieceSetError::NoRotations(letter)),
+            1 => loop {
+                let turned = turn_clockwise(&shapes[shapes.len() - 1]);
+                if turned == shapes[0] {
+                    break;
+                }
+                shapes.push(turned);
+            },
+            _ => {}
+        }
+        let def = PieceDef {
+            letter,
+            color: data.color,
+            rotations: shapes
+                .into_iter()
+                .map(|(width, rows)| PieceShape {
+                    width,
+                    rows: rows.into(),
+                })
+                .collect(),
+            tetromino_type: None,
+        };
+        // Classic pieces keep sharing the classic definitions, so they're still recognized.
+        let classic = TetrominoType::ALL
+            .into_iter()
+            .map(TetrominoType::piece)
+            .find(|piece| {
+                piece.letter() == def.letter
+                    && piece.color() == def.color
+                    && piece.rotations() == &*def.rotations
+            });
+        Ok(classic.unwrap_or_else(|| Piece::new(def)))
+    }
+}
+
+impl From<Piece> for PieceData {
+    fn from(piece: Piece) -> Self {
+        let rotations = piece
+            .rotations()
+            .iter()
+            .map(|shape| {
+                shape
+                    .rows
+                    .iter()
+                    .map(|&row| {
+                        (0..shape.width)
+                            .map(|x| if row >> x & 1 == 1 { 'X' } else { '.' })
+                            .collect()
+                    })
+                    .collect()
+            })
+            .collect();
+        Self {
+            letter: piece.letter(),
+            color: piece.color(),
+            rotations,
+        }
+    }
+}
+
+/// A rotation state's width and row masks, if its rows form a rectangle of `X` and `.` with at
+/// least one cell filled.
+fn parse_shape(rows: &[String]) -> Option<(usize, Vec<u64>)> {
+    let width = rows.first()?.chars().count();
+    if width == 0 || width > MAX_GRID_WIDTH {
+        return None;
+    }
+    let mut masks = vec![];
+    for row in rows {
+        if row.chars().count() != width {
+            return None;
+        }
+        let mut mask = 0;
+        for (x, cell) in row.chars().enumerate() {
+            match cell {
+                'X' => mask |= 1 << x,
+                '.' => {}
+                _ => return None,
+            }
+        }
+        masks.push(mask);
+    }
+    masks
+        .iter()
+        .any(|&mask| mask != 0)
+        .then_some((width, masks))
+}
+
+/// `shape` turned a quarter turn clockwise: its left column becomes its top row.
+fn turn_clockwise((width, rows): &(usize, Vec<u64>)) -> (usize, Vec<u64>) {
+    let height = rows.len();
+    let turned = (0..*width)
+        .map(|x| {
+            (0..height)
+                .filter(|&y| rows[height - 1 - y] >> x & 1 == 1)
+                .fold(0, |mask, y| mask | 1 << y)
+        })
+        .collect();
+    (height, turned)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PieceSetError {
+    Empty,
+    /// Letters name pieces in stats and must be uppercase ASCII.
+    InvalidLetter(char),
+    DuplicateLetter(char),
+    NoRotations(char),
+    /// A rotation state isn't a rectangle of `X` and `.` with at least one `X`.
+    InvalidShape {
+        letter: char,
+        rotation: usize,
+    },
+    /// A rotation state is wider than the grid it would be dealt to.
+    TooWide {
+        letter: char,
+        width: usize,
+        grid_width: usize,
+    },
+}
+
+impl Display for PieceSetError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmtResult {
+        match self {
+            PieceSetError::Empty => write!(f, "piece set has no pieces"),
+            PieceSetError::InvalidLetter(letter) => {
+                write!(f, "piece letter {letter:?} isn't an uppercase letter")
+            }
+            PieceSetError::DuplicateLetter(letter) => {
+                write!(f, "more than one piece is named {letter}")
+            }
+            PieceSetError::NoRotations(letter) => write!(f, "piece {letter} has no shape"),
+            PieceSetError::InvalidShape { letter, rotation } => write!(
+                f,
+                "rotation {rotation} of piece {letter} isn't a rectangle of X and . cells"
+            ),
+            PieceSetError::TooWide {
+                letter,
+                width,
+                grid_width,
+            } => write!(
+                f,
+                "piece {letter} is {width} cells wide, grids are only {grid_width}"
+            ),
+        }
+    }
+}
+
+impl Error for PieceSetError {}
+
+/// The pieces a game deals from, each as likely as the others. Sets are equal if their pieces are
+/// defined the same way, so a set read back from a save still matches the one it was saved from.
+#[derive(Debug, Clone, Resource, Serialize, Deserialize)]
+#[serde(try_from = "PieceSetData")]
+pub struct PieceSet {
+    pub name: String,
+    pieces: Vec<Piece>,
+}
+
+#[derive(Deserialize)]
+struct PieceSetData {
+    name: String,
+    pieces: Vec<Piece>,
+}
+
+impl PartialEq for PieceSet {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+            && self.pieces.len() == other.pieces.len()
+            && self
+                .pieces
+                .iter()
+                .zip(&other.pieces)
+                .all(|(piece, other)| piece.same_def(other))
+    }
+}
+
+impl Eq for PieceSet {}
+
+impl TryFrom<PieceSetData> for PieceSet {
+    type Error = PieceSetError;
+
+    fn try_from(data: PieceSetData) -> Result<Self, Self::Error> {
+        Self::new(data.name, data.pieces)
+    }
+}
+
+impl PieceSet {
+    /// # Errors
+    ///
+    /// Fails if there are no pieces or two share a letter.
+    pub fn new(name: impl Into<String>, pieces: Vec<Piece>) -> Result<Self, PieceSetError> {
+        if pieces.is_empty() {
+            return Err(PieceSetError::Empty);
+        }
+        for (i, piece) in pieces.iter().enumerate() {
+            if pieces[..i]
+                .iter()
+                .any(|other| other.letter() == piece.letter())
+            {
+                return Err(PieceSetError::DuplicateLetter(piece.letter()));
+            }
+        }
+        Ok(Self {
+            name: name.into(),
+            pieces,
+        })
+    }
+
+    /// The seven tetrominoes.
+    pub fn classic() -> Self {
+        Self {
+            name: "Classic".to_string(),
+            pieces: TetrominoType::ALL.map(TetrominoType::piece).to_vec(),
+        }
+    }
+
+    #[must_use]
+    pub fn pieces(&self) -> &[Piece] {
+        &self.pieces
+    }
+
+    pub fn is_classic(&self) -> bool {
+        self.pieces
+            .iter()
+            .map(Piece::tetromino_type)
+            .eq(TetrominoType::ALL.map(Some))
+    }
+
+    pub fn random(&self, rng: &mut RandomSource) -> Piece {
+        let len = u32::try_from(self.pieces.len()).unwrap_or(u32::MAX);
+        self.pieces[rng.next(0, len) as usize].clone()
+    }
+
+    /// Checks that every rotation of every piece fits in a grid `grid_width` cells wide.
+    ///
+    /// # Errors
+    ///
+    /// Fails with the first piece that's too wide.
+    pub fn check_width(&self, grid_width: usize) -> Result<(), PieceSetError> {
+        match self.pieces.iter().find(|piece| piece.width() > grid_width) {
+            Some(piece) => Err(PieceSetError::TooWide {
+                letter: piece.letter(),
+                width: piece.width(),
+                grid_width,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Parses a piece set from RON.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `contents` isn't a valid piece set definition.
+    pub fn parse(contents: &str) -> io::Result<Self> {
+        ron::from_str(contents).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Reads a piece set definition file.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the file can't be read or isn't a valid piece set definition.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+}
+
+impl Default for PieceSet {
+    fn default() -> Self {
+        Self::classic()
+    }
+}
+
+/// Every piece set that can be picked from the end screen, starting with the classic one.
+#[derive(Debug, Clone, Resource)]
+pub struct PieceSets(pub Vec<PieceSet>);
+
+impl Default for PieceSets {
+    fn default() -> Self {
+        Self(vec![PieceSet::classic()])
+    }
+}
+
+impl PieceSets {
+    /// The classic set followed by every `.ron` file in `dir`, sorted by file name. Files that
+    /// fail to parse are skipped with a warning.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the directory can't be read.
+    pub fn load_dir(dir: impl AsRef<Path>) -> io::Result<Self> {
+        let mut paths: Vec<_> = std::fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "ron"))
+            .collect();
+        paths.sort();
+        let mut sets = Self::default();
+        sets.0
+            .extend(paths.iter().filter_map(|path| match PieceSet::load(path) {
+                Ok(set) => Some(set),
+                Err(e) => {
+                    warn!("Skipping piece set {}: {}", path.display(), e);
+                    None
+                }
+            }));
+        Ok(sets)
+    }
+
+    /// The piece sets shipped in `assets/pieces`, built into the binary for the web.
+    #[cfg(target_arch = "wasm32")]
+    pub fn builtin() -> Self {
+        let mut sets = Self::default();
+        sets.0.extend(
+            [
+                include_str!("../../assets/pieces/easy.ron"),
+                include_str!("../../assets/pieces/pentominoes.ron"),
+            ]
+            .iter()
+            .filter_map(|contents| PieceSet::parse(contents).ok()),
+        );
+        sets
+    }
+
+    /// The set listed after `current`, wrapping around to the first.
+    #[must_use]
+    pub fn after(&self, current: &PieceSet) -> Option<&PieceSet> {
+        let next = self
+            .0
+            .iter()
+            .position(|set| set == current)
+            .map_or(0, |i| (i + 1) % self.0.len());
+        self.0.get(next)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tetris::components::{ControlledTetromino, Grid, GridConfig, PieceId};
+
+    #[test]
+    fn test_builtin_piece_sets() {
+        let sets = PieceSets::load_dir(PIECE_SETS_DIR).unwrap();
+        assert!(sets.0.len() > 1);
+        for set in &sets.0 {
+            assert!(set.check_width(GridConfig::default().width).is_ok());
+            let parsed = PieceSet::parse(&ron::to_string(set).unwrap()).unwrap();
+            assert_eq!(&parsed, set, "{} doesn't round trip", set.name);
+        }
+        let pentominoes = sets.0.iter().find(|set| set.name == "Pentominoes").unwrap();
+        assert_eq!(pentominoes.pieces().len(), 12);
+        for piece in pentominoes.pieces() {
+            for shape in piece.rotations() {
+                assert_eq!(shape.coords().count(), 5, "{}", piece.letter());
+            }
+        }
+        assert!(pentominoes.check_width(4).is_err());
+    }
+
+    #[test]
+    fn test_piece_rotations_from_one_state() {
+        let piece: Piece =
+            ron::from_str(r#"(letter: 'T', color: (170, 0, 255), rotations: [[".X.", "XXX"]])"#)
+                .unwrap();
+        assert_eq!(piece, TetrominoType::T.piece());
+        assert_eq!(piece.tetromino_type(), Some(TetrominoType::T));
+
+        let mut grid = Grid::new(GridConfig::new(4, 4));
+        let piece: Piece =
+            ron::from_str(r#"(letter: 'Q', color: (0, 0, 0), rotations: [["XX.", ".XX"]])"#)
+                .unwrap();
+        // Pieces in play only refer to pieces of the set their grid deals from.
+        assert_eq!(ControlledTetromino::new_with_piece(&piece, &grid), None);
+        grid.set_pieces(&PieceSet::new("Q", vec![piece.clone()]).unwrap());
+        let mut tetromino = ControlledTetromino::new_with_piece(&piece, &grid).unwrap();
+        assert_eq!(tetromino.piece, PieceId::Set(0));
+        assert_eq!(grid.piece(tetromino.piece), &piece);
+        tetromino.rotate(&grid);
+        assert_eq!(*tetromino.shape(&grid).rows, [0b10, 0b11, 0b01]);
+        tetromino.rotate(&grid);
+        assert_eq!(tetromino.rotation, 0);
+
+        let invalid: Result<Piece, _> =
+            ron::from_str(r#"(letter: 'Q', color: (0, 0, 0), rotations: [["X.", "X"]])"#);
+        assert!(invalid.is_err());
+    }
+}
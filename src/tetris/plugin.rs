@@ -1,95 +1,362 @@
+use crate::tetris::audio::{AudioPlugin, AudioSettings, Sound, SoundEvent, VolumeSlider};
+use crate::tetris::bot::{Bot, BotSettings};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::tetris::bot::{Weights, WEIGHTS_PATH};
 use crate::tetris::components::{
-    ControlledTetromino, Coordinate, DrawGrid, Focus, GameOver, Grid, GridTetromino,
-    RowClearedEvent, Score, Shadow,
+    ControlledTetromino, Coordinate, Delays, DrawGrid, Focus, GameOver, Grid, GridAction,
+    GridActionEvent, GridConfigs, GridIndex, GridPhase, GridStats, GridTetromino, LastMove,
+    PauseText, PieceQueue, PieceSpawnedEvent, RowClearedEvent, Score, Shadow,
+};
+use crate::tetris::effects::{Effect, EffectEvent, EffectSettings, EffectsPlugin};
+use crate::tetris::finesse::{
+    self, FinesseFaultEvent, FinesseFlash, FinesseStats, FinesseText, InputLog,
+};
+use crate::tetris::highscore::{
+    HighScore, HighScorePrompt, HighScoreTable, HighScores, PendingHighScore, MAX_NAME_LEN,
+};
+use crate::tetris::interpolation::{cell_offsets, PieceSprites};
+use crate::tetris::layout::{Layout, LayoutAnchor};
+use crate::tetris::mode::{
+    format_duration, format_splits, BotAssisted, GameClock, GameMode, Level, ModeText,
+    PersonalBests, Splits,
 };
+#[cfg(not(target_arch = "wasm32"))]
+use crate::tetris::pieces::PIECE_SETS_DIR;
+use crate::tetris::pieces::{Piece, PieceSet, PieceSets};
+use crate::tetris::puzzle::Puzzles;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::tetris::puzzle::PUZZLES_DIR;
+use crate::tetris::render::{self, CellLook, GridSprites, Overlay};
+use crate::tetris::save::{SavedGame, SavedGrid, SavedTetromino, SAVE_VERSION};
+use crate::tetris::stats::{save_lifetime_stats, LifetimeStats, StatsPlugin};
+use crate::tetris::theme::{CellBackground, TextRole, Theme, ThemePlugin, ThemedText};
+use bevy::input::keyboard::{Key, KeyboardInput};
 use bevy::prelude::*;
+use bevy::window::{PrimaryWindow, WindowCloseRequested, WindowFocused};
 #[cfg(not(target_arch = "wasm32"))]
 use iyes_perf_ui::prelude::PerfUiCompleteBundle;
 use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+use std::time::Duration;
 use tracing::debug;
 
 use super::components::TetrominoTimer;
 
-const FOCUS_COLOR: Color = Color::linear_rgba(1.0, 1.0, 1.0, 1.0);
-const NON_FOCUS_COLOR: Color = Color::linear_rgba(0.5, 0.5, 0.5, 1.0);
-const SHADOW_COLOR: Color = Color::linear_rgba(0.0, 0.0, 0.0, 0.1);
-const CELL_SIZE: f32 = 20.0;
+/// Frames that clearing rows spend lit, then dark, while they flash.
+const FLASH_FRAMES: u128 = 4;
+pub(crate) const CELL_SIZE: f32 = 20.0;
+const MODE_KEYS: [KeyCode; 9] = [
+    KeyCode::Digit1,
+    KeyCode::Digit2,
+    KeyCode::Digit3,
+    KeyCode::Digit4,
+    KeyCode::Digit5,
+    KeyCode::Digit6,
+    KeyCode::Digit7,
+    KeyCode::Digit8,
+    KeyCode::Digit9,
+];
 
 #[derive(States, Default, Debug, Clone, PartialEq, Eq, Hash)]
-enum TetrisState {
+pub(crate) enum TetrisState {
     #[default]
     InGame,
     GameOver,
+    /// The mode's goal was reached.
+    Finished,
+    /// A timed mode ran out of time.
+    TimeUp,
+    PuzzleSelect,
+    HighScores,
+    Stats,
+    /// The main menu, opened from the pause screen.
+    Menu,
+}
+
+/// Whether play is suspended during `TetrisState::InGame`. Kept apart from `TetrisState` so that
+/// resuming doesn't start a new game.
+#[derive(States, Default, Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) enum PauseState {
+    #[default]
+    Running,
+    Paused,
+    /// A saved game was found on launch and the player is asked whether to continue it.
+    ContinuePrompt,
+}
+
+#[derive(Debug, Clone, Resource, Serialize, Deserialize)]
+#[serde(from = "SavedRandomSource", into = "SavedRandomSource")]
+pub struct RandomSource {
+    rng: rand_chacha::ChaCha8Rng,
+    seed: u64,
+}
+
+/// How a `RandomSource` is saved: its seed and how far along its stream it is. The position is a
+/// `u128`, which RON can't store, so it's split into two halves.
+#[derive(Serialize, Deserialize)]
+struct SavedRandomSource {
+    seed: u64,
+    word_pos: (u64, u64),
+}
+
+impl From<RandomSource> for SavedRandomSource {
+    fn from(source: RandomSource) -> Self {
+        let word_pos = source.rng.get_word_pos();
+        SavedRandomSource {
+            seed: source.seed,
+            word_pos: (
+                u64::try_from(word_pos >> 64).expect("shifted down to 64 bits"),
+                u64::try_from(word_pos & u128::from(u64::MAX)).expect("masked to 64 bits"),
+            ),
+        }
+    }
 }
 
-#[derive(Debug, Resource)]
-pub struct RandomSource(rand_chacha::ChaCha8Rng);
+impl From<SavedRandomSource> for RandomSource {
+    fn from(saved: SavedRandomSource) -> Self {
+        let mut source = RandomSource::from_seed(saved.seed);
+        let (high, low) = saved.word_pos;
+        source
+            .rng
+            .set_word_pos((u128::from(high) << 64) | u128::from(low));
+        source
+    }
+}
 
 impl Default for RandomSource {
     fn default() -> Self {
-        RandomSource(rand_chacha::ChaCha8Rng::from_entropy())
+        Self::from_seed(rand::thread_rng().gen())
     }
 }
 
 impl RandomSource {
+    #[must_use]
+    pub fn from_seed(seed: u64) -> Self {
+        RandomSource {
+            rng: rand_chacha::ChaCha8Rng::seed_from_u64(seed),
+            seed,
+        }
+    }
+
+    /// The seed this source started from, which replays the same sequence.
+    #[must_use]
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
     pub fn next(&mut self, min: u32, max: u32) -> u32 {
-        self.0.gen_range(min..max)
+        self.rng.gen_range(min..max)
     }
 }
 
-fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+fn setup(mut commands: Commands, asset_server: Res<AssetServer>, theme: Res<Theme>) {
     commands.spawn(Camera2dBundle::default());
     #[cfg(not(target_arch = "wasm32"))]
     commands.spawn(PerfUiCompleteBundle::default());
-    commands.spawn((
-        Score(0),
-        TextBundle::from_section(
-            "Score: 0".to_string(),
-            TextStyle {
-                font: asset_server.load("fonts/JetBrainsMono-Bold.ttf"),
-                font_size: 36.0,
-                color: Color::WHITE,
-            },
+    let controls = "Left/Right/Down: Move\nSpace: Rotate\nF: Swap Grid\nB: Toggle Bot\nV: Toggle Effects\nI: Smooth Pieces\nT: Theme\nEsc: Pause";
+    let text = |value: String, role| {
+        (
+            ThemedText(vec![role]),
+            TextBundle::from_section(value, theme.text_style(role, &asset_server)),
         )
-        .with_style(Style {
-            position_type: PositionType::Absolute,
-            top: Val::Px(100.0),
-            left: Val::Px(800.0),
-            ..default()
-        }),
-    ));
+    };
+    commands
+        .spawn((
+            LayoutAnchor::Hud,
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    row_gap: Val::Px(12.0),
+                    column_gap: Val::Px(40.0),
+                    ..default()
+                },
+                ..default()
+            },
+        ))
+        .with_children(|hud| {
+            hud.spawn((Score(0), text("Score: 0".to_string(), TextRole::Heading)));
+            hud.spawn((ModeText, text(String::new(), TextRole::Body)));
+            hud.spawn(text(controls.to_string(), TextRole::Body));
+            hud.spawn((
+                FinesseText,
+                text(FinesseStats::default().to_string(), TextRole::Body),
+            ));
+            hud.spawn((
+                FinesseFlash::default(),
+                text("Finesse fault!".to_string(), TextRole::Warning),
+                Visibility::Hidden,
+            ));
+        });
+}
 
-    let controls = "Left/Right/Down: Move\nSpace: Rotate\nF: Swap Grid";
-    commands.spawn((TextBundle::from_section(
-        controls.to_string(),
-        TextStyle {
-            font: asset_server.load("fonts/JetBrainsMono-Bold.ttf"),
-            font_size: 24.0,
-            color: Color::WHITE,
-        },
-    )
-    .with_style(Style {
-        position_type: PositionType::Absolute,
-        top: Val::Px(200.0),
-        left: Val::Px(800.0),
-        ..default()
-    }),));
+#[cfg(not(target_arch = "wasm32"))]
+fn load_bot_weights(mut bot_settings: ResMut<BotSettings>) {
+    match Weights::load(WEIGHTS_PATH) {
+        Ok(weights) => bot_settings.weights = weights,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            debug!("No tuned bot weights at {}, using defaults", WEIGHTS_PATH);
+        }
+        Err(e) => warn!("Failed to load bot weights from {}: {}", WEIGHTS_PATH, e),
+    }
+}
+
+fn load_delays(mut delays: ResMut<Delays>) {
+    match Delays::read() {
+        Ok(loaded) => *delays = loaded,
+        Err(e) => warn!("Failed to load delays: {}", e),
+    }
+}
+
+fn load_personal_bests(mut personal_bests: ResMut<PersonalBests>) {
+    match PersonalBests::read() {
+        Ok(loaded) => *personal_bests = loaded,
+        Err(e) => warn!("Failed to load personal bests: {}", e),
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn load_puzzles(mut puzzles: ResMut<Puzzles>) {
+    match Puzzles::load_dir(PUZZLES_DIR) {
+        Ok(loaded) => *puzzles = loaded,
+        Err(e) => warn!("Failed to load puzzles from {}: {}", PUZZLES_DIR, e),
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn load_puzzles(mut puzzles: ResMut<Puzzles>) {
+    *puzzles = Puzzles::builtin();
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn load_piece_sets(mut piece_sets: ResMut<PieceSets>) {
+    match PieceSets::load_dir(PIECE_SETS_DIR) {
+        Ok(loaded) => *piece_sets = loaded,
+        Err(e) => warn!("Failed to load piece sets from {}: {}", PIECE_SETS_DIR, e),
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn load_piece_sets(mut piece_sets: ResMut<PieceSets>) {
+    *piece_sets = PieceSets::builtin();
+}
+
+/// The set listed after `current` whose pieces fit every grid, or `current` if none do.
+fn next_piece_set(sets: &PieceSets, configs: &GridConfigs, current: &PieceSet) -> PieceSet {
+    let mut candidate = current;
+    for _ in 0..sets.0.len() {
+        let Some(next) = sets.after(candidate) else {
+            break;
+        };
+        match configs
+            .0
+            .iter()
+            .try_for_each(|config| next.check_width(config.width))
+        {
+            Ok(()) => return next.clone(),
+            Err(e) => warn!("Skipping piece set {}: {}", next.name, e),
+        }
+        candidate = next;
+    }
+    current.clone()
+}
+
+fn save_personal_bests(personal_bests: &PersonalBests) {
+    if let Err(e) = personal_bests.write() {
+        warn!("Failed to save personal bests: {}", e);
+    }
+}
+
+fn add_garbage(
+    mode: Res<GameMode>,
+    mut random_source: ResMut<RandomSource>,
+    mut grid_query: Query<(&mut Grid, &mut GridStats)>,
+) {
+    let rows = mode.garbage_rows();
+    if rows == 0 {
+        return;
+    }
+    for (mut grid, mut stats) in &mut grid_query {
+        debug!("Adding {} garbage rows", rows);
+        grid.add_random_garbage(rows as usize, random_source.as_mut());
+        stats.garbage = rows;
+    }
+}
+
+fn setup_puzzle(
+    mut commands: Commands,
+    mode: Res<GameMode>,
+    puzzles: Res<Puzzles>,
+    mut grid_query: Query<(Entity, &GridIndex, &mut Grid)>,
+) {
+    let puzzle = match *mode {
+        GameMode::Puzzle { index } => puzzles.0.get(index),
+        _ => None,
+    };
+    for (entity, index, mut grid) in &mut grid_query {
+        let Some(puzzle) = puzzle else {
+            commands.entity(entity).remove::<PieceQueue>();
+            continue;
+        };
+        let Some(puzzle_grid) = puzzle.grids.get(index.0) else {
+            commands.entity(entity).insert(PieceQueue::default());
+            continue;
+        };
+        match Grid::with_visible_rows(grid.config(), &puzzle_grid.rows) {
+            Ok(start) => *grid = start,
+            Err(e) => warn!(
+                "Puzzle {} has an invalid grid {}: {}",
+                puzzle.name, index.0, e
+            ),
+        }
+        commands.entity(entity).insert(PieceQueue(
+            puzzle_grid
+                .pieces
+                .iter()
+                .map(|tetromino_type| tetromino_type.piece())
+                .collect(),
+        ));
+    }
+}
+
+/// The next piece for a grid, from its queue if it has one and otherwise drawn from `pieces`.
+/// `None` once the queue runs out.
+fn next_piece(
+    queue: Option<&mut PieceQueue>,
+    pieces: &PieceSet,
+    rng: &mut RandomSource,
+) -> Option<Piece> {
+    match queue {
+        Some(queue) => queue.0.pop_front(),
+        None => Some(pieces.random(rng)),
+    }
 }
 
 fn init_spawn_tetrominos(
     mut commands: Commands,
+    level: Res<Level>,
+    pieces: Res<PieceSet>,
     mut random_source: ResMut<RandomSource>,
-    mut grid_query: Query<(Entity, &mut Grid)>,
+    mut grid_query: Query<(Entity, &mut Grid, Option<&mut PieceQueue>)>,
+    mut pieces_spawned: EventWriter<PieceSpawnedEvent>,
     mut draw_grid: EventWriter<DrawGrid>,
 ) {
-    for (entity, mut grid) in &mut grid_query {
+    for (entity, mut grid, mut queue) in &mut grid_query {
         debug!("Spawning a tetromino");
-        let tetromino = ControlledTetromino::new(random_source.as_mut());
-        grid.set_tetromino(&tetromino);
+        let Some((piece, tetromino)) =
+            next_piece(queue.as_deref_mut(), &pieces, random_source.as_mut())
+                .and_then(|piece| grid.spawn_piece(&piece).map(|tetromino| (piece, tetromino)))
+        else {
+            draw_grid.send(DrawGrid(entity));
+            continue;
+        };
+        pieces_spawned.send(PieceSpawnedEvent(entity, piece));
         let shadow = grid.controlled_tetromino_shadow(&tetromino);
         commands.spawn((shadow, Shadow, GridTetromino::new(entity)));
         commands.spawn((
-            TetrominoTimer::default(),
+            TetrominoTimer::from_interval(level.gravity_interval()),
+            InputLog::new(&tetromino),
+            LastMove::default(),
             tetromino,
             GridTetromino::new(entity),
         ));
@@ -102,10 +369,12 @@ fn swap_focus(
     mut commands: Commands,
     mut focus_grid: Query<Entity, With<Focus>>,
     mut non_focus_grid: Query<Entity, (With<Grid>, Without<Focus>)>,
+    mut sounds: EventWriter<SoundEvent>,
     mut draw_grid: EventWriter<DrawGrid>,
 ) {
     if input.just_pressed(KeyCode::KeyF) {
         debug!("Swapping focus");
+        sounds.send(SoundEvent(Sound::FocusSwap));
         for entity in &mut focus_grid {
             commands.entity(entity).remove::<Focus>();
             draw_grid.send(DrawGrid(entity));
@@ -117,109 +386,389 @@ fn swap_focus(
     }
 }
 
-fn handle_input(
+/// Filters for the grid the keyboard controls.
+type FocusedGrid = (With<Grid>, With<Focus>);
+
+fn toggle_bot(
     input: Res<ButtonInput<KeyCode>>,
-    mut grid: Query<(Entity, &mut Grid), With<Focus>>,
-    mut tetromino: Query<(&GridTetromino, &mut ControlledTetromino), Without<Shadow>>,
-    mut shadows: Query<(&GridTetromino, &mut ControlledTetromino), With<Shadow>>,
-    mut draw_grid: EventWriter<DrawGrid>,
+    mut commands: Commands,
+    bot_settings: Res<BotSettings>,
+    focus_grid: Query<(Entity, Option<&Bot>), FocusedGrid>,
 ) {
-    for (entity, mut grid) in &mut grid {
-        for (grid_owner, mut tetromino) in &mut tetromino {
-            if grid_owner.get() != entity {
-                continue;
+    if input.just_pressed(KeyCode::KeyB) {
+        for (entity, bot) in &focus_grid {
+            if bot.is_some() {
+                debug!("Disabling bot");
+                commands.entity(entity).remove::<Bot>();
+            } else {
+                debug!("Enabling bot");
+                commands.entity(entity).insert(Bot::new(&bot_settings));
             }
-            let shadow = shadows
-                .iter_mut()
-                .find(|(shadow_owner, _)| shadow_owner.get() == entity);
+        }
+    }
+}
 
-            if input.just_pressed(KeyCode::ArrowLeft) && !grid.is_tetromino_blocked_left(&tetromino)
-            {
-                debug!("Moving tetromino left");
-                grid.unset_tetromino(tetromino.as_ref());
-                tetromino.top_left.0 -= 1;
-                grid.set_tetromino(tetromino.as_ref());
+fn track_bot_assistance(
+    mut bot_assisted: ResMut<BotAssisted>,
+    bots: Query<(), (With<Grid>, With<Bot>)>,
+) {
+    if !bots.is_empty() {
+        bot_assisted.0 = true;
+    }
+}
+
+fn keyboard_input(
+    input: Res<ButtonInput<KeyCode>>,
+    grid: Query<Entity, (FocusedGrid, Without<Bot>)>,
+    mut actions: EventWriter<GridActionEvent>,
+) {
+    for entity in &grid {
+        for (key, action) in [
+            (KeyCode::ArrowLeft, GridAction::MoveLeft),
+            (KeyCode::ArrowRight, GridAction::MoveRight),
+            (KeyCode::Space, GridAction::Rotate),
+            (KeyCode::ArrowDown, GridAction::HardDrop),
+        ] {
+            if input.just_pressed(key) {
+                actions.send(GridActionEvent(entity, action));
             }
+        }
+    }
+}
 
-            if input.just_pressed(KeyCode::ArrowRight)
-                && !grid.is_tetromino_blocked_right(&tetromino)
-            {
-                debug!("Moving tetromino right");
-                grid.unset_tetromino(tetromino.as_ref());
-                tetromino.top_left.0 += 1;
-                grid.set_tetromino(tetromino.as_ref());
+fn bot_input(
+    time: Res<Time>,
+    mut bots: Query<(Entity, &Grid, &mut Bot, Option<&PieceQueue>)>,
+    tetromino: Query<(Entity, &GridTetromino, &ControlledTetromino), With<TetrominoTimer>>,
+    mut actions: EventWriter<GridActionEvent>,
+) {
+    for (entity, grid, mut bot, queue) in &mut bots {
+        let Some((tetromino_id, _, tetromino)) = tetromino
+            .iter()
+            .find(|(_, grid_owner, _)| grid_owner.get() == entity)
+        else {
+            continue;
+        };
+        if bot.needs_plan(tetromino_id, tetromino) {
+            let next = queue
+                .and_then(|queue| queue.0.front())
+                .and_then(|piece| ControlledTetromino::new_with_piece(piece, grid));
+            bot.plan(tetromino_id, grid, tetromino, next.as_ref());
+        }
+        for action in bot.tick(time.delta()) {
+            actions.send(GridActionEvent(entity, action));
+        }
+    }
+}
+
+fn update_shadow(
+    entity: Entity,
+    grid: &Grid,
+    tetromino: &ControlledTetromino,
+    shadows: &mut Query<(&GridTetromino, &mut ControlledTetromino), With<Shadow>>,
+) {
+    if let Some((_, mut shadow)) = shadows
+        .iter_mut()
+        .find(|(shadow_owner, _)| shadow_owner.get() == entity)
+    {
+        *shadow = grid.controlled_tetromino_shadow(tetromino);
+    }
+}
+
+fn handle_input(
+    mut actions: EventReader<GridActionEvent>,
+    mut grid: Query<(&mut Grid, Has<Bot>)>,
+    mut tetromino: Query<
+        (
+            &GridTetromino,
+            &mut ControlledTetromino,
+            &mut InputLog,
+            &mut LastMove,
+        ),
+        Without<Shadow>,
+    >,
+    mut shadows: Query<(&GridTetromino, &mut ControlledTetromino), With<Shadow>>,
+    mut sounds: EventWriter<SoundEvent>,
+    mut draw_grid: EventWriter<DrawGrid>,
+) {
+    for GridActionEvent(entity, action) in actions.read() {
+        let Ok((mut grid, is_bot)) = grid.get_mut(*entity) else {
+            continue;
+        };
+        for (grid_owner, mut tetromino, mut input_log, mut last_move) in &mut tetromino {
+            if grid_owner.get() != *entity {
+                continue;
             }
+            input_log.record(*action);
 
-            if input.just_pressed(KeyCode::Space) {
-                debug!("Rotating tetromino");
-                let old_rotation = tetromino.rotation;
-                grid.unset_tetromino(tetromino.as_ref());
-                tetromino.rotate();
-                if !grid.is_tetromino_space_open(&tetromino) {
-                    tetromino.rotation = old_rotation;
+            let mut sound = None;
+            match action {
+                GridAction::MoveLeft if !grid.is_tetromino_blocked_left(&tetromino) => {
+                    debug!("Moving tetromino left");
+                    grid.unset_tetromino(tetromino.as_ref());
+                    tetromino.top_left.0 -= 1;
+                    grid.set_tetromino(tetromino.as_ref());
+                    last_move.0 = Some(*action);
+                    sound = Some(Sound::Move);
                 }
-                grid.set_tetromino(tetromino.as_ref());
+                GridAction::MoveRight if !grid.is_tetromino_blocked_right(&tetromino) => {
+                    debug!("Moving tetromino right");
+                    grid.unset_tetromino(tetromino.as_ref());
+                    tetromino.top_left.0 += 1;
+                    grid.set_tetromino(tetromino.as_ref());
+                    last_move.0 = Some(*action);
+                    sound = Some(Sound::Move);
+                }
+                GridAction::Rotate => {
+                    debug!("Rotating tetromino");
+                    grid.unset_tetromino(tetromino.as_ref());
+                    if let Some(rotated) = grid.rotated(&tetromino) {
+                        *tetromino = rotated;
+                        last_move.0 = Some(*action);
+                        sound = Some(Sound::Rotate);
+                    }
+                    grid.set_tetromino(tetromino.as_ref());
+                }
+                GridAction::SoftDrop if !grid.is_tetromino_at_bottom(&tetromino) => {
+                    debug!("Soft dropping tetromino");
+                    grid.unset_tetromino(tetromino.as_ref());
+                    tetromino.top_left.1 += 1;
+                    grid.set_tetromino(tetromino.as_ref());
+                    last_move.0 = Some(*action);
+                }
+                // Hard drops lock the piece and are handled by `handle_timed_movement`.
+                _ => {}
             }
-
-            if let Some((_, mut shadow)) = shadow {
-                *shadow = grid.controlled_tetromino_shadow(tetromino.as_ref());
+            // A bot moves far too quickly for every move to make a sound.
+            if let Some(sound) = sound.filter(|_| !is_bot) {
+                sounds.send(SoundEvent(sound));
             }
 
-            draw_grid.send(DrawGrid(entity));
+            update_shadow(*entity, &grid, &tetromino, &mut shadows);
+            draw_grid.send(DrawGrid(*entity));
         }
     }
 }
 
+fn record_finesse(
+    entity: Entity,
+    board: &Grid,
+    input_log: &InputLog,
+    tetromino: &ControlledTetromino,
+    finesse_stats: &mut FinesseStats,
+    finesse_faults: &mut EventWriter<FinesseFaultEvent>,
+) {
+    if let Some(result) = finesse::analyze(board, input_log, tetromino) {
+        finesse_stats.record(result);
+        if result.is_fault() {
+            finesse_faults.send(FinesseFaultEvent(entity, result));
+        }
+    }
+}
+
+/// Removes the full rows left by a locked piece and updates the grid's stats, returning the
+/// number of cleared rows.
+fn clear_rows(grid: &mut Grid, stats: &mut GridStats, is_t_spin: bool) -> u32 {
+    let lines = grid.clear_full_grid_rows();
+    stats.lines += lines;
+    stats.garbage = u32::try_from(grid.garbage_rows()).unwrap_or(u32::MAX);
+    if is_t_spin && lines == 2 {
+        debug!("T-spin double");
+        stats.t_spin_doubles += 1;
+    }
+    if lines > 0 && grid.set_coords_iter().next().is_none() {
+        debug!("Perfect clear");
+        stats.perfect_clears += 1;
+    }
+    lines
+}
+
+/// A falling piece with what gravity and locking need from it.
+type FallingPiece<'a> = (
+    Entity,
+    &'a GridTetromino,
+    &'a mut ControlledTetromino,
+    &'a mut TetrominoTimer,
+    &'a InputLog,
+    &'a mut LastMove,
+);
+
 fn handle_timed_movement(
     mut commands: Commands,
     time: Res<Time>,
-    input: Res<ButtonInput<KeyCode>>,
-    mut random_source: ResMut<RandomSource>,
-    mut grid: Query<(Entity, &mut Grid, Option<&Focus>)>,
-    mut tetromino: Query<(
+    delays: Res<Delays>,
+    mut actions: EventReader<GridActionEvent>,
+    mut grid: Query<(
         Entity,
-        &GridTetromino,
-        &mut ControlledTetromino,
-        &mut TetrominoTimer,
+        &mut Grid,
+        &mut GridStats,
+        &mut GridPhase,
+        Option<&Bot>,
     )>,
+    mut tetromino: Query<FallingPiece, Without<Shadow>>,
+    mut shadows: Query<(&GridTetromino, &mut ControlledTetromino), With<Shadow>>,
     mut next_state: ResMut<NextState<TetrisState>>,
-    mut rows_cleared: EventWriter<RowClearedEvent>,
+    mut finesse_stats: ResMut<FinesseStats>,
+    mut finesse_faults: EventWriter<FinesseFaultEvent>,
+    mut effects: EventWriter<EffectEvent>,
+    mut sounds: EventWriter<SoundEvent>,
     mut draw_grid: EventWriter<DrawGrid>,
 ) {
-    for (entity, mut grid, focus) in &mut grid {
-        for (tetromino_id, grid_owner, mut tetromino, mut timer) in &mut tetromino {
+    let hard_drops: Vec<Entity> = actions
+        .read()
+        .filter(|GridActionEvent(_, action)| *action == GridAction::HardDrop)
+        .map(|GridActionEvent(entity, _)| *entity)
+        .collect();
+    for (entity, mut grid, mut stats, mut phase, bot) in &mut grid {
+        for (tetromino_id, grid_owner, mut tetromino, mut timer, input_log, mut last_move) in
+            &mut tetromino
+        {
             if grid_owner.get() != entity {
                 continue;
             }
             timer.0.tick(time.delta());
-            let should_force_to_bottom = input.just_pressed(KeyCode::ArrowDown) && focus.is_some();
+            let should_force_to_bottom = hard_drops.contains(&entity);
             if should_force_to_bottom {
+                let top = tetromino.top_left.1;
                 grid.unset_tetromino(tetromino.as_ref());
                 grid.force_tetromino_to_bottom(tetromino.as_mut());
                 grid.set_tetromino(tetromino.as_ref());
+                if tetromino.top_left.1 != top {
+                    last_move.0 = Some(GridAction::HardDrop);
+                }
+                effects.send(EffectEvent::HardDrop {
+                    grid: entity,
+                    cells: tetromino.coords(&grid).collect(),
+                    distance: tetromino.top_left.1 - top,
+                });
             }
 
-            if timer.0.finished() || should_force_to_bottom {
-                if grid.is_tetromino_at_bottom(tetromino.as_ref()) {
-                    debug!("Tetromino at bottom, despawning and spawning a new one");
-                    rows_cleared.send(RowClearedEvent::new(grid.clear_full_grid_rows()));
+            // Gravity only moves a falling piece; a resting one locks once its lock delay is
+            // over, or straight away when hard dropped.
+            let resting = grid.is_tetromino_at_bottom(tetromino.as_ref());
+            let lock_delay_over =
+                matches!(&*phase, GridPhase::Locking { timer } if timer.finished());
+            let lock = resting && (should_force_to_bottom || lock_delay_over);
+            if (timer.0.finished() && !resting) || lock {
+                if resting {
+                    debug!("Tetromino at bottom, locking it");
                     commands.entity(tetromino_id).despawn();
-                    let tetromino = ControlledTetromino::new(random_source.as_mut());
-                    if grid.is_tetromino_space_open(&tetromino) {
-                        grid.set_tetromino(&tetromino);
-                        commands.spawn((
-                            TetrominoTimer::default(),
-                            tetromino,
-                            GridTetromino::new(entity),
-                        ));
-                    } else {
+                    if bot.is_none() {
+                        sounds.send(SoundEvent(Sound::Lock));
+                    }
+                    let mut board = grid.clone();
+                    board.unset_tetromino(tetromino.as_ref());
+                    let is_t_spin = last_move.0 == Some(GridAction::Rotate)
+                        && board.is_t_spin_position(&tetromino);
+                    if bot.is_none() {
+                        record_finesse(
+                            entity,
+                            &board,
+                            input_log,
+                            &tetromino,
+                            &mut finesse_stats,
+                            &mut finesse_faults,
+                        );
+                    }
+                    if grid.is_tetromino_above_visible(tetromino.as_ref()) {
+                        debug!("Tetromino locked out above the visible field");
                         next_state.set(TetrisState::GameOver);
+                        continue;
                     }
+                    stats.pieces += 1;
+                    *phase = GridPhase::after_lock(&grid, is_t_spin, &delays);
                 } else {
                     debug!("Moving tetromino down");
                     grid.unset_tetromino(tetromino.as_ref());
                     tetromino.top_left.1 += 1;
                     grid.set_tetromino(tetromino.as_ref());
+                    last_move.0 = Some(GridAction::SoftDrop);
+                    update_shadow(entity, &grid, &tetromino, &mut shadows);
+                }
+                draw_grid.send(DrawGrid(entity));
+            }
+
+            if !phase.is_between_pieces() {
+                let resting = grid.is_tetromino_at_bottom(tetromino.as_ref());
+                match &mut *phase {
+                    GridPhase::Locking { timer } if resting => {
+                        timer.tick(time.delta());
+                    }
+                    GridPhase::Locking { .. } => *phase = GridPhase::Falling,
+                    _ if resting => *phase = GridPhase::locking(&delays),
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+/// Flashes full rows before removing them, then spawns the next piece once the entry delay is
+/// over.
+fn advance_grid_phases(
+    mut commands: Commands,
+    time: Res<Time>,
+    level: Res<Level>,
+    delays: Res<Delays>,
+    pieces: Res<PieceSet>,
+    mut random_source: ResMut<RandomSource>,
+    mut grid: Query<(
+        Entity,
+        &mut Grid,
+        &mut GridStats,
+        &mut GridPhase,
+        Option<&mut PieceQueue>,
+    )>,
+    mut shadows: Query<(&GridTetromino, &mut ControlledTetromino), With<Shadow>>,
+    mut next_state: ResMut<NextState<TetrisState>>,
+    mut rows_cleared: EventWriter<RowClearedEvent>,
+    mut pieces_spawned: EventWriter<PieceSpawnedEvent>,
+    mut effects: EventWriter<EffectEvent>,
+    mut draw_grid: EventWriter<DrawGrid>,
+) {
+    for (entity, mut grid, mut stats, mut phase, mut queue) in &mut grid {
+        match &mut *phase {
+            GridPhase::Falling | GridPhase::Locking { .. } => {}
+            GridPhase::Clearing {
+                rows,
+                t_spin,
+                timer,
+            } => {
+                timer.tick(time.delta());
+                if timer.finished() {
+                    effects.send(EffectEvent::LinesCleared {
+                        grid: entity,
+                        rows: std::mem::take(rows),
+                        t_spin: *t_spin,
+                    });
+                    let lines = clear_rows(&mut grid, &mut stats, *t_spin);
+                    rows_cleared.send(RowClearedEvent::new(entity, lines));
+                    *phase = GridPhase::spawning(&delays);
+                }
+                draw_grid.send(DrawGrid(entity));
+            }
+            GridPhase::Spawning { timer } => {
+                if !timer.tick(time.delta()).finished() {
+                    continue;
+                }
+                *phase = GridPhase::Falling;
+                let Some(piece) = next_piece(queue.as_deref_mut(), &pieces, random_source.as_mut())
+                else {
+                    debug!("Out of pieces");
+                    continue;
+                };
+                if let Some(tetromino) = grid.spawn_piece(&piece) {
+                    pieces_spawned.send(PieceSpawnedEvent(entity, piece));
+                    update_shadow(entity, &grid, &tetromino, &mut shadows);
+                    commands.spawn((
+                        TetrominoTimer::from_interval(level.gravity_interval()),
+                        InputLog::new(&tetromino),
+                        LastMove::default(),
+                        tetromino,
+                        GridTetromino::new(entity),
+                    ));
+                } else {
+                    next_state.set(TetrisState::GameOver);
                 }
                 draw_grid.send(DrawGrid(entity));
             }
@@ -227,110 +776,631 @@ fn handle_timed_movement(
     }
 }
 
+/// Shows `title` and `details` along with the restart and mode selection keys.
+fn spawn_end_screen(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    theme: &Theme,
+    title: &str,
+    details: &str,
+    pieces: &PieceSet,
+) {
+    let mut menu = details.to_string();
+    if !menu.is_empty() {
+        menu.push('\n');
+    }
+    menu.push_str("R: Restart");
+    for (i, mode) in GameMode::ALL.iter().enumerate() {
+        let _ = write!(menu, "\n{}: {}", i + 1, mode.name());
+    }
+    let _ = write!(menu, "\nC: Pieces: {}", pieces.name);
+    menu.push_str("\nP: Puzzles\nH: High Scores\nS: Stats");
+    spawn_menu(commands, asset_server, theme, GameOver, title, menu);
+}
+
+/// Shows a large `title` over `menu`, tagged with `marker` so it can be despawned again.
+fn spawn_menu(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    theme: &Theme,
+    marker: impl Component,
+    title: &str,
+    menu: String,
+) {
+    commands.spawn((
+        marker,
+        ThemedText(vec![TextRole::Title, TextRole::Body]),
+        LayoutAnchor::Menu,
+        TextBundle::from_sections([
+            TextSection::new(
+                format!("{title}\n"),
+                theme.text_style(TextRole::Title, asset_server),
+            ),
+            TextSection::new(menu, theme.text_style(TextRole::Body, asset_server)),
+        ]),
+    ));
+}
+
+/// Level, lines, score and time for the marathon results screen.
+fn marathon_results(
+    level: Level,
+    grids: &Query<&GridStats>,
+    score: &Query<&Score>,
+    time: Duration,
+) -> String {
+    format!(
+        "Level: {}\nLines: {}\nScore: {}\nTime: {}",
+        level.0,
+        grids.iter().map(|stats| stats.lines).sum::<u32>(),
+        score.iter().map(|score| score.get()).sum::<u32>(),
+        format_duration(time)
+    )
+}
+
+fn main_menu(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    theme: Res<Theme>,
+    pieces: Res<PieceSet>,
+    tetromino: Query<Entity, With<ControlledTetromino>>,
+) {
+    for entity_id in tetromino.iter() {
+        commands.entity(entity_id).despawn();
+    }
+    spawn_end_screen(&mut commands, &asset_server, &theme, "Twotris", "", &pieces);
+}
+
 fn game_over(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
+    theme: Res<Theme>,
+    mode: Res<GameMode>,
+    level: Res<Level>,
+    clock: Res<GameClock>,
+    pieces: Res<PieceSet>,
+    grids: Query<&GridStats>,
+    score: Query<&Score>,
     tetromino: Query<Entity, With<ControlledTetromino>>,
+    mut sounds: EventWriter<SoundEvent>,
 ) {
     for entity_id in tetromino.iter() {
         commands.entity(entity_id).despawn();
     }
-    commands.spawn((
-        GameOver,
-        TextBundle::from_section(
-            "Game Over\nR: Restart".to_string(),
-            TextStyle {
-                font: asset_server.load("fonts/JetBrainsMono-Bold.ttf"),
-                font_size: 72.0,
-                color: Color::WHITE,
+    sounds.send(SoundEvent(Sound::GameOver));
+    let details = match *mode {
+        GameMode::Marathon { .. } => marathon_results(*level, &grids, &score, clock.elapsed()),
+        GameMode::Sprint { .. }
+        | GameMode::Ultra { .. }
+        | GameMode::Dig { .. }
+        | GameMode::Puzzle { .. } => String::new(),
+    };
+    let title = match *mode {
+        GameMode::Puzzle { .. } => "Puzzle Failed",
+        _ => "Game Over",
+    };
+    spawn_end_screen(
+        &mut commands,
+        &asset_server,
+        &theme,
+        title,
+        &details,
+        &pieces,
+    );
+}
+
+fn finish(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    theme: Res<Theme>,
+    mode: Res<GameMode>,
+    level: Res<Level>,
+    clock: Res<GameClock>,
+    pieces: Res<PieceSet>,
+    bot_assisted: Res<BotAssisted>,
+    splits: Res<Splits>,
+    mut personal_bests: ResMut<PersonalBests>,
+    grids: Query<&GridStats>,
+    score: Query<&Score>,
+    tetromino: Query<Entity, With<ControlledTetromino>>,
+) {
+    for entity_id in tetromino.iter() {
+        commands.entity(entity_id).despawn();
+    }
+    let time = clock.elapsed();
+    // Compared against the splits of the best this run is about to replace.
+    let best_splits = match *mode {
+        GameMode::Sprint { per_grid } => personal_bests.sprint_splits(per_grid).to_vec(),
+        _ => vec![],
+    };
+    let title = format!("{} Complete", mode.name());
+    if let GameMode::Marathon { .. } = *mode {
+        let details = marathon_results(*level, &grids, &score, time);
+        spawn_end_screen(
+            &mut commands,
+            &asset_server,
+            &theme,
+            &title,
+            &details,
+            &pieces,
+        );
+        return;
+    }
+
+    // Only runs played entirely by hand count as personal bests.
+    let (new_best, best) = match *mode {
+        GameMode::Sprint { per_grid } => (
+            !bot_assisted.0 && personal_bests.record_sprint(per_grid, time, &splits.0),
+            if per_grid {
+                personal_bests.sprint_per_grid
+            } else {
+                personal_bests.sprint
             },
-        )
-        .with_style(Style {
-            position_type: PositionType::Absolute,
-            top: Val::Px(500.0),
-            left: Val::Px(600.0),
-            ..default()
-        }),
-    ));
+        ),
+        GameMode::Dig { rows } => (
+            !bot_assisted.0 && personal_bests.record_dig(rows, time),
+            personal_bests.dig.get(&rows).copied(),
+        ),
+        GameMode::Marathon { .. } | GameMode::Ultra { .. } | GameMode::Puzzle { .. } => {
+            (false, None)
+        }
+    };
+    if new_best {
+        save_personal_bests(&personal_bests);
+    }
+
+    let mut details = format!("Time: {}", format_duration(time));
+    if !splits.0.is_empty() {
+        let _ = write!(details, "\n{}", format_splits(&splits.0, &best_splits));
+    }
+    if bot_assisted.0 {
+        details.push_str("\nBot assisted, not counted as a personal best");
+    }
+    if new_best {
+        details.push_str("\nNew personal best!");
+    } else if let Some(best) = best {
+        let _ = write!(details, "\nPersonal best: {}", format_duration(best));
+    }
+    spawn_end_screen(
+        &mut commands,
+        &asset_server,
+        &theme,
+        &title,
+        &details,
+        &pieces,
+    );
 }
 
-fn reset(
+fn time_up(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    theme: Res<Theme>,
+    mode: Res<GameMode>,
+    pieces: Res<PieceSet>,
+    bot_assisted: Res<BotAssisted>,
+    mut personal_bests: ResMut<PersonalBests>,
+    score: Query<&Score>,
+    tetromino: Query<Entity, With<ControlledTetromino>>,
+) {
+    for entity_id in tetromino.iter() {
+        commands.entity(entity_id).despawn();
+    }
+    let score = score.iter().map(|score| score.get()).sum();
+    let GameMode::Ultra { minutes } = *mode else {
+        return;
+    };
+    let new_best = !bot_assisted.0 && personal_bests.record_ultra(minutes, score);
+    if new_best {
+        save_personal_bests(&personal_bests);
+    }
+
+    let mut details = format!("Score: {score}");
+    if bot_assisted.0 {
+        details.push_str("\nBot assisted, not counted as a personal best");
+    }
+    if new_best {
+        details.push_str("\nNew personal best!");
+    } else if let Some(best) = personal_bests.ultra.get(&minutes) {
+        let _ = write!(details, "\nPersonal best: {best}");
+    }
+    spawn_end_screen(
+        &mut commands,
+        &asset_server,
+        &theme,
+        "Time's Up",
+        &details,
+        &pieces,
+    );
+}
+
+fn puzzle_select(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    theme: Res<Theme>,
+    puzzles: Res<Puzzles>,
+) {
+    let mut menu = String::new();
+    for (i, puzzle) in puzzles.0.iter().take(MODE_KEYS.len()).enumerate() {
+        let _ = writeln!(menu, "{}: {} ({})", i + 1, puzzle.name, puzzle.goal);
+    }
+    if puzzles.0.is_empty() {
+        menu.push_str("No puzzles found\n");
+    }
+    menu.push_str("Esc: Back");
+    spawn_menu(
+        &mut commands,
+        &asset_server,
+        &theme,
+        GameOver,
+        "Puzzles",
+        menu,
+    );
+}
+
+fn select_puzzle(
     mut next_state: ResMut<NextState<TetrisState>>,
     mut commands: Commands,
     input: Res<ButtonInput<KeyCode>>,
-    gameover: Query<Entity, With<GameOver>>,
+    puzzles: Res<Puzzles>,
+    mut mode: ResMut<GameMode>,
+    menu: Query<Entity, With<GameOver>>,
 ) {
-    if input.just_pressed(KeyCode::KeyR) {
+    let selected = MODE_KEYS
+        .iter()
+        .take(puzzles.0.len())
+        .position(|key| input.just_pressed(*key));
+    if let Some(index) = selected {
+        debug!("Starting puzzle {}", puzzles.0[index].name);
+        *mode = GameMode::Puzzle { index };
+    }
+    if selected.is_some() {
         next_state.set(TetrisState::InGame);
-        for entity_id in gameover.iter() {
+    } else if input.just_pressed(KeyCode::Escape) {
+        next_state.set(TetrisState::Menu);
+    }
+    if selected.is_some() || input.just_pressed(KeyCode::Escape) {
+        for entity_id in menu.iter() {
             commands.entity(entity_id).despawn();
         }
-        commands.remove_resource::<RandomSource>();
-        commands.insert_resource(RandomSource::default());
     }
 }
 
-fn reset_grid(
-    mut commands: Commands,
-    _asset_server: Res<AssetServer>,
-    mut grid: Query<&mut Grid>,
+fn update_puzzle(
+    mode: Res<GameMode>,
+    puzzles: Res<Puzzles>,
+    grids: Query<(&GridStats, &GridPhase, Option<&PieceQueue>)>,
+    tetromino: Query<(), With<TetrominoTimer>>,
+    mut next_state: ResMut<NextState<TetrisState>>,
+) {
+    let GameMode::Puzzle { index } = *mode else {
+        return;
+    };
+    let Some(puzzle) = puzzles.0.get(index) else {
+        return;
+    };
+    let stats: Vec<GridStats> = grids.iter().map(|(stats, _, _)| *stats).collect();
+    if puzzle.goal.is_met(&stats) {
+        debug!("Solved {}", puzzle.name);
+        next_state.set(TetrisState::Finished);
+    } else if tetromino.is_empty()
+        && grids.iter().all(|(_, phase, queue)| {
+            !phase.is_between_pieces() && queue.is_none_or(|queue| queue.0.is_empty())
+        })
+    {
+        debug!("Out of pieces for {}", puzzle.name);
+        next_state.set(TetrisState::GameOver);
+    }
+}
+
+/// Asks for a name if the game that just ended made its mode's high score table.
+fn offer_high_score(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    theme: Res<Theme>,
+    state: Res<State<TetrisState>>,
+    mode: Res<GameMode>,
+    level: Res<Level>,
+    clock: Res<GameClock>,
+    random_source: Res<RandomSource>,
+    high_scores: Res<HighScores>,
+    grids: Query<&GridStats>,
+    score: Query<&Score>,
+) {
+    if !HighScores::is_eligible(*mode, *state.get() == TetrisState::Finished) {
+        return;
+    }
+    let entry = HighScore {
+        name: String::new(),
+        score: score.iter().next().map_or(0, |score| score.get()),
+        lines: grids.iter().map(|stats| stats.lines).sum(),
+        level: level.0,
+        duration: clock.elapsed(),
+        seed: random_source.seed(),
+        date: HighScore::now(),
+    };
+    let Some(rank) = high_scores.rank(*mode, &entry) else {
+        return;
+    };
+    debug!("New {} high score at #{}", mode.name(), rank + 1);
+    commands.spawn((
+        GameOver,
+        HighScorePrompt,
+        ThemedText(vec![TextRole::Highlight, TextRole::Body]),
+        LayoutAnchor::Panel,
+        TextBundle::from_sections([
+            TextSection::new(
+                format!("New high score! #{}\n", rank + 1),
+                theme.text_style(TextRole::Highlight, &asset_server),
+            ),
+            TextSection::new(
+                name_prompt(""),
+                theme.text_style(TextRole::Body, &asset_server),
+            ),
+        ]),
+    ));
+    commands.insert_resource(PendingHighScore { mode: *mode, entry });
+}
+
+fn name_prompt(name: &str) -> String {
+    format!("Name: {name}_\nEnter: Save")
+}
+
+/// Types the name for a new high score, saving it to the table on Enter.
+fn enter_high_score_name(
+    mut commands: Commands,
+    mut keys: EventReader<KeyboardInput>,
+    mut pending: ResMut<PendingHighScore>,
+    mut high_scores: ResMut<HighScores>,
+    mut prompt: Query<&mut Text, With<HighScorePrompt>>,
+) {
+    let mut confirmed = false;
+    for key in keys.read() {
+        if !key.state.is_pressed() {
+            continue;
+        }
+        let name = &mut pending.entry.name;
+        match &key.logical_key {
+            Key::Character(characters) => {
+                for character in characters.chars().filter(|c| !c.is_control()) {
+                    if name.chars().count() < MAX_NAME_LEN {
+                        name.push(character);
+                    }
+                }
+            }
+            Key::Space if name.chars().count() < MAX_NAME_LEN => name.push(' '),
+            Key::Backspace => {
+                name.pop();
+            }
+            Key::Enter => confirmed = true,
+            _ => {}
+        }
+    }
+
+    let message = if confirmed {
+        let PendingHighScore { mode, mut entry } = pending.clone();
+        entry.name = entry.name.trim().to_string();
+        if entry.name.is_empty() {
+            entry.name = "Anonymous".to_string();
+        }
+        let name = entry.name.clone();
+        high_scores.insert(mode, entry);
+        if let Err(e) = high_scores.write() {
+            warn!("Failed to save high scores: {}", e);
+        }
+        commands.remove_resource::<PendingHighScore>();
+        format!("Saved as {name}")
+    } else if pending.is_changed() {
+        name_prompt(&pending.entry.name)
+    } else {
+        return;
+    };
+    for mut text in &mut prompt {
+        text.sections[1].value.clone_from(&message);
+    }
+}
+
+fn load_high_scores(mut high_scores: ResMut<HighScores>) {
+    match HighScores::read() {
+        Ok(loaded) => *high_scores = loaded,
+        Err(e) => warn!("Failed to load high scores: {}", e),
+    }
+}
+
+fn show_high_scores(text: &mut Text, high_scores: &HighScores, mode: GameMode) {
+    text.sections[0].value = format!("High Scores: {}\n", mode.name());
+    text.sections[1].value = format!("{}\n", high_scores.format_table(mode));
+}
+
+fn high_score_screen(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    theme: Res<Theme>,
+    mode: Res<GameMode>,
+    high_scores: Res<HighScores>,
+) {
+    let index = GameMode::ALL
+        .iter()
+        .position(|listed| *listed == *mode)
+        .unwrap_or_default();
+    let roles = vec![TextRole::Header, TextRole::Small, TextRole::Body];
+    let style = |role| theme.text_style(role, &asset_server);
+    let mut text = Text::from_sections([
+        TextSection::new(String::new(), style(roles[0])),
+        TextSection::new(String::new(), style(roles[1])),
+        TextSection::new("Left/Right: Mode\nEsc: Menu", style(roles[2])),
+    ]);
+    show_high_scores(&mut text, &high_scores, GameMode::ALL[index]);
+    commands.spawn((
+        GameOver,
+        HighScoreTable(index),
+        ThemedText(roles),
+        LayoutAnchor::Panel,
+        TextBundle { text, ..default() },
+    ));
+}
+
+fn browse_high_scores(
+    mut next_state: ResMut<NextState<TetrisState>>,
+    mut commands: Commands,
+    input: Res<ButtonInput<KeyCode>>,
+    high_scores: Res<HighScores>,
+    mut table: Query<(&mut HighScoreTable, &mut Text)>,
+    screen: Query<Entity, With<GameOver>>,
+) {
+    if input.just_pressed(KeyCode::Escape) {
+        next_state.set(TetrisState::Menu);
+        for entity_id in &screen {
+            commands.entity(entity_id).despawn();
+        }
+        return;
+    }
+    let modes = GameMode::ALL.len();
+    let step = if input.just_pressed(KeyCode::ArrowRight) {
+        1
+    } else if input.just_pressed(KeyCode::ArrowLeft) {
+        modes - 1
+    } else {
+        return;
+    };
+    for (mut table, mut text) in &mut table {
+        table.0 = (table.0 + step) % modes;
+        show_high_scores(&mut text, &high_scores, GameMode::ALL[table.0]);
+    }
+}
+
+fn reset(
+    mut next_state: ResMut<NextState<TetrisState>>,
+    mut commands: Commands,
+    input: Res<ButtonInput<KeyCode>>,
+    mut mode: ResMut<GameMode>,
+    piece_sets: Res<PieceSets>,
+    grid_configs: Res<GridConfigs>,
+    mut pieces: ResMut<PieceSet>,
+    gameover: Query<Entity, With<GameOver>>,
+) {
+    let screen = if input.just_pressed(KeyCode::KeyP) {
+        Some(TetrisState::PuzzleSelect)
+    } else if input.just_pressed(KeyCode::KeyH) {
+        Some(TetrisState::HighScores)
+    } else if input.just_pressed(KeyCode::KeyS) {
+        Some(TetrisState::Stats)
+    } else {
+        None
+    };
+    if let Some(screen) = screen {
+        next_state.set(screen);
+        for entity_id in gameover.iter() {
+            commands.entity(entity_id).despawn();
+        }
+        return;
+    }
+    let selected = MODE_KEYS
+        .iter()
+        .zip(GameMode::ALL)
+        .find(|(key, _)| input.just_pressed(**key))
+        .map(|(_, mode)| mode);
+    if let Some(selected) = selected {
+        debug!("Switching to {}", selected.name());
+        *mode = selected;
+    }
+    let cycle_pieces = input.just_pressed(KeyCode::KeyC);
+    if cycle_pieces {
+        *pieces = next_piece_set(&piece_sets, &grid_configs, &pieces);
+        debug!("Switching to {} pieces", pieces.name);
+    }
+    if input.just_pressed(KeyCode::KeyR) || selected.is_some() || cycle_pieces {
+        next_state.set(TetrisState::InGame);
+        for entity_id in gameover.iter() {
+            commands.entity(entity_id).despawn();
+        }
+        commands.remove_resource::<RandomSource>();
+        commands.insert_resource(RandomSource::default());
+    }
+}
+
+fn reset_grid(
+    mut commands: Commands,
+    theme: Res<Theme>,
+    grid_configs: Res<GridConfigs>,
+    pieces: Res<PieceSet>,
+    mut finesse_stats: ResMut<FinesseStats>,
+    mut clock: ResMut<GameClock>,
+    mut level: ResMut<Level>,
+    mut bot_assisted: ResMut<BotAssisted>,
+    mut splits: ResMut<Splits>,
+    mut grid: Query<(&mut Grid, &mut GridStats, &mut GridPhase, &mut GridSprites)>,
     mut score: Query<(&mut Score, &mut Text), Without<Grid>>,
     mut visibile_squares: Query<&mut Visibility, With<Coordinate>>,
 ) {
     if grid.iter().len() == 0 {
-        for i in 0..2 {
-            let grid = Grid::default();
+        for (i, config) in grid_configs.0.iter().enumerate() {
+            let mut grid = Grid::new(*config);
+            grid.set_pieces(&pieces);
+            let buffer = grid.buffer_height();
             let height = grid.height();
             let width = grid.width();
             let mut entity = commands.spawn((
                 grid,
-                SpatialBundle {
-                    transform: Transform::from_xyz(-500.0 + (i as f32 * 400.0), 260.0, 0.0),
-                    ..default()
-                },
+                GridIndex(i),
+                GridStats::default(),
+                GridPhase::default(),
+                // Moved into place by `apply_layout`.
+                SpatialBundle::default(),
             ));
             if i == 0 {
                 entity.insert(Focus);
             }
+            let mut squares = vec![];
             entity.with_children(|cb| {
-                for i in 0..height {
+                // Only visible rows get sprites; the buffer above them is never drawn.
+                for i in buffer..buffer + height {
                     for j in 0..width {
-                        cb.spawn((
+                        let mut square = cb.spawn((
                             Coordinate(j, i),
                             SpriteBundle {
                                 transform: Transform::from_xyz(
                                     j as f32 * CELL_SIZE,
-                                    i as f32 * CELL_SIZE * -1.0,
+                                    -((i - buffer) as f32) * CELL_SIZE,
                                     2.0,
                                 ),
                                 visibility: Visibility::Hidden,
                                 sprite: Sprite {
-                                    color: FOCUS_COLOR,
-                                    custom_size: Some(Vec2::splat(CELL_SIZE - 2.0)),
+                                    color: theme.palette.focus.into(),
+                                    custom_size: Some(Vec2::splat(
+                                        theme.cell_sprite_size(CELL_SIZE),
+                                    )),
                                     ..default()
                                 },
                                 ..default()
                             },
-                        ))
-                        .with_children(|cb| {
-                            cb.spawn((SpriteBundle {
-                                transform: Transform::from_xyz(0.0, 0.0, -1.0),
-                                visibility: Visibility::Inherited,
-                                sprite: Sprite {
-                                    color: Color::srgb(0.0, 0.0, 0.0),
-                                    custom_size: Some(Vec2::splat(CELL_SIZE)),
+                        ));
+                        square.with_children(|cb| {
+                            cb.spawn((
+                                CellBackground,
+                                SpriteBundle {
+                                    transform: Transform::from_xyz(0.0, 0.0, -1.0),
+                                    visibility: Visibility::Inherited,
+                                    sprite: Sprite {
+                                        color: theme.palette.grid.into(),
+                                        custom_size: Some(Vec2::splat(CELL_SIZE)),
+                                        ..default()
+                                    },
                                     ..default()
                                 },
-                                ..default()
-                            },));
+                            ));
                         });
+                        squares.push(square.id());
                     }
                 }
             });
+            entity.insert(GridSprites::new(squares));
         }
     } else {
-        for mut grid in &mut grid {
+        for (mut grid, mut stats, mut phase, mut grid_sprites) in &mut grid {
             grid.clear();
+            grid.set_pieces(&pieces);
+            *stats = GridStats::default();
+            *phase = GridPhase::default();
+            grid_sprites.clear();
             for mut visibility in &mut visibile_squares {
                 *visibility = Visibility::Hidden;
             }
@@ -341,50 +1411,84 @@ fn reset_grid(
         score.reset();
         text.sections[0].value = format!("Score: {}", score.get());
     }
+    finesse_stats.reset();
+    clock.reset();
+    *level = Level::default();
+    bot_assisted.0 = false;
+    splits.0.clear();
 }
 
 fn draw_grid(
     mut dg_events: EventReader<DrawGrid>,
-    grid: Query<(Entity, &Grid, Option<&Focus>)>,
-    mut visible_squares: Query<(&mut Visibility, &mut Sprite, &Coordinate, &Parent)>,
+    settings: Res<EffectSettings>,
+    theme: Res<Theme>,
+    mut grid: Query<(&Grid, &GridPhase, &mut GridSprites, Has<Focus>)>,
+    mut visible_squares: Query<(&mut Visibility, &mut Sprite), With<Coordinate>>,
     shadows: Query<(&ControlledTetromino, &GridTetromino), With<Shadow>>,
+    pieces: Query<(&ControlledTetromino, &GridTetromino), With<TetrominoTimer>>,
 ) {
-    for event in dg_events.read() {
-        for (entity, grid, focus) in &grid {
-            if entity != event.0 {
-                continue;
+    // A grid asked to redraw several times in a frame only needs drawing once.
+    let mut entities: Vec<Entity> = dg_events.read().map(|DrawGrid(entity)| *entity).collect();
+    entities.sort_unstable();
+    entities.dedup();
+    for entity in entities {
+        let Ok((grid, phase, mut grid_sprites, focus)) = grid.get_mut(entity) else {
+            continue;
+        };
+        // The shadow is left where the last piece locked until the next one spawns.
+        let shadow_coords: Vec<_> = match shadows.iter().find(|(_, gt)| gt.get() == entity) {
+            Some((shadow, _)) if !phase.is_between_pieces() => shadow.coords(grid).collect(),
+            _ => vec![],
+        };
+        let (clearing, flash_on): (&[usize], bool) = match phase {
+            GridPhase::Clearing { rows, timer, .. } => {
+                let frame =
+                    timer.elapsed().as_millis() * u128::from(Delays::FRAMES_PER_SECOND) / 1000;
+                (rows, (frame / FLASH_FRAMES).is_multiple_of(2))
             }
-            let shadow_coords: Vec<_> =
-                if let Some((shadow, _)) = shadows.iter().find(|(_, gt)| gt.get() == entity) {
-                    shadow.coords().collect()
-                } else {
-                    vec![]
-                };
-            let set_coords: Vec<_> = grid.set_coords_iter().collect();
-            for (mut visibility, mut sprite, coord, parent) in &mut visible_squares {
-                if parent.get() != entity {
-                    continue;
-                }
-                let is_shadow = shadow_coords.contains(&coord.tuple());
-                let color = if focus.is_some() {
-                    FOCUS_COLOR
-                } else {
-                    NON_FOCUS_COLOR
-                };
-
-                if shadow_coords.contains(&coord.tuple()) {
-                    *visibility = Visibility::Visible;
-                    sprite.color = SHADOW_COLOR;
-                }
-
-                if set_coords.contains(&coord.tuple()) {
-                    *visibility = Visibility::Visible;
-                    sprite.color = color;
-                } else if !is_shadow {
+            _ => (&[], false),
+        };
+        let falling = pieces
+            .iter()
+            .find(|(_, gt)| gt.get() == entity)
+            .map(|(tetromino, _)| tetromino);
+        let piece_coords: Vec<_> = falling
+            .iter()
+            .flat_map(|tetromino| tetromino.coords(grid))
+            .collect();
+        // A smoothly drawn piece has sprites of its own.
+        let falling_color = falling
+            .filter(|_| !settings.is_enabled(Effect::SmoothPieces))
+            .map(|tetromino| theme.piece_color(grid.piece(tetromino.piece)));
+        let looks = render::compose(
+            grid,
+            focus,
+            Overlay {
+                shadow: &shadow_coords,
+                piece: &piece_coords,
+                piece_color: falling_color,
+                clearing,
+                flash_on,
+            },
+        );
+        for (square, look) in grid_sprites.update(&looks) {
+            let Ok((mut visibility, mut sprite)) = visible_squares.get_mut(square) else {
+                continue;
+            };
+            let palette = &theme.palette;
+            let color = match look {
+                CellLook::Empty => {
                     *visibility = Visibility::Hidden;
-                    sprite.color = color;
+                    continue;
                 }
-            }
+                CellLook::Shadow => palette.shadow.into(),
+                CellLook::Flash => palette.flash.into(),
+                CellLook::Filled { focused: true } => palette.focus.into(),
+                CellLook::Filled { focused: false } => palette.non_focus.into(),
+                CellLook::Piece { color, focused } => piece_color(color, focused),
+            };
+            *visibility = Visibility::Visible;
+            sprite.color = color;
         }
     }
 }
@@ -395,37 +1499,750 @@ fn update_score(
 ) {
     for event in event.read() {
         for (mut score, mut text) in &mut score {
-            score.add_cleared_rows(event.0);
+            score.add_cleared_rows(event.1);
             text.sections[0].value = format!("Score: {}", score.get());
         }
     }
 }
 
+fn tick_clock(time: Res<Time>, mut clock: ResMut<GameClock>) {
+    clock.0.tick(time.delta());
+}
+
+fn update_mode(
+    mode: Res<GameMode>,
+    puzzles: Res<Puzzles>,
+    piece_set: Res<PieceSet>,
+    clock: Res<GameClock>,
+    personal_bests: Res<PersonalBests>,
+    mut level: ResMut<Level>,
+    mut splits: ResMut<Splits>,
+    grids: Query<&GridStats>,
+    mut text: Query<&mut Text, With<ModeText>>,
+    mut next_state: ResMut<NextState<TetrisState>>,
+    mut sounds: EventWriter<SoundEvent>,
+) {
+    let stats: Vec<GridStats> = grids.iter().copied().collect();
+    let pieces: u32 = stats.iter().map(|stats| stats.pieces).sum();
+    let elapsed = clock.elapsed();
+    let new_level = Level(mode.level(&stats));
+    if *level != new_level {
+        debug!("Reached level {}", new_level.0);
+        if new_level.0 > level.0 {
+            sounds.send(SoundEvent(Sound::LevelUp));
+        }
+        *level = new_level;
+    }
+    while splits.0.len() < mode.splits(&stats) {
+        splits.0.push(elapsed);
+    }
+    let pieces_per_second = pieces as f32 / elapsed.as_secs_f32().max(f32::EPSILON);
+
+    let mut hud = match mode.time_limit() {
+        Some(limit) => format!(
+            "{}  {} left",
+            mode.name(),
+            format_duration(limit.saturating_sub(elapsed))
+        ),
+        None => format!("{}  {}", mode.name(), format_duration(elapsed)),
+    };
+    match *mode {
+        GameMode::Marathon { .. } => {
+            let _ = write!(hud, "\nLevel: {}", level.0);
+        }
+        GameMode::Puzzle { index } => {
+            if let Some(puzzle) = puzzles.0.get(index) {
+                let _ = write!(hud, "\n{}: {}", puzzle.name, puzzle.goal);
+            }
+        }
+        _ => {}
+    }
+    if let Some(remaining) = mode.lines_remaining(&stats) {
+        let label = match *mode {
+            GameMode::Dig { .. } => "Garbage left",
+            _ => "Lines left",
+        };
+        let _ = write!(hud, "\n{label}: {remaining}");
+    }
+    if let GameMode::Sprint { per_grid } = *mode {
+        if !splits.0.is_empty() {
+            let best = personal_bests.sprint_splits(per_grid);
+            let _ = write!(hud, "\n{}", format_splits(&splits.0, best));
+        }
+    }
+    if !piece_set.is_classic() {
+        let _ = write!(hud, "\nPieces: {}", piece_set.name);
+    }
+    let _ = write!(hud, "\nPPS: {pieces_per_second:.2}");
+    for mut text in &mut text {
+        text.sections[0].value.clone_from(&hud);
+    }
+
+    if mode.is_complete(&stats) {
+        debug!("{} complete in {:?}", mode.name(), elapsed);
+        next_state.set(TetrisState::Finished);
+    } else if mode.time_limit().is_some_and(|limit| elapsed >= limit) {
+        debug!("{} time's up", mode.name());
+        next_state.set(TetrisState::TimeUp);
+    }
+}
+
+fn update_finesse(
+    time: Res<Time>,
+    finesse_stats: Res<FinesseStats>,
+    mut faults: EventReader<FinesseFaultEvent>,
+    mut text: Query<&mut Text, With<FinesseText>>,
+    mut flash: Query<(&mut Visibility, &mut FinesseFlash)>,
+) {
+    if finesse_stats.is_changed() {
+        for mut text in &mut text {
+            text.sections[0].value = finesse_stats.to_string();
+        }
+    }
+    let faulted = faults.read().count() > 0;
+    for (mut visibility, mut flash) in &mut flash {
+        if faulted {
+            flash.0.reset();
+            *visibility = Visibility::Visible;
+        }
+        flash.0.tick(time.delta());
+        if flash.0.finished() {
+            *visibility = Visibility::Hidden;
+        }
+    }
+}
+
+fn load_saved_game(mut commands: Commands, mut next_pause: ResMut<NextState<PauseState>>) {
+    match SavedGame::read() {
+        Ok(Some(saved)) => {
+            commands.insert_resource(saved);
+            next_pause.set(PauseState::ContinuePrompt);
+        }
+        Ok(None) => debug!("No saved game"),
+        Err(e) => warn!("Failed to read saved game: {}", e),
+    }
+}
+
+/// Drops the saved game once it can no longer be continued.
+fn discard_saved_game() {
+    if let Err(e) = SavedGame::delete() {
+        warn!("Failed to delete saved game: {}", e);
+    }
+}
+
+/// Everything about a grid that goes into its `SavedGrid`.
+type GridToSave<'a> = (
+    Entity,
+    &'a GridIndex,
+    &'a Grid,
+    &'a GridStats,
+    Option<&'a PieceQueue>,
+    Option<&'a Focus>,
+    Option<&'a Bot>,
+);
+
+/// Saves the game when it's paused with Escape, loses window focus or is closed.
+fn pause(
+    input: Res<ButtonInput<KeyCode>>,
+    mut focus_changes: EventReader<WindowFocused>,
+    mut close_requests: EventReader<WindowCloseRequested>,
+    mode: Res<GameMode>,
+    clock: Res<GameClock>,
+    finesse_stats: Res<FinesseStats>,
+    random_source: Res<RandomSource>,
+    bot_assisted: Res<BotAssisted>,
+    splits: Res<Splits>,
+    pieces: Res<PieceSet>,
+    lifetime_stats: Res<LifetimeStats>,
+    score: Query<&Score>,
+    grids: Query<GridToSave>,
+    tetrominos: Query<
+        (
+            &GridTetromino,
+            &ControlledTetromino,
+            &TetrominoTimer,
+            &InputLog,
+            &LastMove,
+        ),
+        Without<Shadow>,
+    >,
+    mut next_pause: ResMut<NextState<PauseState>>,
+) {
+    let lost_focus = focus_changes.read().any(|change| !change.focused);
+    let closing = close_requests.read().count() > 0;
+    let paused = input.just_pressed(KeyCode::Escape) || lost_focus;
+    if !paused && !closing {
+        return;
+    }
+
+    let mut saved_grids: Vec<_> = grids
+        .iter()
+        .map(|(entity, index, grid, stats, queue, focus, bot)| {
+            let tetromino = tetrominos
+                .iter()
+                .find(|(grid_owner, ..)| grid_owner.get() == entity)
+                .map(
+                    |(_, tetromino, timer, input_log, last_move)| SavedTetromino {
+                        tetromino: *tetromino,
+                        gravity_elapsed: timer.0.elapsed(),
+                        input_log: input_log.clone(),
+                        last_move: last_move.clone(),
+                    },
+                );
+            let saved = SavedGrid {
+                grid: grid.clone(),
+                stats: *stats,
+                queue: queue.cloned(),
+                focus: focus.is_some(),
+                bot: bot.is_some(),
+                tetromino,
+            };
+            (index.0, saved)
+        })
+        .collect();
+    saved_grids.sort_by_key(|(index, _)| *index);
+    let saved = SavedGame {
+        version: SAVE_VERSION,
+        mode: *mode,
+        score: score.iter().next().copied().unwrap_or_default(),
+        elapsed: clock.elapsed(),
+        finesse: finesse_stats.clone(),
+        random_source: random_source.clone(),
+        bot_assisted: bot_assisted.0,
+        splits: splits.0.clone(),
+        pieces: pieces.clone(),
+        grids: saved_grids.into_iter().map(|(_, saved)| saved).collect(),
+    };
+    match saved.write() {
+        Ok(()) => debug!("Saved game"),
+        Err(e) => warn!("Failed to save game: {}", e),
+    }
+    save_lifetime_stats(&lifetime_stats);
+    if paused {
+        next_pause.set(PauseState::Paused);
+    }
+}
+
+fn unpause(input: Res<ButtonInput<KeyCode>>, mut next_pause: ResMut<NextState<PauseState>>) {
+    if input.just_pressed(KeyCode::Escape) {
+        next_pause.set(PauseState::Running);
+    }
+}
+
+/// Abandons the paused game for the main menu.
+fn quit_to_menu(
+    input: Res<ButtonInput<KeyCode>>,
+    mut next_pause: ResMut<NextState<PauseState>>,
+    mut next_state: ResMut<NextState<TetrisState>>,
+) {
+    if input.just_pressed(KeyCode::KeyM) {
+        next_pause.set(PauseState::Running);
+        next_state.set(TetrisState::Menu);
+    }
+}
+
+/// The pause screen's keys and volume sliders.
+fn pause_menu(settings: &AudioSettings, slider: VolumeSlider) -> String {
+    format!(
+        "Esc: Resume\nM: Main Menu\nUp/Down/Left/Right: Volume\n{}",
+        settings.sliders(slider)
+    )
+}
+
+fn pause_screen(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    theme: Res<Theme>,
+    audio_settings: Res<AudioSettings>,
+    slider: Res<VolumeSlider>,
+) {
+    let menu = pause_menu(&audio_settings, *slider);
+    spawn_menu(
+        &mut commands,
+        &asset_server,
+        &theme,
+        PauseText,
+        "Paused",
+        menu,
+    );
+}
+
+/// Picks a volume slider with Up/Down and moves it with Left/Right.
+fn adjust_volume(
+    input: Res<ButtonInput<KeyCode>>,
+    mut settings: ResMut<AudioSettings>,
+    mut slider: ResMut<VolumeSlider>,
+    mut screen: Query<&mut Text, With<PauseText>>,
+) {
+    if input.any_just_pressed([KeyCode::ArrowUp, KeyCode::ArrowDown]) {
+        *slider = slider.other();
+    }
+    let up = if input.just_pressed(KeyCode::ArrowRight) {
+        Some(true)
+    } else if input.just_pressed(KeyCode::ArrowLeft) {
+        Some(false)
+    } else {
+        None
+    };
+    if let Some(up) = up {
+        settings.step(*slider, up);
+        if let Err(e) = settings.write() {
+            warn!("Failed to save audio settings: {}", e);
+        }
+    }
+    if !slider.is_changed() && !settings.is_changed() {
+        return;
+    }
+    for mut text in &mut screen {
+        text.sections[1].value = pause_menu(&settings, *slider);
+    }
+}
+
+fn continue_screen(mut commands: Commands, asset_server: Res<AssetServer>, theme: Res<Theme>) {
+    let menu = "Enter: Continue\nN: New Game".to_string();
+    spawn_menu(
+        &mut commands,
+        &asset_server,
+        &theme,
+        PauseText,
+        "Saved Game",
+        menu,
+    );
+}
+
+fn despawn_pause_screen(mut commands: Commands, screen: Query<Entity, With<PauseText>>) {
+    for entity_id in &screen {
+        commands.entity(entity_id).despawn();
+    }
+}
+
+/// Enter keeps the saved game to be restored once the prompt closes, N discards it.
+fn continue_prompt(
+    mut commands: Commands,
+    input: Res<ButtonInput<KeyCode>>,
+    mut next_pause: ResMut<NextState<PauseState>>,
+) {
+    if input.just_pressed(KeyCode::Enter) {
+        next_pause.set(PauseState::Running);
+    } else if input.just_pressed(KeyCode::KeyN) {
+        commands.remove_resource::<SavedGame>();
+        discard_saved_game();
+        next_pause.set(PauseState::Running);
+    }
+}
+
+/// Replaces the freshly started game with the saved one, if the player chose to continue it.
+fn restore_game(
+    mut commands: Commands,
+    saved: Option<Res<SavedGame>>,
+    bot_settings: Res<BotSettings>,
+    delays: Res<Delays>,
+    mut mode: ResMut<GameMode>,
+    mut clock: ResMut<GameClock>,
+    mut level: ResMut<Level>,
+    mut bot_assisted: ResMut<BotAssisted>,
+    mut splits: ResMut<Splits>,
+    mut finesse_stats: ResMut<FinesseStats>,
+    mut random_source: ResMut<RandomSource>,
+    mut pieces: ResMut<PieceSet>,
+    mut grids: Query<(
+        Entity,
+        &GridIndex,
+        &mut Grid,
+        &mut GridStats,
+        &mut GridPhase,
+    )>,
+    tetrominos: Query<Entity, With<GridTetromino>>,
+    mut score: Query<(&mut Score, &mut Text)>,
+    mut draw_grid: EventWriter<DrawGrid>,
+) {
+    let Some(saved) = saved else {
+        return;
+    };
+    commands.remove_resource::<SavedGame>();
+    let mut configs: Vec<_> = grids
+        .iter()
+        .map(|(_, index, grid, _, _)| (index.0, grid.config()))
+        .collect();
+    configs.sort_by_key(|(index, _)| *index);
+    let configs: Vec<_> = configs.into_iter().map(|(_, config)| config).collect();
+    if !saved.fits(&configs) {
+        warn!("Saved game doesn't fit the current grids, starting a new game");
+        return;
+    }
+
+    debug!("Continuing saved {} game", saved.mode.name());
+    for entity_id in &tetrominos {
+        commands.entity(entity_id).despawn();
+    }
+    *mode = saved.mode;
+    clock.0.set_elapsed(saved.elapsed);
+    finesse_stats.clone_from(&saved.finesse);
+    random_source.clone_from(&saved.random_source);
+    bot_assisted.0 = saved.bot_assisted;
+    splits.0.clone_from(&saved.splits);
+    pieces.clone_from(&saved.pieces);
+    let stats: Vec<GridStats> = saved.grids.iter().map(|grid| grid.stats).collect();
+    *level = Level(saved.mode.level(&stats));
+    for (mut score, mut text) in &mut score {
+        *score = saved.score;
+        text.sections[0].value = format!("Score: {}", score.get());
+    }
+    for (entity, index, mut grid, mut stats, mut phase) in &mut grids {
+        let Some(saved_grid) = saved.grids.get(index.0) else {
+            continue;
+        };
+        grid.clone_from(&saved_grid.grid);
+        grid.set_pieces(&saved.pieces);
+        *stats = saved_grid.stats;
+        let mut grid_commands = commands.entity(entity);
+        match &saved_grid.queue {
+            Some(queue) => grid_commands.insert(queue.clone()),
+            None => grid_commands.remove::<PieceQueue>(),
+        };
+        if saved_grid.focus {
+            grid_commands.insert(Focus);
+        } else {
+            grid_commands.remove::<Focus>();
+        }
+        if saved_grid.bot {
+            grid_commands.insert(Bot::new(&bot_settings));
+        } else {
+            grid_commands.remove::<Bot>();
+        }
+        // A grid saved between pieces clears any full rows and spawns its next piece again.
+        *phase = if saved_grid.tetromino.is_some() {
+            GridPhase::Falling
+        } else {
+            GridPhase::after_lock(&grid, false, &delays)
+        };
+        if let Some(saved_tetromino) = &saved_grid.tetromino {
+            let mut timer = TetrominoTimer::from_interval(level.gravity_interval());
+            timer.0.set_elapsed(saved_tetromino.gravity_elapsed);
+            let shadow = grid.controlled_tetromino_shadow(&saved_tetromino.tetromino);
+            commands.spawn((shadow, Shadow, GridTetromino::new(entity)));
+            commands.spawn((
+                timer,
+                saved_tetromino.input_log.clone(),
+                saved_tetromino.last_move.clone(),
+                saved_tetromino.tetromino,
+                GridTetromino::new(entity),
+            ));
+        }
+        draw_grid.send(DrawGrid(entity));
+    }
+}
+
+/// Spawns a sprite for each of `tetromino`'s cells under `parent`, returning them in the order of
+/// `ControlledTetromino::coords`.
+fn spawn_piece_cells(
+    commands: &mut Commands,
+    parent: Entity,
+    grid: &Grid,
+    theme: &Theme,
+    tetromino: &ControlledTetromino,
+) -> Vec<Entity> {
+    let mut cells = vec![];
+    commands.entity(parent).with_children(|cb| {
+        let offsets = cell_offsets(grid, tetromino);
+        for (offset, (_, y)) in offsets.into_iter().zip(tetromino.coords(grid)) {
+            let cell = cb
+                .spawn(SpriteBundle {
+                    transform: Transform::from_translation(
+                        Vec3::new(offset.x, -offset.y, 0.0) * CELL_SIZE,
+                    ),
+                    visibility: cell_visibility(grid, y),
+                    sprite: Sprite {
+                        color: piece_color(theme.piece_color(grid.piece(tetromino.piece)), true),
+                        custom_size: Some(Vec2::splat(theme.cell_sprite_size(CELL_SIZE))),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .id();
+            cells.push(cell);
+        }
+    });
+    cells
+}
+
+/// Draws each falling piece as sprites of its own that glide after it, while the grid's sprites
+/// only show locked cells.
+fn draw_pieces(
+    mut commands: Commands,
+    time: Res<Time>,
+    settings: Res<EffectSettings>,
+    theme: Res<Theme>,
+    grids: Query<(&Grid, Has<Focus>)>,
+    tetrominos: Query<(Entity, &ControlledTetromino, &GridTetromino), With<TetrominoTimer>>,
+    mut groups: Query<(Entity, &mut PieceSprites, &mut Transform)>,
+    mut cells: Query<(&mut Visibility, &mut Sprite), Without<PieceSprites>>,
+) {
+    for (group_id, mut sprites, mut transform) in &mut groups {
+        let tetromino = tetrominos.get(sprites.tetromino).ok();
+        let Some((_, tetromino, grid_tetromino)) =
+            tetromino.filter(|_| settings.is_enabled(Effect::SmoothPieces))
+        else {
+            commands.entity(group_id).despawn_recursive();
+            continue;
+        };
+        let Ok((grid, focus)) = grids.get(grid_tetromino.get()) else {
+            continue;
+        };
+        if sprites.rotation != tetromino.rotation {
+            for cell in &sprites.cells {
+                commands.entity(*cell).despawn_recursive();
+            }
+            let cells = spawn_piece_cells(&mut commands, group_id, grid, &theme, tetromino);
+            sprites.rotate_to(tetromino, cells);
+        }
+        sprites.follow(grid, tetromino, time.delta_seconds());
+        transform.translation = cell_position_f32(grid, sprites.center).extend(3.0);
+        transform.rotation = Quat::from_rotation_z(sprites.angle);
+        let color = theme.piece_color(grid.piece(tetromino.piece));
+        for (cell, (_, y)) in sprites.cells.iter().zip(tetromino.coords(grid)) {
+            if let Ok((mut visibility, mut sprite)) = cells.get_mut(*cell) {
+                *visibility = cell_visibility(grid, y);
+                sprite.color = piece_color(color, focus);
+                sprite.custom_size = Some(Vec2::splat(theme.cell_sprite_size(CELL_SIZE)));
+            }
+        }
+    }
+
+    if !settings.is_enabled(Effect::SmoothPieces) {
+        return;
+    }
+    for (tetromino_id, tetromino, grid_tetromino) in &tetrominos {
+        if groups
+            .iter()
+            .any(|(_, sprites, _)| sprites.tetromino == tetromino_id)
+        {
+            continue;
+        }
+        let Ok((grid, _)) = grids.get(grid_tetromino.get()) else {
+            continue;
+        };
+        let group_id = commands
+            .spawn(SpatialBundle::default())
+            .set_parent(grid_tetromino.get())
+            .id();
+        let cells = spawn_piece_cells(&mut commands, group_id, grid, &theme, tetromino);
+        let sprites = PieceSprites::new(tetromino_id, grid, tetromino, cells);
+        let translation = cell_position_f32(grid, sprites.center).extend(3.0);
+        commands
+            .entity(group_id)
+            .insert((sprites, Transform::from_translation(translation)));
+    }
+}
+
+/// A falling piece's color, dimmed like the rest of an unfocused grid.
+fn piece_color([r, g, b]: [u8; 3], focused: bool) -> Color {
+    if focused {
+        Color::srgb_u8(r, g, b)
+    } else {
+        Color::srgb_u8(r / 2, g / 2, b / 2)
+    }
+}
+
+/// Cells still in the buffer above the visible field aren't drawn, like the grid's.
+fn cell_visibility(grid: &Grid, y: usize) -> Visibility {
+    if grid.is_visible_row(y) {
+        Visibility::Inherited
+    } else {
+        Visibility::Hidden
+    }
+}
+
+/// Where the center of cell `(x, y)` of a grid is, relative to the grid.
+pub(crate) fn cell_position(grid: &Grid, (x, y): (usize, usize)) -> Vec2 {
+    cell_position_f32(grid, Vec2::new(x as f32, y as f32))
+}
+
+/// `cell_position` for a point that may lie between cells.
+fn cell_position_f32(grid: &Grid, cell: Vec2) -> Vec2 {
+    Vec2::new(
+        cell.x * CELL_SIZE,
+        -(cell.y - grid.buffer_height() as f32) * CELL_SIZE,
+    )
+}
+
+/// Lays the game out again whenever the window changes size.
+fn update_layout(
+    windows: Query<&Window, With<PrimaryWindow>>,
+    grid_configs: Res<GridConfigs>,
+    mut layout: ResMut<Layout>,
+) {
+    let Ok(window) = windows.get_single() else {
+        return;
+    };
+    let size = Vec2::new(window.width(), window.height());
+    if size == layout.window && !grid_configs.is_changed() {
+        return;
+    }
+    let new_layout = Layout::new(size, &grid_configs.0);
+    if *layout != new_layout {
+        debug!("Laying out for a {}x{} window", size.x, size.y);
+        *layout = new_layout;
+    }
+}
+
+/// Moves grids and anchored UI into place when the layout changes or they're first spawned.
+/// Grids are built with `CELL_SIZE` cells and scaled to the layout's cell size.
+fn apply_layout(
+    layout: Res<Layout>,
+    mut grids: Query<(Ref<GridIndex>, &mut Transform), With<Grid>>,
+    mut anchors: Query<(Ref<LayoutAnchor>, &mut Style)>,
+) {
+    let scale = layout.cell_size / CELL_SIZE;
+    for (index, mut transform) in &mut grids {
+        if !layout.is_changed() && !index.is_added() {
+            continue;
+        }
+        if let Some(origin) = layout.grids.get(index.0) {
+            transform.translation = origin.extend(0.0);
+            transform.scale = Vec3::new(scale, scale, 1.0);
+        }
+    }
+    for (anchor, mut style) in &mut anchors {
+        if layout.is_changed() || anchor.is_added() {
+            layout.place(*anchor, &mut style);
+        }
+    }
+}
+
+/// Saves the game when it's paused or the window closes, and offers to continue a saved game on
+/// launch.
+struct PausePlugin;
+
+impl Plugin for PausePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, load_saved_game)
+            .add_systems(
+                Update,
+                pause.run_if(in_state(TetrisState::InGame).and_then(in_state(PauseState::Running))),
+            )
+            .add_systems(OnEnter(PauseState::Paused), (pause_screen,))
+            .add_systems(OnExit(PauseState::Paused), (despawn_pause_screen,))
+            .add_systems(
+                Update,
+                (unpause, quit_to_menu, adjust_volume).run_if(in_state(PauseState::Paused)),
+            )
+            .add_systems(OnEnter(PauseState::ContinuePrompt), (continue_screen,))
+            .add_systems(
+                OnExit(PauseState::ContinuePrompt),
+                (despawn_pause_screen, restore_game),
+            )
+            .add_systems(
+                Update,
+                (continue_prompt,).run_if(in_state(PauseState::ContinuePrompt)),
+            );
+    }
+}
+
 pub struct TetrisPlugin;
 
 impl Plugin for TetrisPlugin {
     fn build(&self, app: &mut App) {
         app.insert_resource(RandomSource::default())
+            .init_resource::<GridConfigs>()
+            .init_resource::<BotSettings>()
+            .init_resource::<FinesseStats>()
+            .init_resource::<GameMode>()
+            .init_resource::<GameClock>()
+            .init_resource::<Level>()
+            .init_resource::<PersonalBests>()
+            .init_resource::<BotAssisted>()
+            .init_resource::<Splits>()
+            .init_resource::<Puzzles>()
+            .init_resource::<PieceSets>()
+            .init_resource::<PieceSet>()
+            .init_resource::<HighScores>()
+            .init_resource::<Delays>()
+            .init_resource::<Layout>()
             .init_state::<TetrisState>()
+            .init_state::<PauseState>()
             .add_event::<RowClearedEvent>()
             .add_event::<DrawGrid>()
-            .add_systems(Startup, setup)
+            .add_event::<GridActionEvent>()
+            .add_event::<PieceSpawnedEvent>()
+            .add_event::<FinesseFaultEvent>()
+            .add_systems(
+                Startup,
+                (setup, load_puzzles, load_piece_sets, load_high_scores),
+            )
+            .add_systems(Update, (update_layout, apply_layout).chain())
             .add_systems(
                 OnEnter(TetrisState::InGame),
-                (reset_grid, init_spawn_tetrominos).chain(),
+                (reset_grid, add_garbage, setup_puzzle, init_spawn_tetrominos).chain(),
             )
             .add_systems(
                 Update,
                 (
                     swap_focus,
-                    handle_timed_movement,
+                    toggle_bot,
+                    track_bot_assistance,
+                    (keyboard_input, bot_input),
                     handle_input,
+                    handle_timed_movement,
+                    advance_grid_phases,
                     update_score,
+                    tick_clock,
+                    update_mode,
+                    update_puzzle,
+                    update_finesse,
                     draw_grid,
                 )
-                    .run_if(in_state(TetrisState::InGame)),
+                    .chain()
+                    .run_if(in_state(TetrisState::InGame).and_then(in_state(PauseState::Running))),
             )
-            .add_systems(OnEnter(TetrisState::GameOver), (game_over,))
-            .add_systems(Update, (reset,).run_if(in_state(TetrisState::GameOver)));
+            .add_systems(Update, draw_pieces.run_if(in_state(PauseState::Running)))
+            .add_systems(
+                OnEnter(TetrisState::GameOver),
+                (game_over, discard_saved_game, offer_high_score),
+            )
+            .add_systems(
+                OnEnter(TetrisState::Finished),
+                (finish, discard_saved_game, offer_high_score),
+            )
+            .add_systems(
+                OnEnter(TetrisState::TimeUp),
+                (time_up, discard_saved_game, offer_high_score),
+            )
+            .add_systems(OnEnter(TetrisState::Menu), (main_menu, discard_saved_game))
+            .add_systems(OnEnter(TetrisState::HighScores), (high_score_screen,))
+            .add_systems(
+                Update,
+                (browse_high_scores,).run_if(in_state(TetrisState::HighScores)),
+            )
+            .add_systems(OnEnter(TetrisState::PuzzleSelect), (puzzle_select,))
+            .add_systems(
+                Update,
+                (select_puzzle,).run_if(in_state(TetrisState::PuzzleSelect)),
+            )
+            .add_systems(
+                Update,
+                (
+                    enter_high_score_name.run_if(resource_exists::<PendingHighScore>),
+                    reset.run_if(not(resource_exists::<PendingHighScore>)),
+                )
+                    .chain()
+                    .run_if(
+                        in_state(TetrisState::GameOver)
+                            .or_else(in_state(TetrisState::Finished))
+                            .or_else(in_state(TetrisState::TimeUp))
+                            .or_else(in_state(TetrisState::Menu)),
+                    ),
+            );
+
+        app.add_plugins((
+            PausePlugin,
+            AudioPlugin,
+            EffectsPlugin,
+            StatsPlugin,
+            ThemePlugin,
+        ))
+        .add_systems(Startup, (load_delays, load_personal_bests));
+        #[cfg(not(target_arch = "wasm32"))]
+        app.add_systems(Startup, load_bot_weights);
     }
 }
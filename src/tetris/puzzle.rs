@@ -0,0 +1,207 @@
+use crate::tetris::components::{GridStats, TetrominoType};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter, Result as fmtResult};
+use std::io;
+use std::path::Path;
+
+/// Where puzzle definitions are loaded from on native builds.
+pub const PUZZLES_DIR: &str = "assets/puzzles";
+
+/// What has to happen for a puzzle to be solved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PuzzleGoal {
+    /// Clear this many lines across all grids.
+    Lines(u32),
+    /// Empty any grid completely with a line clear.
+    PerfectClear,
+    /// Clear two lines with a T piece rotated into place.
+    TSpinDouble,
+}
+
+impl PuzzleGoal {
+    #[must_use]
+    pub fn is_met(&self, grids: &[GridStats]) -> bool {
+        match self {
+            PuzzleGoal::Lines(lines) => {
+                grids.iter().map(|stats| stats.lines).sum::<u32>() >= *lines
+            }
+            PuzzleGoal::PerfectClear => grids.iter().any(|stats| stats.perfect_clears > 0),
+            PuzzleGoal::TSpinDouble => grids.iter().any(|stats| stats.t_spin_doubles > 0),
+        }
+    }
+}
+
+impl Display for PuzzleGoal {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmtResult {
+        match self {
+            PuzzleGoal::Lines(1) => write!(f, "Clear 1 line"),
+            PuzzleGoal::Lines(lines) => write!(f, "Clear {lines} lines"),
+            PuzzleGoal::PerfectClear => write!(f, "Perfect clear"),
+            PuzzleGoal::TSpinDouble => write!(f, "T-spin double"),
+        }
+    }
+}
+
+/// The starting contents of one grid and the pieces it's dealt, in order.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PuzzleGrid {
+    /// The bottom rows of the visible field, written like `Grid`'s `Display` output. Rows above a
+    /// line of `-` fill the bottom of the hidden buffer.
+    pub rows: String,
+    pub pieces: Vec<TetrominoType>,
+}
+
+/// A curated starting position. Grids are listed left to right; grids without an entry start
+/// empty and get no pieces.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Puzzle {
+    pub name: String,
+    pub goal: PuzzleGoal,
+    pub grids: Vec<PuzzleGrid>,
+}
+
+impl Puzzle {
+    /// Parses a puzzle from RON.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `contents` isn't a valid puzzle definition.
+    pub fn parse(contents: &str) -> io::Result<Self> {
+        ron::from_str(contents).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Reads a puzzle definition file.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the file can't be read or isn't a valid puzzle definition.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+}
+
+/// Every puzzle available from the puzzle select screen.
+#[derive(Debug, Default, Clone, Resource)]
+pub struct Puzzles(pub Vec<Puzzle>);
+
+impl Puzzles {
+    /// Loads every `.ron` file in `dir`, sorted by file name. Files that fail to parse are
+    /// skipped with a warning.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the directory can't be read.
+    pub fn load_dir(dir: impl AsRef<Path>) -> io::Result<Self> {
+        let mut paths: Vec<_> = std::fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "ron"))
+            .collect();
+        paths.sort();
+        let puzzles = paths
+            .iter()
+            .filter_map(|path| match Puzzle::load(path) {
+                Ok(puzzle) => Some(puzzle),
+                Err(e) => {
+                    warn!("Skipping puzzle {}: {}", path.display(), e);
+                    None
+                }
+            })
+            .collect();
+        Ok(Self(puzzles))
+    }
+
+    /// The puzzles shipped in `assets/puzzles`, built into the binary for the web.
+    #[cfg(target_arch = "wasm32")]
+    pub fn builtin() -> Self {
+        let puzzles = [
+            include_str!("../../assets/puzzles/01-tetris.ron"),
+            include_str!("../../assets/puzzles/02-perfect-clear.ron"),
+            include_str!("../../assets/puzzles/03-both-sides.ron"),
+            include_str!("../../assets/puzzles/04-t-spin-double.ron"),
+        ]
+        .iter()
+        .filter_map(|contents| Puzzle::parse(contents).ok())
+        .collect();
+        Self(puzzles)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tetris::bot::reachable_placements;
+    use crate::tetris::components::{ControlledTetromino, Grid, GridAction, GridConfig};
+
+    #[test]
+    fn test_builtin_puzzles_parse() {
+        let puzzles = Puzzles::load_dir(PUZZLES_DIR).unwrap();
+        assert!(!puzzles.0.is_empty());
+        for puzzle in &puzzles.0 {
+            for grid in &puzzle.grids {
+                assert!(
+                    Grid::with_visible_rows(GridConfig::default(), &grid.rows).is_ok(),
+                    "{} has an invalid grid",
+                    puzzle.name
+                );
+                assert!(!grid.pieces.is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn test_puzzle_grid_round_trips_display() {
+        let config = GridConfig::default();
+        let mut grid = Grid::with_visible_rows(config, "X....XXXXX\nXX.XXXXXXX").unwrap();
+        grid.set(4, grid.buffer_height() - 1, true);
+        let puzzle = Puzzle {
+            name: "Pasted".to_string(),
+            goal: PuzzleGoal::Lines(1),
+            grids: vec![PuzzleGrid {
+                rows: grid.to_string(),
+                pieces: vec![TetrominoType::I],
+            }],
+        };
+        let parsed = Puzzle::parse(&ron::to_string(&puzzle).unwrap()).unwrap();
+        let loaded = Grid::with_visible_rows(config, &parsed.grids[0].rows).unwrap();
+        assert_eq!(loaded.to_string(), grid.to_string());
+        assert!(loaded.get(4, loaded.buffer_height() - 1));
+    }
+
+    #[test]
+    fn test_t_spin_double_puzzle_is_reachable() {
+        let puzzles = Puzzles::load_dir(PUZZLES_DIR).unwrap();
+        let puzzle = puzzles
+            .0
+            .iter()
+            .find(|puzzle| puzzle.goal == PuzzleGoal::TSpinDouble)
+            .unwrap();
+        let grid = Grid::with_visible_rows(GridConfig::default(), &puzzle.grids[0].rows).unwrap();
+        let tetromino = ControlledTetromino::new_with_tetromino_type(TetrominoType::T, &grid);
+        let spin = reachable_placements(&grid, &tetromino)
+            .into_iter()
+            .find(|placement| {
+                let mut locked = grid.clone();
+                locked.set_tetromino(&placement.tetromino);
+                placement.actions.iter().rev().nth(1) == Some(&GridAction::Rotate)
+                    && grid.is_t_spin_position(&placement.tetromino)
+                    && locked.full_rows().len() == 2
+            });
+        assert!(spin.is_some(), "no T-spin double is reachable");
+    }
+
+    #[test]
+    fn test_puzzle_goals() {
+        let mut grids = vec![GridStats::default(); 2];
+        grids[0].lines = 2;
+        grids[1].lines = 1;
+        assert!(PuzzleGoal::Lines(3).is_met(&grids));
+        assert!(!PuzzleGoal::Lines(4).is_met(&grids));
+        assert!(!PuzzleGoal::PerfectClear.is_met(&grids));
+        grids[1].perfect_clears = 1;
+        assert!(PuzzleGoal::PerfectClear.is_met(&grids));
+        assert!(!PuzzleGoal::TSpinDouble.is_met(&grids));
+        grids[0].t_spin_doubles = 1;
+        assert!(PuzzleGoal::TSpinDouble.is_met(&grids));
+    }
+}
@@ -0,0 +1,171 @@
+use crate::tetris::components::Grid;
+use bevy::prelude::*;
+
+/// How one cell sprite of a grid should look.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CellLook {
+    Empty,
+    /// Where the falling piece would land.
+    Shadow,
+    /// A full row lit up while it flashes before it's cleared.
+    Flash,
+    /// A filled cell, brighter on the focused grid.
+    Filled {
+        focused: bool,
+    },
+    /// A cell of the falling piece, in the piece's sRGB color.
+    Piece {
+        color: [u8; 3],
+        focused: bool,
+    },
+}
+
+/// What `compose` draws a grid with besides its own cells.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Overlay<'a> {
+    /// Cells of the falling piece's shadow.
+    pub shadow: &'a [(usize, usize)],
+    /// Cells of the falling piece.
+    pub piece: &'a [(usize, usize)],
+    /// The color to draw the falling piece's cells in, or `None` when it's drawn separately and
+    /// its cells are left out of the grid's sprites.
+    pub piece_color: Option<[u8; 3]>,
+    /// Rows flashing before they're cleared, and whether they're lit right now.
+    pub clearing: &'a [usize],
+    pub flash_on: bool,
+}
+
+/// The look of every visible cell of `grid`, row by row from the top of the visible field.
+#[must_use]
+pub fn compose(grid: &Grid, focused: bool, overlay: Overlay) -> Vec<CellLook> {
+    let width = grid.width();
+    let buffer = grid.buffer_height();
+    let mut looks = vec![CellLook::Empty; width * grid.height()];
+    let index = |(x, y): (usize, usize)| (y - buffer) * width + x;
+
+    for &coord in overlay.shadow {
+        if grid.is_visible_row(coord.1) {
+            looks[index(coord)] = CellLook::Shadow;
+        }
+    }
+    for coord in grid.set_coords_iter() {
+        if !grid.is_visible_row(coord.1) {
+            continue;
+        }
+        if !overlay.piece.contains(&coord) {
+            looks[index(coord)] = CellLook::Filled { focused };
+        } else if let Some(color) = overlay.piece_color {
+            looks[index(coord)] = CellLook::Piece { color, focused };
+        }
+    }
+    let flash = if overlay.flash_on {
+        CellLook::Flash
+    } else {
+        CellLook::Empty
+    };
+    for &y in overlay.clearing {
+        if grid.is_visible_row(y) {
+            let start = index((0, y));
+            looks[start..start + width].fill(flash);
+        }
+    }
+    looks
+}
+
+/// A grid's cell sprites, so a redraw can go straight to the sprites whose look changed instead
+/// of searching every sprite in the world.
+#[derive(Debug, Clone, Component)]
+pub struct GridSprites {
+    /// One sprite per visible cell, in the same order as `compose`.
+    sprites: Vec<Entity>,
+    /// How each sprite was last drawn.
+    drawn: Vec<CellLook>,
+}
+
+impl GridSprites {
+    /// `sprites` must list the grid's visible cells row by row from the top of the visible field.
+    #[must_use]
+    pub fn new(sprites: Vec<Entity>) -> Self {
+        let drawn = vec![CellLook::Empty; sprites.len()];
+        Self { sprites, drawn }
+    }
+
+    /// Records `looks` as drawn, returning just the sprites that need to change to show them.
+    pub fn update(&mut self, looks: &[CellLook]) -> Vec<(Entity, CellLook)> {
+        let mut changes = vec![];
+        for ((sprite, drawn), look) in self.sprites.iter().zip(&mut self.drawn).zip(looks) {
+            if drawn != look {
+                *drawn = *look;
+                changes.push((*sprite, *look));
+            }
+        }
+        changes
+    }
+
+    /// Forgets what was drawn, after every sprite has been hidden.
+    pub fn clear(&mut self) {
+        self.drawn.fill(CellLook::Empty);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tetris::components::GridConfig;
+
+    #[test]
+    fn test_grid_sprites_update() {
+        let grid = Grid::with_visible_rows(GridConfig::new(3, 3), "...\nX..\nXXX").unwrap();
+        let top = grid.buffer_height();
+        let looks = compose(
+            &grid,
+            true,
+            Overlay {
+                shadow: &[(1, top), (2, top + 1)],
+                piece: &[(0, top + 1)],
+                ..default()
+            },
+        );
+        let filled = CellLook::Filled { focused: true };
+        assert_eq!(
+            looks,
+            [
+                CellLook::Empty,
+                CellLook::Shadow,
+                CellLook::Empty,
+                CellLook::Empty,
+                CellLook::Empty,
+                CellLook::Shadow,
+                filled,
+                filled,
+                filled,
+            ]
+        );
+
+        let sprites: Vec<Entity> = (0..9).map(Entity::from_raw).collect();
+        let mut grid_sprites = GridSprites::new(sprites);
+        assert_eq!(grid_sprites.update(&looks).len(), 5);
+        assert!(grid_sprites.update(&looks).is_empty());
+
+        let flashing = compose(
+            &grid,
+            true,
+            Overlay {
+                clearing: &[top + 2],
+                flash_on: true,
+                ..default()
+            },
+        );
+        assert_eq!(
+            grid_sprites.update(&flashing),
+            [
+                (Entity::from_raw(1), CellLook::Empty),
+                (Entity::from_raw(3), filled),
+                (Entity::from_raw(5), CellLook::Empty),
+                (Entity::from_raw(6), CellLook::Flash),
+                (Entity::from_raw(7), CellLook::Flash),
+                (Entity::from_raw(8), CellLook::Flash),
+            ]
+        );
+    }
+}
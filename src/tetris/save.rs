@@ -0,0 +1,432 @@
+use crate::tetris::components::{
+    ControlledTetromino, Grid, GridConfig, GridStats, LastMove, PieceId, PieceQueue, Score,
+};
+use crate::tetris::finesse::{FinesseStats, InputLog};
+use crate::tetris::mode::GameMode;
+use crate::tetris::pieces::PieceSet;
+use crate::tetris::RandomSource;
+use bevy::prelude::*;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::io;
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// The file the in-progress game is saved to on native builds.
+pub const SAVE_FILE: &str = "game.ron";
+
+/// The localStorage key the in-progress game is saved under on the web.
+pub const SAVE_STORAGE_KEY: &str = "twotris.save";
+
+/// The layout of `SavedGame`. Bump it whenever older saves can no longer be read, so they're
+/// discarded instead of failing to load. Saves from before it was recorded count as version 0.
+pub const SAVE_VERSION: u32 = 2;
+
+/// Where `file` is kept on native builds: the platform data directory, or the `save` directory if
+/// there isn't one.
+#[cfg(not(target_arch = "wasm32"))]
+#[must_use]
+pub fn data_path(file: &str) -> PathBuf {
+    dirs::data_dir()
+        .map_or_else(|| PathBuf::from("save"), |dir| dir.join("twotris"))
+        .join(file)
+}
+
+/// Parses a value from RON.
+///
+/// # Errors
+///
+/// Fails if `contents` isn't valid RON for `T`.
+pub fn from_ron<T: DeserializeOwned>(contents: &str) -> io::Result<T> {
+    ron::from_str(contents).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Serializes a value as pretty-printed RON.
+///
+/// # Errors
+///
+/// Fails if the value can't be serialized.
+pub fn to_ron<T: Serialize>(value: &T) -> io::Result<String> {
+    ron::ser::to_string_pretty(value, ron::ser::PrettyConfig::default())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Reads a RON file.
+///
+/// # Errors
+///
+/// Fails if the file can't be read or isn't valid RON for `T`.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn load_ron<T: DeserializeOwned>(path: impl AsRef<Path>) -> io::Result<T> {
+    from_ron(&std::fs::read_to_string(path)?)
+}
+
+/// Writes `value` as RON, creating parent directories as needed.
+///
+/// # Errors
+///
+/// Fails if the file can't be written.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn save_ron<T: Serialize>(path: impl AsRef<Path>, value: &T) -> io::Result<()> {
+    let path = path.as_ref();
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, to_ron(value)?)
+}
+
+/// Reads the value saved on this platform: `file` in the data directory on native builds, `key`
+/// in localStorage on the web. `None` if nothing has been saved yet.
+///
+/// # Errors
+///
+/// Fails if the value can't be read or isn't valid RON for `T`.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn read_ron<T: DeserializeOwned>(file: &str, _key: &str) -> io::Result<Option<T>> {
+    match load_ron(data_path(file)) {
+        Ok(value) => Ok(Some(value)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Saves `value` for this platform, replacing anything saved there before.
+///
+/// # Errors
+///
+/// Fails if the value can't be written.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn write_ron<T: Serialize>(file: &str, _key: &str, value: &T) -> io::Result<()> {
+    save_ron(data_path(file), value)
+}
+
+/// Removes the value saved on this platform, if there is one.
+///
+/// # Errors
+///
+/// Fails if an existing value can't be removed.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn delete_ron(file: &str, _key: &str) -> io::Result<()> {
+    match std::fs::remove_file(data_path(file)) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Reads the value saved on this platform: `file` in the data directory on native builds, `key`
+/// in localStorage on the web. `None` if nothing has been saved yet.
+///
+/// # Errors
+///
+/// Fails if localStorage is unavailable or the value isn't valid RON for `T`.
+#[cfg(target_arch = "wasm32")]
+pub fn read_ron<T: DeserializeOwned>(_file: &str, key: &str) -> io::Result<Option<T>> {
+    local_storage()?
+        .get_item(key)
+        .map_err(storage_error)?
+        .map(|contents| from_ron(&contents))
+        .transpose()
+}
+
+/// Saves `value` for this platform, replacing anything saved there before.
+///
+/// # Errors
+///
+/// Fails if localStorage is unavailable or full.
+#[cfg(target_arch = "wasm32")]
+pub fn write_ron<T: Serialize>(_file: &str, key: &str, value: &T) -> io::Result<()> {
+    local_storage()?
+        .set_item(key, &to_ron(value)?)
+        .map_err(storage_error)
+}
+
+/// Removes the value saved on this platform, if there is one.
+///
+/// # Errors
+///
+/// Fails if localStorage is unavailable.
+#[cfg(target_arch = "wasm32")]
+pub fn delete_ron(_file: &str, key: &str) -> io::Result<()> {
+    local_storage()?.remove_item(key).map_err(storage_error)
+}
+
+/// The falling piece on a grid. Its shadow isn't stored since it follows from the piece and the
+/// grid.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedTetromino {
+    pub tetromino: ControlledTetromino,
+    /// How far the piece is into its current gravity step.
+    pub gravity_elapsed: Duration,
+    pub input_log: InputLog,
+    pub last_move: LastMove,
+}
+
+/// One grid, listed in `GridIndex` order.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedGrid {
+    /// The grid's cells, including the falling piece.
+    pub grid: Grid,
+    pub stats: GridStats,
+    pub queue: Option<PieceQueue>,
+    pub focus: bool,
+    pub bot: bool,
+    pub tetromino: Option<SavedTetromino>,
+}
+
+/// Everything needed to pick a game back up where it was left. Present as a resource while the
+/// player is asked whether to continue it.
+#[derive(Debug, Clone, Resource, Serialize, Deserialize)]
+pub struct SavedGame {
+    /// The `SAVE_VERSION` the game was saved with.
+    pub version: u32,
+    pub mode: GameMode,
+    pub score: Score,
+    pub elapsed: Duration,
+    pub finesse: FinesseStats,
+    pub random_source: RandomSource,
+    /// Whether a bot has played any part of the game so far.
+    #[serde(default)]
+    pub bot_assisted: bool,
+    /// The game clock at each sprint split so far.
+    #[serde(default)]
+    pub splits: Vec<Duration>,
+    /// The set new pieces are drawn from.
+    #[serde(default)]
+    pub pieces: PieceSet,
+    pub grids: Vec<SavedGrid>,
+}
+
+/// Just the version of a saved game, which can be read whatever the rest of its layout is.
+#[derive(Debug, Deserialize)]
+#[serde(rename = "SavedGame")]
+struct SaveHeader {
+    #[serde(default)]
+    version: u32,
+}
+
+impl SavedGame {
+    /// Whether the saved grids have the same sizes as `configs`, every saved piece fits in them
+    /// and every falling piece is one of the saved set's, so they can be restored onto the grids
+    /// currently on screen.
+    #[must_use]
+    pub fn fits(&self, configs: &[GridConfig]) -> bool {
+        self.grids.len() == configs.len()
+            && self.grids.iter().zip(configs).all(|(saved, config)| {
+                saved.grid.config() == *config
+                    && self.pieces.check_width(config.width).is_ok()
+                    && saved
+                        .queue
+                        .iter()
+                        .flat_map(|queue| &queue.0)
+                        .all(|piece| piece.width() <= config.width)
+                    && saved.tetromino.iter().all(|saved| {
+                        self.has_piece(&saved.tetromino) && self.has_piece(&saved.input_log.spawn)
+                    })
+            })
+    }
+
+    /// Whether `tetromino`'s piece and rotation are ones the saved set has.
+    fn has_piece(&self, tetromino: &ControlledTetromino) -> bool {
+        let rotations = match tetromino.piece {
+            PieceId::Classic(tetromino_type) => Some(tetromino_type.rotations()),
+            PieceId::Set(index) => self
+                .pieces
+                .pieces()
+                .get(index)
+                .map(super::pieces::Piece::rotations),
+        };
+        rotations.is_some_and(|rotations| tetromino.rotation < rotations.len())
+    }
+
+    /// Reads the game saved on this platform, if there is one. A game saved with another
+    /// `SAVE_VERSION` is deleted instead.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the save can't be read or isn't a valid saved game.
+    pub fn read() -> io::Result<Option<Self>> {
+        let Some(header) = read_ron::<SaveHeader>(SAVE_FILE, SAVE_STORAGE_KEY)? else {
+            return Ok(None);
+        };
+        if header.version != SAVE_VERSION {
+            let age = if header.version < SAVE_VERSION {
+                "an older"
+            } else {
+                "a newer"
+            };
+            warn!(
+                "Discarding a game saved in {age} format (version {}, this build reads {})",
+                header.version, SAVE_VERSION
+            );
+            Self::delete()?;
+            return Ok(None);
+        }
+        read_ron(SAVE_FILE, SAVE_STORAGE_KEY)
+    }
+
+    /// Saves the game for this platform, replacing any earlier save.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the save can't be written.
+    pub fn write(&self) -> io::Result<()> {
+        write_ron(SAVE_FILE, SAVE_STORAGE_KEY, self)
+    }
+
+    /// Removes the game saved on this platform, if there is one.
+    ///
+    /// # Errors
+    ///
+    /// Fails if an existing save can't be removed.
+    pub fn delete() -> io::Result<()> {
+        delete_ron(SAVE_FILE, SAVE_STORAGE_KEY)
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn local_storage() -> io::Result<web_sys::Storage> {
+    web_sys::window()
+        .and_then(|window| window.local_storage().ok().flatten())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::Unsupported, "localStorage is unavailable"))
+}
+
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn storage_error(e: web_sys::wasm_bindgen::JsValue) -> io::Error {
+    io::Error::other(format!("{e:?}"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tetris::components::TetrominoType;
+    use crate::tetris::pieces::{PieceSets, PIECE_SETS_DIR};
+
+    #[test]
+    fn test_saved_game_round_trip() {
+        let mut grid = Grid::default();
+        grid.add_garbage_row(&[3]);
+        let tetromino = grid.spawn_tetromino_type(TetrominoType::T).unwrap();
+        let mut random_source = RandomSource::from_seed(7);
+        random_source.next(0, 7);
+        let saved = SavedGame {
+            version: SAVE_VERSION,
+            mode: GameMode::Dig { rows: 10 },
+            score: Score(1200),
+            elapsed: Duration::from_millis(61_500),
+            finesse: FinesseStats::default(),
+            random_source: random_source.clone(),
+            bot_assisted: false,
+            splits: vec![],
+            pieces: PieceSet::classic(),
+            grids: vec![SavedGrid {
+                grid: grid.clone(),
+                stats: GridStats::default(),
+                queue: Some(PieceQueue([TetrominoType::I.piece()].into())),
+                focus: true,
+                bot: false,
+                tetromino: Some(SavedTetromino {
+                    input_log: InputLog::new(&tetromino),
+                    tetromino,
+                    gravity_elapsed: Duration::from_millis(400),
+                    last_move: LastMove::default(),
+                }),
+            }],
+        };
+
+        let contents = to_ron(&saved).unwrap();
+        assert_eq!(
+            from_ron::<SaveHeader>(&contents).unwrap().version,
+            SAVE_VERSION
+        );
+        let mut loaded: SavedGame = from_ron(&contents).unwrap();
+        assert_eq!(loaded.mode, saved.mode);
+        assert_eq!(loaded.elapsed, saved.elapsed);
+        assert_eq!(loaded.pieces, saved.pieces);
+        assert_eq!(loaded.grids[0].grid.to_string(), grid.to_string());
+        assert!(loaded.grids[0].grid.is_garbage_row(grid.total_height() - 1));
+        assert!(loaded.fits(&[GridConfig::default()]));
+        assert!(!loaded.fits(&[GridConfig::default(), GridConfig::default()]));
+        // The random source picks up exactly where it left off.
+        for _ in 0..10 {
+            assert_eq!(loaded.random_source.next(0, 7), random_source.next(0, 7));
+        }
+    }
+
+    #[test]
+    fn test_saved_pieces_must_fit() {
+        let config = GridConfig::new(4, 8);
+        let mut saved = SavedGame {
+            version: SAVE_VERSION,
+            mode: GameMode::default(),
+            score: Score::default(),
+            elapsed: Duration::ZERO,
+            finesse: FinesseStats::default(),
+            random_source: RandomSource::from_seed(0),
+            bot_assisted: false,
+            splits: vec![],
+            pieces: PieceSet::classic(),
+            grids: vec![SavedGrid {
+                grid: Grid::new(config),
+                stats: GridStats::default(),
+                queue: None,
+                focus: true,
+                bot: false,
+                tetromino: None,
+            }],
+        };
+        assert!(saved.fits(&[config]));
+
+        let sets = PieceSets::load_dir(PIECE_SETS_DIR).unwrap();
+        let pentominoes = sets.0.iter().find(|set| set.name == "Pentominoes").unwrap();
+        saved.pieces = pentominoes.clone();
+        assert!(!saved.fits(&[config]));
+
+        saved.pieces = PieceSet::classic();
+        saved.grids[0].queue = Some(PieceQueue(pentominoes.pieces().iter().cloned().collect()));
+        assert!(!saved.fits(&[config]));
+
+        // A falling piece must be one of the saved set's.
+        saved.grids[0].queue = None;
+        let mut tetromino =
+            ControlledTetromino::new_with_tetromino_type(TetrominoType::O, &Grid::new(config));
+        saved.grids[0].tetromino = Some(SavedTetromino {
+            input_log: InputLog::new(&tetromino),
+            tetromino,
+            gravity_elapsed: Duration::ZERO,
+            last_move: LastMove::default(),
+        });
+        assert!(saved.fits(&[config]));
+        tetromino.piece = PieceId::Set(saved.pieces.pieces().len());
+        saved.grids[0].tetromino.as_mut().unwrap().tetromino = tetromino;
+        assert!(!saved.fits(&[config]));
+    }
+
+    #[test]
+    fn test_unversioned_save_header() {
+        // Saves from before the version was recorded still have a readable header.
+        let old = "SavedGame(mode: Marathon(level: 1), grids: [(tetromino: Some((piece: T)))])";
+        assert_eq!(from_ron::<SaveHeader>(old).unwrap().version, 0);
+        assert!(from_ron::<SavedGame>(old).is_err());
+    }
+
+    /// Points the platform data directory at a scratch directory, which `dirs` only allows on
+    /// Linux.
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_read_discards_other_versions() {
+        let dir = std::env::temp_dir().join("twotris_test_save");
+        std::env::set_var("XDG_DATA_HOME", &dir);
+        let path = data_path(SAVE_FILE);
+        assert!(path.starts_with(&dir));
+
+        for version in [0, SAVE_VERSION + 1] {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, format!("(version: {version}, mode: Marathon)")).unwrap();
+            assert!(SavedGame::read().unwrap().is_none());
+            assert!(!path.exists());
+        }
+        assert!(SavedGame::read().unwrap().is_none());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
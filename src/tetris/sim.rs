@@ -0,0 +1,260 @@
+use crate::tetris::bot::{best_placement, reachable_placements, Placement, Weights};
+use crate::tetris::components::{ControlledTetromino, Grid, GridConfig, Score, TetrominoType};
+use crate::tetris::RandomSource;
+use serde::Serialize;
+
+/// Chooses where each piece goes in a headless game. `grid` never contains `current`.
+pub trait Policy {
+    fn name(&self) -> &str;
+
+    fn choose(
+        &mut self,
+        grid: &Grid,
+        current: &ControlledTetromino,
+        next: &ControlledTetromino,
+    ) -> Option<Placement>;
+}
+
+/// Plays like the in-game bot, optionally looking at the next piece.
+pub struct HeuristicPolicy {
+    pub weights: Weights,
+    pub lookahead: bool,
+    name: String,
+}
+
+impl HeuristicPolicy {
+    pub fn new(name: impl Into<String>, weights: Weights, lookahead: bool) -> Self {
+        Self {
+            weights,
+            lookahead,
+            name: name.into(),
+        }
+    }
+}
+
+impl Policy for HeuristicPolicy {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn choose(
+        &mut self,
+        grid: &Grid,
+        current: &ControlledTetromino,
+        next: &ControlledTetromino,
+    ) -> Option<Placement> {
+        let next = self.lookahead.then_some(next);
+        best_placement(grid, current, next, &self.weights)
+    }
+}
+
+/// Picks uniformly among the reachable placements. Useful as a baseline.
+pub struct RandomPolicy(RandomSource);
+
+impl RandomPolicy {
+    #[must_use]
+    pub fn new(seed: u64) -> Self {
+        Self(RandomSource::from_seed(seed))
+    }
+}
+
+impl Policy for RandomPolicy {
+    fn name(&self) -> &'static str {
+        "random"
+    }
+
+    fn choose(
+        &mut self,
+        grid: &Grid,
+        current: &ControlledTetromino,
+        _next: &ControlledTetromino,
+    ) -> Option<Placement> {
+        let mut placements = reachable_placements(grid, current);
+        let len = u32::try_from(placements.len())
+            .ok()
+            .filter(|&len| len > 0)?;
+        let idx = self.0.next(0, len) as usize;
+        Some(placements.swap_remove(idx))
+    }
+}
+
+/// Builds one of the built-in policies by name.
+#[must_use]
+pub fn policy_from_name(name: &str, seed: u64) -> Option<Box<dyn Policy>> {
+    match name {
+        "heuristic" => Some(Box::new(HeuristicPolicy::new(
+            name,
+            Weights::default(),
+            false,
+        ))),
+        "lookahead" => Some(Box::new(HeuristicPolicy::new(
+            name,
+            Weights::default(),
+            true,
+        ))),
+        "random" => Some(Box::new(RandomPolicy::new(seed))),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GridResult {
+    pub policy: String,
+    pub score: u32,
+    pub lines: u32,
+    pub pieces: u32,
+    pub topped_out: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GameResult {
+    pub seed: u64,
+    pub grids: Vec<GridResult>,
+}
+
+impl GameResult {
+    #[must_use]
+    pub fn score(&self) -> u32 {
+        self.grids.iter().map(|grid| grid.score).sum()
+    }
+
+    #[must_use]
+    pub fn lines(&self) -> u32 {
+        self.grids.iter().map(|grid| grid.lines).sum()
+    }
+}
+
+struct SimGrid {
+    grid: Grid,
+    next: TetrominoType,
+    score: Score,
+    lines: u32,
+    pieces: u32,
+    topped_out: bool,
+}
+
+impl SimGrid {
+    /// Spawns, places and locks one piece using the same rules as the game.
+    fn step(&mut self, rng: &mut RandomSource, policy: &mut dyn Policy) {
+        let tetromino_type = std::mem::replace(&mut self.next, TetrominoType::random(rng));
+        let Some(current) = self.grid.spawn_tetromino_type(tetromino_type) else {
+            self.topped_out = true;
+            return;
+        };
+        self.grid.unset_tetromino(&current);
+        let next = ControlledTetromino::new_with_tetromino_type(self.next, &self.grid);
+
+        let placement = policy.choose(&self.grid, &current, &next).map_or_else(
+            || self.grid.controlled_tetromino_shadow(&current),
+            |placement| placement.tetromino,
+        );
+        self.grid.set_tetromino(&placement);
+        self.pieces += 1;
+        if self.grid.is_tetromino_above_visible(&placement) {
+            self.topped_out = true;
+            return;
+        }
+        let lines = self.grid.clear_full_grid_rows();
+        self.lines += lines;
+        self.score.add_cleared_rows(lines);
+    }
+}
+
+/// A headless two-grid game. Both grids draw from one seeded `RandomSource` and the game ends as
+/// soon as either grid tops out, just like the windowed game.
+pub struct Simulation {
+    seed: u64,
+    rng: RandomSource,
+    grids: Vec<SimGrid>,
+}
+
+impl Simulation {
+    #[must_use]
+    pub fn new(seed: u64, configs: &[GridConfig]) -> Self {
+        let mut rng = RandomSource::from_seed(seed);
+        let grids = configs
+            .iter()
+            .map(|config| SimGrid {
+                grid: Grid::new(*config),
+                next: TetrominoType::random(&mut rng),
+                score: Score::default(),
+                lines: 0,
+                pieces: 0,
+                topped_out: false,
+            })
+            .collect();
+        Self { seed, rng, grids }
+    }
+
+    /// Plays until a grid tops out or every grid has placed `max_pieces`. `policies` are
+    /// assigned to grids in order.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there isn't exactly one policy per grid.
+    pub fn run(mut self, policies: &mut [Box<dyn Policy>], max_pieces: u32) -> GameResult {
+        assert_eq!(
+            policies.len(),
+            self.grids.len(),
+            "every grid needs a policy"
+        );
+        'game: while self.grids.iter().any(|grid| grid.pieces < max_pieces) {
+            for (grid, policy) in self.grids.iter_mut().zip(policies.iter_mut()) {
+                if grid.pieces < max_pieces {
+                    grid.step(&mut self.rng, policy.as_mut());
+                }
+                if grid.topped_out {
+                    break 'game;
+                }
+            }
+        }
+
+        GameResult {
+            seed: self.seed,
+            grids: self
+                .grids
+                .iter()
+                .zip(policies.iter())
+                .map(|(grid, policy)| GridResult {
+                    policy: policy.name().to_string(),
+                    score: grid.score.get(),
+                    lines: grid.lines,
+                    pieces: grid.pieces,
+                    topped_out: grid.topped_out,
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn run(seed: u64, names: &[&str], max_pieces: u32) -> GameResult {
+        let mut policies: Vec<_> = names
+            .iter()
+            .map(|name| policy_from_name(name, seed).unwrap())
+            .collect();
+        Simulation::new(seed, &[GridConfig::default(), GridConfig::default()])
+            .run(&mut policies, max_pieces)
+    }
+
+    #[test]
+    fn test_simulation_is_deterministic() {
+        let a = run(7, &["heuristic", "random"], 50);
+        let b = run(7, &["heuristic", "random"], 50);
+        assert_eq!(
+            serde_json::to_string(&a).unwrap(),
+            serde_json::to_string(&b).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_heuristic_outlasts_random() {
+        let result = run(1, &["heuristic", "random"], 200);
+        assert!(result.grids[1].topped_out);
+        assert!(!result.grids[0].topped_out);
+        assert!(result.grids[0].pieces >= result.grids[1].pieces);
+    }
+}
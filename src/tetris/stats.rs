@@ -0,0 +1,398 @@
+use crate::tetris::bot::Bot;
+use crate::tetris::components::{
+    Focus, Grid, GridActionEvent, GridIndex, PieceSpawnedEvent, RowClearedEvent, TetrominoType,
+};
+use crate::tetris::layout::LayoutAnchor;
+use crate::tetris::mode::format_duration;
+use crate::tetris::pieces::Piece;
+use crate::tetris::plugin::{PauseState, TetrisState};
+use crate::tetris::save::{read_ron, write_ron};
+use crate::tetris::theme::{TextRole, Theme, ThemedText};
+use bevy::prelude::*;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::BTreeMap;
+use std::io;
+use std::time::Duration;
+
+/// File name of the lifetime statistics inside the platform data directory.
+pub const STATS_FILE: &str = "stats.ron";
+
+/// The localStorage key the lifetime statistics are saved under on the web.
+pub const STATS_STORAGE_KEY: &str = "twotris.stats";
+
+/// Names of line clears by how many rows they cleared, singles first.
+pub const CLEAR_NAMES: [&str; 4] = ["Single", "Double", "Triple", "Tetris"];
+
+/// Width of the longest bar in a stats screen chart.
+const BAR_WIDTH: f32 = 300.0;
+
+/// Totals across every game ever played.
+#[derive(Debug, Default, Clone, PartialEq, Resource, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LifetimeStats {
+    pub games: u32,
+    pub pieces: u64,
+    /// Line clears by how many rows they cleared, singles first.
+    pub clears: [u64; 4],
+    /// Moves, rotations and drops entered by the player. Bot inputs don't count.
+    pub inputs: u64,
+    pub time_played: Duration,
+    /// Time each grid spent focused, by `GridIndex`.
+    pub grid_time: Vec<Duration>,
+    /// Pieces dealt of each kind, by letter.
+    #[serde(deserialize_with = "deserialize_piece_counts")]
+    pub piece_counts: BTreeMap<char, u64>,
+}
+
+/// Reads piece counts by letter, or the classic piece counts in `TetrominoType::ALL` order that
+/// older stats files have.
+fn deserialize_piece_counts<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<BTreeMap<char, u64>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum PieceCounts {
+        ByLetter(BTreeMap<char, u64>),
+        Classic([u64; 7]),
+    }
+
+    Ok(match PieceCounts::deserialize(deserializer)? {
+        PieceCounts::ByLetter(counts) => counts,
+        PieceCounts::Classic(counts) => TetrominoType::ALL
+            .map(TetrominoType::letter)
+            .into_iter()
+            .zip(counts)
+            .filter(|(_, count)| *count > 0)
+            .collect(),
+    })
+}
+
+impl LifetimeStats {
+    pub fn record_clear(&mut self, rows: u32) {
+        if let Some(clears) = (rows as usize)
+            .checked_sub(1)
+            .and_then(|i| self.clears.get_mut(i))
+        {
+            *clears += 1;
+        }
+    }
+
+    pub fn record_piece(&mut self, piece: &Piece) {
+        self.pieces += 1;
+        *self.piece_counts.entry(piece.letter()).or_default() += 1;
+    }
+
+    /// Adds `delta` of play, crediting it to the grid at `focus` if there is one.
+    pub fn record_time(&mut self, delta: Duration, focus: Option<usize>) {
+        self.time_played += delta;
+        if let Some(focus) = focus {
+            if self.grid_time.len() <= focus {
+                self.grid_time.resize(focus + 1, Duration::ZERO);
+            }
+            self.grid_time[focus] += delta;
+        }
+    }
+
+    #[must_use]
+    pub fn lines(&self) -> u64 {
+        self.clears
+            .iter()
+            .zip(1..)
+            .map(|(clears, rows)| clears * rows)
+            .sum()
+    }
+
+    /// Share of all cleared lines that came from tetrises.
+    #[must_use]
+    pub fn tetris_rate(&self) -> f32 {
+        let lines = self.lines();
+        if lines == 0 {
+            0.0
+        } else {
+            (self.clears[3] * 4) as f32 / lines as f32
+        }
+    }
+
+    #[must_use]
+    pub fn pieces_per_second(&self) -> f32 {
+        self.pieces as f32 / self.time_played.as_secs_f32().max(f32::EPSILON)
+    }
+
+    #[must_use]
+    pub fn actions_per_minute(&self) -> f32 {
+        self.inputs as f32 * 60.0 / self.time_played.as_secs_f32().max(f32::EPSILON)
+    }
+
+    /// Reads the lifetime statistics saved on this platform, empty if there are none yet.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the statistics can't be read or aren't valid RON.
+    pub fn read() -> io::Result<Self> {
+        Ok(read_ron(STATS_FILE, STATS_STORAGE_KEY)?.unwrap_or_default())
+    }
+
+    /// Saves the lifetime statistics for this platform.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the statistics can't be written.
+    pub fn write(&self) -> io::Result<()> {
+        write_ron(STATS_FILE, STATS_STORAGE_KEY, self)
+    }
+}
+
+/// The root of the stats screen, despawned with its charts when the screen closes.
+#[derive(Debug, Component)]
+pub struct StatsScreen;
+
+/// Keeps the lifetime statistics up to date and shows them on the stats screen.
+pub struct StatsPlugin;
+
+impl Plugin for StatsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LifetimeStats>()
+            .add_systems(Startup, load_lifetime_stats)
+            .add_systems(
+                Update,
+                record_lifetime_stats
+                    .run_if(in_state(TetrisState::InGame).and_then(in_state(PauseState::Running))),
+            )
+            .add_systems(OnEnter(TetrisState::GameOver), record_game)
+            .add_systems(OnEnter(TetrisState::Finished), record_game)
+            .add_systems(OnEnter(TetrisState::TimeUp), record_game)
+            .add_systems(OnEnter(TetrisState::Stats), stats_screen)
+            .add_systems(Update, close_stats.run_if(in_state(TetrisState::Stats)));
+    }
+}
+
+fn load_lifetime_stats(mut lifetime_stats: ResMut<LifetimeStats>) {
+    match LifetimeStats::read() {
+        Ok(loaded) => *lifetime_stats = loaded,
+        Err(e) => warn!("Failed to load lifetime stats: {}", e),
+    }
+}
+
+pub fn save_lifetime_stats(lifetime_stats: &LifetimeStats) {
+    if let Err(e) = lifetime_stats.write() {
+        warn!("Failed to save lifetime stats: {}", e);
+    }
+}
+
+fn record_lifetime_stats(
+    time: Res<Time>,
+    mut lifetime_stats: ResMut<LifetimeStats>,
+    mut rows_cleared: EventReader<RowClearedEvent>,
+    mut pieces_spawned: EventReader<PieceSpawnedEvent>,
+    mut actions: EventReader<GridActionEvent>,
+    players: Query<(), (With<Grid>, Without<Bot>)>,
+    focus: Query<&GridIndex, With<Focus>>,
+) {
+    for RowClearedEvent(grid, rows) in rows_cleared.read() {
+        if players.contains(*grid) {
+            lifetime_stats.record_clear(*rows);
+        }
+    }
+    for PieceSpawnedEvent(grid, piece) in pieces_spawned.read() {
+        if players.contains(*grid) {
+            lifetime_stats.record_piece(piece);
+        }
+    }
+    let inputs = actions
+        .read()
+        .filter(|GridActionEvent(entity, _)| players.contains(*entity))
+        .count();
+    lifetime_stats.inputs += inputs as u64;
+    let focus = focus.iter().next().map(|index| index.0);
+    lifetime_stats.record_time(time.delta(), focus);
+}
+
+fn record_game(mut lifetime_stats: ResMut<LifetimeStats>) {
+    lifetime_stats.games += 1;
+    save_lifetime_stats(&lifetime_stats);
+}
+
+/// Adds `title` and a horizontal bar for each `(label, value, value_label)`, scaled to the
+/// largest value.
+fn spawn_bar_chart(
+    parent: &mut ChildBuilder,
+    asset_server: &AssetServer,
+    theme: &Theme,
+    title: &str,
+    bars: &[(String, u64, String)],
+) {
+    let max = bars
+        .iter()
+        .map(|(_, value, _)| *value)
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    let text = |value: &str, role| {
+        (
+            ThemedText(vec![role]),
+            TextBundle::from_section(value, theme.text_style(role, asset_server)),
+        )
+    };
+    parent.spawn(text(title, TextRole::Body));
+    for (label, value, value_label) in bars {
+        parent
+            .spawn(NodeBundle {
+                style: Style {
+                    flex_direction: FlexDirection::Row,
+                    align_items: AlignItems::Center,
+                    column_gap: Val::Px(8.0),
+                    ..default()
+                },
+                ..default()
+            })
+            .with_children(|row| {
+                let (themed, bundle) = text(label, TextRole::Small);
+                row.spawn((
+                    themed,
+                    bundle.with_style(Style {
+                        width: Val::Px(80.0),
+                        ..default()
+                    }),
+                ));
+                row.spawn(NodeBundle {
+                    style: Style {
+                        width: Val::Px(BAR_WIDTH * *value as f32 / max as f32),
+                        height: Val::Px(14.0),
+                        ..default()
+                    },
+                    background_color: Color::from(theme.palette.focus).into(),
+                    ..default()
+                });
+                row.spawn(text(value_label, TextRole::Small));
+            });
+    }
+}
+
+fn stats_screen(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    theme: Res<Theme>,
+    lifetime_stats: Res<LifetimeStats>,
+) {
+    let text = |value: String, role| {
+        (
+            ThemedText(vec![role]),
+            TextBundle::from_section(value, theme.text_style(role, &asset_server)),
+        )
+    };
+    let summary = format!(
+        "Games: {}\nPieces: {}\nLines: {}\nTetris rate: {:.0}%\nPPS: {:.2}\nAPM: {:.1}\nTime played: {}",
+        lifetime_stats.games,
+        lifetime_stats.pieces,
+        lifetime_stats.lines(),
+        lifetime_stats.tetris_rate() * 100.0,
+        lifetime_stats.pieces_per_second(),
+        lifetime_stats.actions_per_minute(),
+        format_duration(lifetime_stats.time_played),
+    );
+    let clears: Vec<_> = CLEAR_NAMES
+        .iter()
+        .zip(lifetime_stats.clears)
+        .map(|(name, count)| ((*name).to_string(), count, count.to_string()))
+        .collect();
+    let pieces: Vec<_> = lifetime_stats
+        .piece_counts
+        .iter()
+        .map(|(letter, count)| (letter.to_string(), *count, count.to_string()))
+        .collect();
+    let grid_time: Vec<_> = lifetime_stats
+        .grid_time
+        .iter()
+        .enumerate()
+        .map(|(i, time)| {
+            let millis = u64::try_from(time.as_millis()).unwrap_or(u64::MAX);
+            (format!("Grid {}", i + 1), millis, format_duration(*time))
+        })
+        .collect();
+
+    commands
+        .spawn((
+            StatsScreen,
+            LayoutAnchor::Panel,
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    padding: UiRect::all(Val::Px(20.0)),
+                    column_gap: Val::Px(60.0),
+                    ..default()
+                },
+                background_color: Color::from(theme.palette.panel).into(),
+                ..default()
+            },
+        ))
+        .with_children(|root| {
+            let column = NodeBundle {
+                style: Style {
+                    flex_direction: FlexDirection::Column,
+                    row_gap: Val::Px(6.0),
+                    ..default()
+                },
+                ..default()
+            };
+            root.spawn(column.clone()).with_children(|column| {
+                column.spawn(text("Lifetime Stats".to_string(), TextRole::Header));
+                column.spawn(text(summary, TextRole::Body));
+                column.spawn(text("Esc: Menu".to_string(), TextRole::Body));
+            });
+            root.spawn(column).with_children(|column| {
+                spawn_bar_chart(column, &asset_server, &theme, "Line clears", &clears);
+                spawn_bar_chart(column, &asset_server, &theme, "Pieces", &pieces);
+                spawn_bar_chart(column, &asset_server, &theme, "Time focused", &grid_time);
+            });
+        });
+}
+
+fn close_stats(
+    mut next_state: ResMut<NextState<TetrisState>>,
+    mut commands: Commands,
+    input: Res<ButtonInput<KeyCode>>,
+    screen: Query<Entity, With<StatsScreen>>,
+) {
+    if input.just_pressed(KeyCode::Escape) {
+        next_state.set(TetrisState::Menu);
+        for entity_id in &screen {
+            commands.entity(entity_id).despawn_recursive();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_lifetime_stats() {
+        let mut stats = LifetimeStats::default();
+        stats.record_clear(0);
+        stats.record_clear(1);
+        stats.record_clear(4);
+        stats.record_clear(4);
+        assert_eq!(stats.clears, [1, 0, 0, 2]);
+        assert_eq!(stats.lines(), 9);
+        assert!((stats.tetris_rate() - 8.0 / 9.0).abs() < f32::EPSILON);
+
+        stats.record_piece(&TetrominoType::T.piece());
+        stats.record_piece(&TetrominoType::T.piece());
+        stats.record_piece(&TetrominoType::I.piece());
+        assert_eq!(stats.pieces, 3);
+        assert_eq!(stats.piece_counts, BTreeMap::from([('I', 1), ('T', 2)]));
+
+        stats.record_time(Duration::from_secs(1), Some(1));
+        stats.record_time(Duration::from_secs(2), None);
+        assert_eq!(stats.grid_time, [Duration::ZERO, Duration::from_secs(1)]);
+        assert!((stats.pieces_per_second() - 1.0).abs() < f32::EPSILON);
+
+        let contents = ron::to_string(&stats).unwrap();
+        assert_eq!(ron::from_str::<LifetimeStats>(&contents).unwrap(), stats);
+
+        // Stats saved when only classic pieces were counted still load.
+        let old: LifetimeStats =
+            ron::from_str("(pieces: 3, piece_counts: (1, 0, 2, 0, 0, 0, 0))").unwrap();
+        assert_eq!(old.piece_counts, stats.piece_counts);
+    }
+}
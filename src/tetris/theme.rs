@@ -0,0 +1,365 @@
+use crate::tetris::components::{Coordinate, DrawGrid};
+use crate::tetris::pieces::Piece;
+use crate::tetris::plugin::CELL_SIZE;
+use crate::tetris::render::GridSprites;
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, AsyncReadExt, LoadContext};
+use bevy::prelude::*;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as fmtResult};
+use std::io;
+
+/// The themes shipped in `assets/themes`, in the order T cycles through them.
+pub const THEMES: [&str; 3] = [
+    "themes/classic.theme.ron",
+    "themes/dark.theme.ron",
+    "themes/high-contrast.theme.ron",
+];
+
+/// An sRGB color with alpha, written `(r, g, b, a)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(transparent)]
+pub struct ThemeColor(pub [u8; 4]);
+
+impl From<ThemeColor> for Color {
+    fn from(ThemeColor([r, g, b, a]): ThemeColor) -> Self {
+        Color::srgba_u8(r, g, b, a)
+    }
+}
+
+/// Every color the game draws with other than the pieces'.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Palette {
+    /// Behind everything.
+    pub background: ThemeColor,
+    /// Behind each cell of a grid, showing around cells with a border.
+    pub grid: ThemeColor,
+    /// Locked cells of the focused grid.
+    pub focus: ThemeColor,
+    /// Locked cells of the other grids.
+    pub non_focus: ThemeColor,
+    pub shadow: ThemeColor,
+    /// Rows lit up while they're being cleared.
+    pub flash: ThemeColor,
+    pub particle: ThemeColor,
+    /// Hard drop trails, as they start before fading out.
+    pub trail: ThemeColor,
+    pub text: ThemeColor,
+    /// Text announcing something good, like a new high score.
+    pub highlight: ThemeColor,
+    /// Text announcing a mistake, like a finesse fault.
+    pub warning: ThemeColor,
+    /// Behind full screen panels such as the stats screen.
+    pub panel: ThemeColor,
+}
+
+/// How cells are set apart from their neighbours.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub enum CellBorder {
+    /// Cells fill their whole square.
+    None,
+    /// Cells are shrunk by this many pixels on each side, showing the grid color around them.
+    Inset(f32),
+}
+
+/// Font sizes of each kind of text.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct TextSizes {
+    /// Menu and end screen titles.
+    pub title: f32,
+    /// High score and stats screen titles.
+    pub header: f32,
+    /// The score and announcements.
+    pub heading: f32,
+    pub body: f32,
+    /// Table rows and chart labels.
+    pub small: f32,
+}
+
+/// What a section of text is for, which picks its size and color from the theme.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextRole {
+    Title,
+    Header,
+    Heading,
+    Body,
+    Small,
+    Highlight,
+    Warning,
+}
+
+/// How the game looks, loaded from a `.theme.ron` asset.
+#[derive(Asset, Resource, TypePath, Debug, Clone, PartialEq, Deserialize)]
+pub struct Theme {
+    pub name: String,
+    pub palette: Palette,
+    /// Colors for pieces by letter in place of their piece set's, for every set with that letter.
+    #[serde(default)]
+    pub pieces: BTreeMap<char, [u8; 3]>,
+    pub border: CellBorder,
+    /// Asset path of the font for all text.
+    pub font: String,
+    pub text_sizes: TextSizes,
+}
+
+impl Default for Theme {
+    /// The classic theme, used until the theme assets have loaded.
+    fn default() -> Self {
+        Theme {
+            name: "Classic".to_string(),
+            palette: Palette {
+                background: ThemeColor([43, 44, 47, 255]),
+                grid: ThemeColor([0, 0, 0, 255]),
+                focus: ThemeColor([255, 255, 255, 255]),
+                non_focus: ThemeColor([188, 188, 188, 255]),
+                shadow: ThemeColor([0, 0, 0, 26]),
+                flash: ThemeColor([255, 255, 203, 255]),
+                particle: ThemeColor([255, 255, 203, 255]),
+                trail: ThemeColor([255, 255, 255, 77]),
+                text: ThemeColor([255, 255, 255, 255]),
+                highlight: ThemeColor([255, 217, 77, 255]),
+                warning: ThemeColor([255, 77, 77, 255]),
+                panel: ThemeColor([0, 0, 0, 230]),
+            },
+            pieces: BTreeMap::new(),
+            border: CellBorder::Inset(1.0),
+            font: "fonts/JetBrainsMono-Bold.ttf".to_string(),
+            text_sizes: TextSizes {
+                title: 72.0,
+                header: 48.0,
+                heading: 36.0,
+                body: 24.0,
+                small: 18.0,
+            },
+        }
+    }
+}
+
+impl Theme {
+    /// The color `piece` is drawn in.
+    #[must_use]
+    pub fn piece_color(&self, piece: &Piece) -> [u8; 3] {
+        self.pieces
+            .get(&piece.letter())
+            .copied()
+            .unwrap_or_else(|| piece.color())
+    }
+
+    /// How wide a cell's sprite is in a square `cell_size` pixels wide.
+    #[must_use]
+    pub fn cell_sprite_size(&self, cell_size: f32) -> f32 {
+        match self.border {
+            CellBorder::None => cell_size,
+            CellBorder::Inset(inset) => (cell_size - 2.0 * inset).max(0.0),
+        }
+    }
+
+    #[must_use]
+    pub fn text_style(&self, role: TextRole, asset_server: &AssetServer) -> TextStyle {
+        let sizes = &self.text_sizes;
+        let (font_size, color) = match role {
+            TextRole::Title => (sizes.title, self.palette.text),
+            TextRole::Header => (sizes.header, self.palette.text),
+            TextRole::Heading => (sizes.heading, self.palette.text),
+            TextRole::Body => (sizes.body, self.palette.text),
+            TextRole::Small => (sizes.small, self.palette.text),
+            TextRole::Highlight => (sizes.heading, self.palette.highlight),
+            TextRole::Warning => (sizes.heading, self.palette.warning),
+        };
+        TextStyle {
+            font: asset_server.load(&self.font),
+            font_size,
+            color: color.into(),
+        }
+    }
+}
+
+/// Text restyled whenever the theme changes, with the role of each of its sections.
+#[derive(Component, Debug, Clone)]
+pub struct ThemedText(pub Vec<TextRole>);
+
+/// The square behind a grid cell, drawn in the theme's grid color.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct CellBackground;
+
+/// The loaded themes and which one is in use. The theme in use is copied into the `Theme`
+/// resource once it has loaded, and again whenever its file changes.
+#[derive(Resource, Debug, Default)]
+pub struct Themes {
+    pub handles: Vec<Handle<Theme>>,
+    pub active: usize,
+}
+
+impl Themes {
+    #[must_use]
+    pub fn active(&self) -> Option<&Handle<Theme>> {
+        self.handles.get(self.active)
+    }
+
+    pub fn cycle(&mut self) {
+        if !self.handles.is_empty() {
+            self.active = (self.active + 1) % self.handles.len();
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ThemeLoaderError {
+    Io(io::Error),
+    Ron(ron::error::SpannedError),
+}
+
+impl Display for ThemeLoaderError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmtResult {
+        match self {
+            ThemeLoaderError::Io(e) => write!(f, "failed to read theme: {e}"),
+            ThemeLoaderError::Ron(e) => write!(f, "invalid theme: {e}"),
+        }
+    }
+}
+
+impl Error for ThemeLoaderError {}
+
+impl From<io::Error> for ThemeLoaderError {
+    fn from(e: io::Error) -> Self {
+        ThemeLoaderError::Io(e)
+    }
+}
+
+impl From<ron::error::SpannedError> for ThemeLoaderError {
+    fn from(e: ron::error::SpannedError) -> Self {
+        ThemeLoaderError::Ron(e)
+    }
+}
+
+#[derive(Default)]
+pub struct ThemeLoader;
+
+impl AssetLoader for ThemeLoader {
+    type Asset = Theme;
+    type Settings = ();
+    type Error = ThemeLoaderError;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext<'_>,
+    ) -> Result<Theme, ThemeLoaderError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        Ok(ron::de::from_bytes(&bytes)?)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["theme.ron"]
+    }
+}
+
+/// Loads the shipped themes, switches between them with T and restyles the screen to match.
+pub struct ThemePlugin;
+
+impl Plugin for ThemePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Theme>()
+            .init_resource::<Themes>()
+            .init_asset::<Theme>()
+            .init_asset_loader::<ThemeLoader>()
+            .add_systems(Startup, load_themes)
+            .add_systems(Update, (cycle_theme, apply_theme, restyle).chain());
+    }
+}
+
+fn load_themes(asset_server: Res<AssetServer>, mut themes: ResMut<Themes>) {
+    themes.handles = THEMES.iter().map(|path| asset_server.load(*path)).collect();
+}
+
+fn cycle_theme(input: Res<ButtonInput<KeyCode>>, mut themes: ResMut<Themes>) {
+    if input.just_pressed(KeyCode::KeyT) {
+        themes.cycle();
+    }
+}
+
+/// Copies the active theme into the `Theme` resource when it's picked, once it has loaded, and
+/// whenever its file changes.
+fn apply_theme(
+    themes: Res<Themes>,
+    assets: Res<Assets<Theme>>,
+    mut events: EventReader<AssetEvent<Theme>>,
+    mut theme: ResMut<Theme>,
+) {
+    let Some(active) = themes.active() else {
+        return;
+    };
+    let reloaded = events
+        .read()
+        .any(|event| event.is_loaded_with_dependencies(active) || event.is_modified(active));
+    if !reloaded && !themes.is_changed() {
+        return;
+    }
+    if let Some(loaded) = assets.get(active).filter(|loaded| **loaded != *theme) {
+        debug!("Switching to the {} theme", loaded.name);
+        *theme = loaded.clone();
+    }
+}
+
+/// Restyles everything already on screen to match a new theme.
+fn restyle(
+    theme: Res<Theme>,
+    asset_server: Res<AssetServer>,
+    mut clear_color: ResMut<ClearColor>,
+    mut texts: Query<(&ThemedText, &mut Text)>,
+    mut squares: Query<&mut Sprite, (With<Coordinate>, Without<CellBackground>)>,
+    mut backgrounds: Query<&mut Sprite, With<CellBackground>>,
+    mut grids: Query<(Entity, &mut GridSprites)>,
+    mut draw_grid: EventWriter<DrawGrid>,
+) {
+    if !theme.is_changed() {
+        return;
+    }
+    clear_color.0 = theme.palette.background.into();
+    for (themed, mut text) in &mut texts {
+        for (section, role) in text.sections.iter_mut().zip(&themed.0) {
+            section.style = theme.text_style(*role, &asset_server);
+        }
+    }
+    for mut sprite in &mut squares {
+        sprite.custom_size = Some(Vec2::splat(theme.cell_sprite_size(CELL_SIZE)));
+    }
+    for mut sprite in &mut backgrounds {
+        sprite.color = theme.palette.grid.into();
+    }
+    // Forgetting how cells were drawn makes the next draw recolor every filled one.
+    for (entity, mut grid_sprites) in &mut grids {
+        grid_sprites.clear();
+        draw_grid.send(DrawGrid(entity));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tetris::components::TetrominoType;
+
+    #[test]
+    fn test_builtin_themes() {
+        let themes: Vec<Theme> = [
+            include_str!("../../assets/themes/classic.theme.ron"),
+            include_str!("../../assets/themes/dark.theme.ron"),
+            include_str!("../../assets/themes/high-contrast.theme.ron"),
+        ]
+        .into_iter()
+        .map(|source| ron::from_str(source).unwrap())
+        .collect();
+        assert_eq!(themes[0], Theme::default());
+        for theme in &themes {
+            assert!(theme.cell_sprite_size(20.0) > 0.0, "{}", theme.name);
+        }
+
+        let t = TetrominoType::T.piece();
+        assert_eq!(themes[0].piece_color(&t), t.color());
+        assert_ne!(themes[2].piece_color(&t), t.color());
+    }
+}